@@ -28,7 +28,7 @@ pub fn directory_sparse(c: &mut Criterion) {
         b.iter(|| {
             let mut cmd = std::process::Command::new("grep");
             cmd.current_dir(d.path())
-                .args(&["-rnP", ON_CHANGE_PAT_STR, "."])
+                .args(["-rnP", ON_CHANGE_PAT_STR, "."])
                 .stdout(std::process::Stdio::null());
             assert!(cmd.spawn().unwrap().wait().unwrap().success());
         });
@@ -37,7 +37,7 @@ pub fn directory_sparse(c: &mut Criterion) {
         b.iter(|| {
             let mut cmd = std::process::Command::new("rg");
             cmd.current_dir(d.path())
-                .args(&["-n", &ripgrep_on_change_pat, "."])
+                .args(["-n", &ripgrep_on_change_pat, "."])
                 .stdout(std::process::Stdio::null());
             assert!(cmd.spawn().unwrap().wait().unwrap().success());
         });
@@ -63,7 +63,7 @@ pub fn directory_sparse(c: &mut Criterion) {
         b.iter(|| {
             let mut cmd = std::process::Command::new("grep");
             cmd.current_dir(d.path())
-                .args(&["-rnP", ON_CHANGE_PAT_STR, "."])
+                .args(["-rnP", ON_CHANGE_PAT_STR, "."])
                 .stdout(std::process::Stdio::null());
             assert!(cmd.spawn().unwrap().wait().unwrap().success());
         });
@@ -72,7 +72,7 @@ pub fn directory_sparse(c: &mut Criterion) {
         b.iter(|| {
             let mut cmd = std::process::Command::new("rg");
             cmd.current_dir(d.path())
-                .args(&["-n", &ripgrep_on_change_pat, "."])
+                .args(["-n", &ripgrep_on_change_pat, "."])
                 .stdout(std::process::Stdio::null());
             assert!(cmd.spawn().unwrap().wait().unwrap().success());
         });
@@ -102,7 +102,7 @@ pub fn directory_dense(c: &mut Criterion) {
         b.iter(|| {
             let mut cmd = std::process::Command::new("grep");
             cmd.current_dir(d.path())
-                .args(&["-rnP", ON_CHANGE_PAT_STR, "."])
+                .args(["-rnP", ON_CHANGE_PAT_STR, "."])
                 .stdout(std::process::Stdio::null());
             assert!(cmd.spawn().unwrap().wait().unwrap().success());
         });
@@ -111,7 +111,7 @@ pub fn directory_dense(c: &mut Criterion) {
         b.iter(|| {
             let mut cmd = std::process::Command::new("rg");
             cmd.current_dir(d.path())
-                .args(&["-n", &ripgrep_on_change_pat, "."])
+                .args(["-n", &ripgrep_on_change_pat, "."])
                 .stdout(std::process::Stdio::null());
             assert!(cmd.spawn().unwrap().wait().unwrap().success());
         });
@@ -137,7 +137,7 @@ pub fn directory_dense(c: &mut Criterion) {
         b.iter(|| {
             let mut cmd = std::process::Command::new("grep");
             cmd.current_dir(d.path())
-                .args(&["-rnP", ON_CHANGE_PAT_STR, "."])
+                .args(["-rnP", ON_CHANGE_PAT_STR, "."])
                 .stdout(std::process::Stdio::null());
             assert!(cmd.spawn().unwrap().wait().unwrap().success());
         });
@@ -146,7 +146,7 @@ pub fn directory_dense(c: &mut Criterion) {
         b.iter(|| {
             let mut cmd = std::process::Command::new("rg");
             cmd.current_dir(d.path())
-                .args(&["-n", &ripgrep_on_change_pat, "."])
+                .args(["-n", &ripgrep_on_change_pat, "."])
                 .stdout(std::process::Stdio::null());
             assert!(cmd.spawn().unwrap().wait().unwrap().success());
         });
@@ -0,0 +1,220 @@
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::{ErrorEntry, Report, Severity, ViolationEntry};
+
+/// Escapes the message of a GitHub workflow command.
+fn escape_github_data(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes a property (e.g., file) of a GitHub workflow command.
+fn escape_github_property(s: &str) -> String {
+    escape_github_data(s)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+fn github_annotation(
+    command: &str,
+    path: Option<&Path>,
+    lines: Option<(u32, u32)>,
+    column: Option<u32>,
+    title: &str,
+    message: &str,
+) -> String {
+    let mut properties = Vec::new();
+    if let Some(path) = path {
+        properties.push(format!(
+            "file={}",
+            escape_github_property(&path.to_string_lossy())
+        ));
+        if let Some((start_line, end_line)) = lines {
+            properties.push(format!("line={}", start_line));
+            properties.push(format!("endLine={}", end_line));
+            if let Some(column) = column {
+                properties.push(format!("col={}", column));
+            }
+        }
+    }
+    properties.push(format!("title={}", escape_github_property(title)));
+    format!(
+        "::{} {}::{}",
+        command,
+        properties.join(","),
+        escape_github_data(message)
+    )
+}
+
+/// Returns one GitHub Actions `::error` workflow command per violation and error in the
+/// given reports, or `::warning` for violations with a "warn" severity and `::notice` for
+/// waived violations. Violations are annotated on the lines of the source block.
+pub fn github_annotations(reports: &[Report]) -> Vec<String> {
+    let mut annotations = Vec::new();
+    for report in reports {
+        for v in &report.violations {
+            let (command, message) = match (&v.waived, v.severity) {
+                (Some(reason), _) => ("notice", format!("{} (waived: {})", v.message, reason)),
+                (None, Severity::Error) => ("error", v.message.clone()),
+                (None, Severity::Warn) => ("warning", v.message.clone()),
+            };
+            annotations.push(github_annotation(
+                command,
+                Some(&v.block.file),
+                Some((v.block.start_line, v.block.end_line)),
+                None,
+                &format!("onchg: {}", v.kind),
+                &message,
+            ));
+        }
+        for e in &report.errors {
+            annotations.push(github_annotation(
+                "error",
+                e.path.as_deref(),
+                e.line.map(|l| (l, l)),
+                e.column,
+                &format!("onchg: {}", e.kind),
+                &e.message,
+            ));
+        }
+    }
+    annotations
+}
+
+/// Returns a stable fingerprint for a Code Quality issue. Line numbers are not included so
+/// that the same issue is tracked across changes that move it.
+fn fingerprint(parts: &[&str]) -> String {
+    // 64-bit FNV-1a.
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for part in parts {
+        for b in part.bytes().chain(std::iter::once(0)) {
+            hash ^= b as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+fn gitlab_violation(v: &ViolationEntry) -> Value {
+    let target_file = v.target.file.to_string_lossy();
+    json!({
+        "description": v.message,
+        "check_name": v.kind,
+        "fingerprint": fingerprint(&[
+            v.kind,
+            &v.block.file.to_string_lossy(),
+            v.block.name.as_deref().unwrap_or_default(),
+            &target_file,
+            v.target.block.as_deref().unwrap_or_default(),
+        ]),
+        "severity": match (&v.waived, v.severity) {
+            (Some(_), _) => "info",
+            (None, Severity::Error) => "major",
+            (None, Severity::Warn) => "minor",
+        },
+        "location": {
+            "path": v.block.file,
+            "lines": { "begin": v.block.start_line, "end": v.block.end_line },
+        },
+    })
+}
+
+fn gitlab_error(e: &ErrorEntry) -> Value {
+    let path = e.path.as_deref().map(|p| p.to_string_lossy());
+    json!({
+        "description": e.message,
+        "check_name": e.kind,
+        "fingerprint": fingerprint(&[
+            e.kind,
+            path.as_deref().unwrap_or_default(),
+            e.block.as_deref().unwrap_or_default(),
+            &e.message,
+        ]),
+        "severity": "major",
+        "location": {
+            // GitLab requires a path, so errors without one are reported on the root.
+            "path": path.as_deref().unwrap_or("."),
+            "lines": { "begin": e.line.unwrap_or(1) },
+        },
+    })
+}
+
+/// Builds a GitLab Code Quality report containing the violations and errors of all of the
+/// given reports.
+pub fn gitlab_code_quality(reports: &[Report]) -> Value {
+    let mut issues = Vec::new();
+    for report in reports {
+        issues.extend(report.violations.iter().map(gitlab_violation));
+        issues.extend(report.errors.iter().map(gitlab_error));
+    }
+    Value::Array(issues)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_helpers::*;
+    use crate::Parser;
+
+    #[test]
+    fn test_annotations() {
+        let d = GitRepo::from_files(&[
+            (
+                "f1.txt",
+                "LINT.OnChange(a)\nabc\nLINT.ThenChange(f2.txt:b)\n",
+            ),
+            ("f2.txt", "LINT.OnChange(b)\nabc\nLINT.ThenChange()\n"),
+        ]);
+        d.write_and_add_files(&[(
+            "f1.txt",
+            "LINT.OnChange(a)\nxyz\nLINT.ThenChange(f2.txt:b)\n",
+        )]);
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        let violations = p.validate_git_repo().unwrap();
+        let reports = [Report::new(&p, &violations)];
+
+        let annotations = github_annotations(&reports);
+        assert_eq!(annotations.len(), 1);
+        assert!(annotations[0].starts_with(
+            "::error file=f1.txt,line=1,endLine=3,title=onchg%3A target_not_changed::"
+        ));
+
+        let issues = gitlab_code_quality(&reports);
+        let issue = &issues[0];
+        assert_eq!(issue["check_name"], "target_not_changed");
+        assert_eq!(
+            issue["location"],
+            json!({ "path": "f1.txt", "lines": { "begin": 1, "end": 3 } })
+        );
+        assert_eq!(issue["fingerprint"], "e326fc5ad38ffccb");
+
+        // Shifting the block must not change the fingerprint.
+        d.write_and_add_files(&[(
+            "f1.txt",
+            "\n\nLINT.OnChange(a)\nxyz\nLINT.ThenChange(f2.txt:b)\n",
+        )]);
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        let violations = p.validate_git_repo().unwrap();
+        let issues = gitlab_code_quality(&[Report::new(&p, &violations)]);
+        assert_eq!(issues[0]["location"]["lines"]["begin"], 3);
+        assert_eq!(issues[0]["fingerprint"], issue["fingerprint"]);
+    }
+
+    #[test]
+    fn test_escape_github() {
+        assert_eq!(
+            github_annotation(
+                "error",
+                Some(Path::new("a,b:c.txt")),
+                None,
+                None,
+                "t",
+                "100%\nfailed"
+            ),
+            "::error file=a%2Cb%3Ac.txt,title=t::100%25%0Afailed"
+        );
+    }
+}
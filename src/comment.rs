@@ -0,0 +1,308 @@
+use std::path::Path;
+
+/// Comment and string literal syntax of a language, used to only recognize markers inside
+/// comments.
+#[derive(Debug)]
+pub(crate) struct CommentSyntax {
+    /// Line comment prefixes, e.g., `//`.
+    line: &'static [&'static str],
+    /// Block comment delimiters, e.g., (`/*`, `*/`).
+    block: &'static [(&'static str, &'static str)],
+    /// String literal delimiters. A string ends at the end of the line if it is not closed.
+    strings: &'static [&'static str],
+    /// String literal delimiters that can span multiple lines, e.g., `"""`.
+    multiline_strings: &'static [&'static str],
+    /// Raw string delimiters, e.g., (`r#"`, `"#`). Raw strings can span multiple lines and
+    /// have no escapes.
+    raw_strings: &'static [(&'static str, &'static str)],
+    /// Literals that are skipped as a whole, e.g., the `'"'` character in Rust, so that
+    /// they are not mistaken for the start of a string.
+    literals: &'static [&'static str],
+}
+
+const C_LIKE: CommentSyntax = CommentSyntax {
+    line: &["//"],
+    block: &[("/*", "*/")],
+    strings: &["\"", "'"],
+    multiline_strings: &[],
+    raw_strings: &[],
+    literals: &[],
+};
+
+const JS_LIKE: CommentSyntax = CommentSyntax {
+    line: &["//"],
+    block: &[("/*", "*/")],
+    strings: &["\"", "'"],
+    multiline_strings: &["`"],
+    raw_strings: &[],
+    literals: &[],
+};
+
+const RUST: CommentSyntax = CommentSyntax {
+    // Doc comment prefixes come first, so that they are not left in the comment.
+    line: &["///", "//!", "//"],
+    block: &[("/*", "*/")],
+    strings: &[],
+    multiline_strings: &["\""],
+    raw_strings: &[
+        ("r###\"", "\"###"),
+        ("r##\"", "\"##"),
+        ("r#\"", "\"#"),
+        ("r\"", "\""),
+    ],
+    // Single quotes are not treated as strings since they are also used for lifetimes.
+    literals: &["'\"'", "'\\\"'"],
+};
+
+const CSS: CommentSyntax = CommentSyntax {
+    line: &[],
+    block: &[("/*", "*/")],
+    strings: &["\"", "'"],
+    multiline_strings: &[],
+    raw_strings: &[],
+    literals: &[],
+};
+
+const HASH: CommentSyntax = CommentSyntax {
+    line: &["#"],
+    block: &[],
+    strings: &["\"", "'"],
+    multiline_strings: &[],
+    raw_strings: &[],
+    literals: &[],
+};
+
+const PYTHON: CommentSyntax = CommentSyntax {
+    line: &["#"],
+    block: &[],
+    strings: &["\"", "'"],
+    multiline_strings: &["\"\"\"", "'''"],
+    raw_strings: &[],
+    literals: &[],
+};
+
+const SQL: CommentSyntax = CommentSyntax {
+    line: &["--"],
+    block: &[("/*", "*/")],
+    strings: &["\"", "'"],
+    multiline_strings: &[],
+    raw_strings: &[],
+    literals: &[],
+};
+
+const LUA: CommentSyntax = CommentSyntax {
+    line: &["--"],
+    block: &[("--[[", "]]")],
+    strings: &["\"", "'"],
+    multiline_strings: &[],
+    raw_strings: &[],
+    literals: &[],
+};
+
+const HASKELL: CommentSyntax = CommentSyntax {
+    line: &["--"],
+    block: &[("{-", "-}")],
+    strings: &["\""],
+    multiline_strings: &[],
+    raw_strings: &[],
+    literals: &[],
+};
+
+const MARKUP: CommentSyntax = CommentSyntax {
+    line: &[],
+    block: &[("<!--", "-->")],
+    strings: &[],
+    multiline_strings: &[],
+    raw_strings: &[],
+    literals: &[],
+};
+
+const LISP: CommentSyntax = CommentSyntax {
+    line: &[";"],
+    block: &[],
+    strings: &["\""],
+    multiline_strings: &[],
+    raw_strings: &[],
+    literals: &[],
+};
+
+const INI: CommentSyntax = CommentSyntax {
+    line: &[";", "#"],
+    block: &[],
+    strings: &[],
+    multiline_strings: &[],
+    raw_strings: &[],
+    literals: &[],
+};
+
+const PERCENT: CommentSyntax = CommentSyntax {
+    line: &["%"],
+    block: &[],
+    strings: &[],
+    multiline_strings: &[],
+    raw_strings: &[],
+    literals: &[],
+};
+
+const HCL: CommentSyntax = CommentSyntax {
+    line: &["#", "//"],
+    block: &[("/*", "*/")],
+    strings: &["\""],
+    multiline_strings: &[],
+    raw_strings: &[],
+    literals: &[],
+};
+
+/// Returns the comment syntax for the given file based on its name or extension, or None if
+/// the language is unknown.
+pub(crate) fn comment_syntax(path: &Path) -> Option<&'static CommentSyntax> {
+    let name = path.file_name()?.to_str()?;
+    match name {
+        "Makefile" | "Dockerfile" | "BUILD" | "WORKSPACE" | "CMakeLists.txt" | "Gemfile"
+        | "Rakefile" => return Some(&HASH),
+        _ => (),
+    }
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    let syntax = match ext.as_str() {
+        "c" | "h" | "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" | "m" | "mm" | "java" | "kt"
+        | "kts" | "scala" | "swift" | "cs" | "dart" | "proto" | "groovy" | "gradle" | "php"
+        | "scss" | "less" | "zig" => &C_LIKE,
+        "js" | "jsx" | "mjs" | "cjs" | "ts" | "tsx" | "go" => &JS_LIKE,
+        "rs" => &RUST,
+        "css" => &CSS,
+        "sh" | "bash" | "zsh" | "fish" | "rb" | "pl" | "pm" | "r" | "yaml" | "yml" | "toml"
+        | "bzl" | "bazel" | "star" | "cmake" | "mk" | "nix" | "ps1" | "dockerfile" => &HASH,
+        "py" | "pyi" => &PYTHON,
+        "sql" => &SQL,
+        "lua" => &LUA,
+        "hs" | "elm" => &HASKELL,
+        "html" | "htm" | "xml" | "svg" | "md" | "markdown" | "vue" => &MARKUP,
+        "clj" | "cljs" | "el" | "lisp" | "scm" => &LISP,
+        "ini" | "cfg" | "conf" => &INI,
+        "erl" | "hrl" | "tex" => &PERCENT,
+        "tf" | "hcl" => &HCL,
+        _ => return None,
+    };
+    Some(syntax)
+}
+
+impl CommentSyntax {
+    /// Returns a copy of the given content where everything outside of comments is replaced
+    /// by spaces. Newlines are kept so that byte positions and line numbers do not change.
+    pub(crate) fn mask(&self, buf: &[u8]) -> Vec<u8> {
+        let mut masked: Vec<u8> = buf
+            .iter()
+            .map(|&b| if b == b'\n' { b'\n' } else { b' ' })
+            .collect();
+        let mut i = 0;
+        'outer: while i < buf.len() {
+            let rest = &buf[i..];
+            for (open, close) in self.block {
+                if rest.starts_with(open.as_bytes()) {
+                    let start = i + open.len();
+                    let end = find(buf, start, close.as_bytes()).unwrap_or(buf.len());
+                    masked[start..end].copy_from_slice(&buf[start..end]);
+                    i = (end + close.len()).min(buf.len());
+                    continue 'outer;
+                }
+            }
+            for prefix in self.line {
+                if rest.starts_with(prefix.as_bytes()) {
+                    let start = i + prefix.len();
+                    let end = find(buf, start, b"\n").unwrap_or(buf.len());
+                    masked[start..end].copy_from_slice(&buf[start..end]);
+                    i = end;
+                    continue 'outer;
+                }
+            }
+            for (open, close) in self.raw_strings {
+                if rest.starts_with(open.as_bytes()) {
+                    let end = find(buf, i + open.len(), close.as_bytes()).unwrap_or(buf.len());
+                    i = (end + close.len()).min(buf.len());
+                    continue 'outer;
+                }
+            }
+            for literal in self.literals {
+                if rest.starts_with(literal.as_bytes()) {
+                    i += literal.len();
+                    continue 'outer;
+                }
+            }
+            for delim in self.multiline_strings {
+                if rest.starts_with(delim.as_bytes()) {
+                    i = skip_string(buf, i + delim.len(), delim.as_bytes(), true);
+                    continue 'outer;
+                }
+            }
+            for delim in self.strings {
+                if rest.starts_with(delim.as_bytes()) {
+                    i = skip_string(buf, i + delim.len(), delim.as_bytes(), false);
+                    continue 'outer;
+                }
+            }
+            i += 1;
+        }
+        masked
+    }
+}
+
+/// Returns the position of the first occurrence of needle in buf, starting at the given
+/// position.
+fn find(buf: &[u8], start: usize, needle: &[u8]) -> Option<usize> {
+    buf[start..]
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|p| start + p)
+}
+
+/// Returns the position right after the end of the string starting at the given position.
+/// Backslash escapes are skipped.
+fn skip_string(buf: &[u8], mut i: usize, delim: &[u8], multiline: bool) -> usize {
+    while i < buf.len() {
+        if buf[i] == b'\\' {
+            i += 2;
+        } else if buf[i..].starts_with(delim) {
+            return i + delim.len();
+        } else if buf[i] == b'\n' && !multiline {
+            return i;
+        } else {
+            i += 1;
+        }
+    }
+    buf.len()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bstr::ByteSlice;
+
+    #[test]
+    fn test_mask() {
+        let syntax = comment_syntax(Path::new("a/b.rs")).unwrap();
+        let buf = b"let a = \"// x\"; // y\n/* z\nw */ v\n";
+        let masked = syntax.mask(buf);
+        assert_eq!(masked.len(), buf.len());
+        assert_eq!(masked.as_bstr(), "                   y\n   z\nw     \n");
+
+        let syntax = comment_syntax(Path::new("a.py")).unwrap();
+        assert_eq!(
+            syntax.mask(b"\"\"\"\n# a\n\"\"\" # b 'c'\n").as_bstr(),
+            "   \n   \n      b 'c'\n"
+        );
+
+        // Rust strings span lines, and raw strings and char literals contain quotes.
+        let syntax = comment_syntax(Path::new("a.rs")).unwrap();
+        assert_eq!(
+            syntax
+                .mask(b"\"a\n// b\\\"\"; r#\"\"\n// c\"#; '\"'; // d\n")
+                .as_bstr(),
+            "  \n             \n                d\n"
+        );
+        // Doc comment prefixes are stripped.
+        assert_eq!(syntax.mask(b"/// a\n//! b\n").as_bstr(), "    a\n    b\n");
+
+        assert!(comment_syntax(Path::new("a.unknown")).is_none());
+        assert!(comment_syntax(Path::new("Makefile")).is_some());
+    }
+}
@@ -0,0 +1,487 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use regex::bytes::Regex;
+use serde::Deserialize;
+
+use crate::comment::comment_syntax;
+use crate::file::{ON_CHANGE_GROUP, ON_CHANGE_PAT, THEN_CHANGE_GROUP};
+use crate::git::{Changes, Repo};
+use crate::marker::{build_pattern, Marker, DEFAULT_PRESET, PRESETS};
+use crate::{Error, MarkerSyntax, Result};
+
+/// Name of the config file. It is discovered at the root path, as well as in any subdirectory.
+pub const CONFIG_FILE_NAME: &str = ".onchg.toml";
+
+pub const DEFAULT_MAX_FILES_TO_DISPLAY: usize = 15;
+pub const DEFAULT_MAX_VIOLATIONS_TO_DISPLAY: usize = 10;
+
+/// Settings read from a config file, or passed on the command line.
+///
+/// Example:
+///
+/// ```toml
+/// # Globs of files to parse. Defaults to all files.
+/// include = ["src/**", "proto/**"]
+/// # Globs of files to skip.
+/// exclude = ["**/testdata/**"]
+/// # Built-in marker syntaxes to accept (see [MarkerSyntax::preset]). Defaults to ["onchg"].
+/// presets = ["onchg", "lint-ifchange"]
+/// # Regex used to find OnChange and ThenChange markers, instead of the presets and custom
+/// # markers. Must contain the named groups "on_change" and "then_change".
+/// marker = 'CHECK\.OnChange\((?<on_change>.*?)\)|CHECK\.ThenChange\((?<then_change>[^)]*)\)'
+/// # Whether to respect ignore files in directory mode.
+/// ignore = true
+/// # Only recognize markers inside comments, based on the file extension. Files in unknown
+/// # languages are matched as a whole.
+/// comments_only = false
+/// # Whether every file matching a glob or directory ThenChange target must change ("all"),
+/// # or at least one of them ("any").
+/// glob_targets = "all"
+/// # Whether every changed block requires all blocks reachable through its ThenChange targets
+/// # to change, not just its direct targets. Can also be set per block.
+/// transitive = false
+/// max_files_to_display = 15
+/// max_violations_to_display = 10
+///
+/// # Custom marker syntaxes to accept, in addition to the presets.
+/// [[markers]]
+/// on_change = "SYNC.Begin"
+/// then_change = "SYNC.End"
+/// ```
+///
+/// Globs are matched against paths relative to the directory containing the config file, and
+/// `*` does not match `/` (use `**` instead).
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub presets: Option<Vec<String>>,
+    pub markers: Vec<MarkerSyntax>,
+    pub marker: Option<String>,
+    pub ignore: Option<bool>,
+    pub comments_only: Option<bool>,
+    pub glob_targets: Option<GlobMode>,
+    pub transitive: Option<bool>,
+    pub max_files_to_display: Option<usize>,
+    pub max_violations_to_display: Option<usize>,
+}
+
+/// Semantics of glob and directory ThenChange targets in repo mode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GlobMode {
+    /// Every file matching the glob must change.
+    #[default]
+    All,
+    /// At least one file matching the glob must change.
+    Any,
+}
+
+impl Settings {
+    /// Reads the settings from the given config file.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read(path).map_err(|e| Error::io(path, e))?;
+        Self::from_bytes(path, &content)
+    }
+
+    /// Parses the settings in the content of the given config file.
+    fn from_bytes(path: &Path, content: &[u8]) -> Result<Self> {
+        let invalid = |message: String| Error::InvalidConfig {
+            path: path.to_owned(),
+            message,
+        };
+        let content = std::str::from_utf8(content).map_err(|e| invalid(e.to_string()))?;
+        toml::from_str(content).map_err(|e| invalid(e.message().to_string()))
+    }
+}
+
+/// Where config files are read from.
+#[derive(Debug)]
+enum Source {
+    /// The working tree.
+    Disk,
+    /// The content of every config file in a revision (or the index), keyed by path relative
+    /// to the root path.
+    Files(HashMap<PathBuf, Vec<u8>>),
+}
+
+impl Source {
+    /// Reads the settings in the config file at the given path (relative to the root path),
+    /// if it exists.
+    fn settings(&self, root_path: &Path, path: &Path) -> Result<Option<Settings>> {
+        match self {
+            Self::Disk => {
+                let path = root_path.join(path);
+                if path.is_file() {
+                    Settings::from_file(&path).map(Some)
+                } else {
+                    Ok(None)
+                }
+            }
+            Self::Files(files) => files
+                .get(path)
+                .map(|content| Settings::from_bytes(&root_path.join(path), content))
+                .transpose(),
+        }
+    }
+}
+
+/// Compiled settings of a single config file, which apply to its directory and all of its
+/// subdirectories.
+#[derive(Debug)]
+struct Scope {
+    /// Directory containing the config file, relative to the root path.
+    dir: PathBuf,
+    /// None if all files are included.
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    marker: Option<Regex>,
+}
+
+impl Scope {
+    fn new(config_path: &Path, dir: PathBuf, settings: &Settings) -> Result<Self> {
+        let invalid = |message: String| Error::InvalidConfig {
+            path: config_path.to_owned(),
+            message,
+        };
+        let build_globs = |globs: &[String]| -> Result<GlobSet> {
+            let mut builder = GlobSetBuilder::new();
+            for glob in globs {
+                let glob = GlobBuilder::new(glob)
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|e| invalid(e.to_string()))?;
+                builder.add(glob);
+            }
+            builder.build().map_err(|e| invalid(e.to_string()))
+        };
+        let marker = match &settings.marker {
+            Some(marker) => {
+                let pat = Regex::new(marker).map_err(|e| invalid(e.to_string()))?;
+                let names: Vec<&str> = pat.capture_names().flatten().collect();
+                if !names.contains(&ON_CHANGE_GROUP) || !names.contains(&THEN_CHANGE_GROUP) {
+                    return Err(invalid(format!(
+                        r#"marker must contain the named groups "{}" and "{}""#,
+                        ON_CHANGE_GROUP, THEN_CHANGE_GROUP,
+                    )));
+                }
+                Some(pat)
+            }
+            None if settings.presets.is_some() || !settings.markers.is_empty() => {
+                let default_presets = [DEFAULT_PRESET.to_string()];
+                let presets = settings.presets.as_deref().unwrap_or(&default_presets);
+                let mut syntaxes = Vec::new();
+                for name in presets {
+                    syntaxes.push(MarkerSyntax::preset(name).ok_or_else(|| {
+                        invalid(format!(
+                            r#"unknown preset "{}" (expected one of: {})"#,
+                            name,
+                            PRESETS.join(", "),
+                        ))
+                    })?);
+                }
+                syntaxes.extend(settings.markers.iter().cloned());
+                if syntaxes.is_empty() {
+                    return Err(invalid("no markers configured".to_string()));
+                }
+                Some(build_pattern(&syntaxes).map_err(|e| invalid(e.to_string()))?)
+            }
+            None => None,
+        };
+        Ok(Self {
+            dir,
+            include: if settings.include.is_empty() {
+                None
+            } else {
+                Some(build_globs(&settings.include)?)
+            },
+            exclude: build_globs(&settings.exclude)?,
+            marker,
+        })
+    }
+
+    fn is_included(&self, path: &Path) -> bool {
+        let path = path.strip_prefix(&self.dir).unwrap_or(path);
+        self.include.as_ref().is_none_or(|g| g.is_match(path)) && !self.exclude.is_match(path)
+    }
+}
+
+/// Project configuration, i.e., the settings in the config file at the root path merged with
+/// any overrides, along with the config files found in subdirectories.
+///
+/// A config file in a subdirectory refines the settings for its subtree: a file is only
+/// parsed if it is matched by the globs of _every_ config file above it, and the marker of the
+/// closest config file is used (unless the marker is overridden). Ignore handling,
+/// comments-only mode, the glob mode and display limits can only be set at the root.
+///
+/// In comments-only mode, markers are only recognized inside comments for files in a known
+/// language, e.g., so that a marker in a string literal is skipped.
+#[derive(Debug)]
+pub struct Config {
+    root_path: PathBuf,
+    root: Arc<Scope>,
+    /// Config files found in subdirectories so far, keyed by relative directory.
+    nested: Mutex<HashMap<PathBuf, Option<Arc<Scope>>>>,
+    source: Source,
+    /// Settings that take precedence over the config file at the root path.
+    overrides: Settings,
+    /// If set, the root marker is used for all files, i.e., it was overridden.
+    marker_override: bool,
+    /// Whether to respect ignore files in directory mode.
+    pub ignore: bool,
+    /// Whether to only recognize markers inside comments.
+    pub comments_only: bool,
+    /// Semantics of glob and directory ThenChange targets in repo mode.
+    pub glob_targets: GlobMode,
+    /// Whether all blocks are transitive (repo mode only).
+    pub transitive: bool,
+    pub max_files_to_display: usize,
+    pub max_violations_to_display: usize,
+}
+
+impl Config {
+    /// Loads the config file at the given root path (if any), with the given settings taking
+    /// precedence. Globs in the overrides are added to the ones in the config file.
+    pub fn load<P: AsRef<Path>>(root_path: P, overrides: &Settings) -> Result<Self> {
+        Self::load_from(root_path.as_ref(), overrides, Source::Disk)
+    }
+
+    /// Same as [Config::load], but reads the config files from the Git repo at the root path:
+    /// from the head revision of the given range (see [crate::Parser::from_git_range]), or from
+    /// the index if the range is None.
+    pub fn load_from_git<P: AsRef<Path>>(
+        root_path: P,
+        range: Option<&str>,
+        overrides: &Settings,
+    ) -> Result<Self> {
+        let root_path = root_path.as_ref();
+        let repo = crate::git::open(root_path)?;
+        let changes = match range {
+            Some(range) => Changes::from_range(range)?,
+            None => Changes::Staged,
+        };
+        Self::without_files(root_path, overrides)?.for_changes(&repo, &changes)
+    }
+
+    /// Builds a config from the given overrides alone, i.e., as if there were no config files.
+    pub(crate) fn without_files(root_path: &Path, overrides: &Settings) -> Result<Self> {
+        Self::load_from(root_path, overrides, Source::Files(HashMap::new()))
+    }
+
+    /// Reloads this config with the config files on the "new" side of the given changes (i.e.,
+    /// the index or the head revision), rather than the ones on disk, keeping the overrides.
+    pub(crate) fn for_changes(&self, repo: &impl Repo, changes: &Changes) -> Result<Self> {
+        let paths: Vec<PathBuf> = repo
+            .list_files(changes)?
+            .into_iter()
+            .filter(|p| p.file_name().is_some_and(|n| n == CONFIG_FILE_NAME))
+            .collect();
+        let files = repo.get_file_contents(changes, &paths)?;
+        Self::load_from(
+            &self.root_path,
+            &self.overrides,
+            Source::Files(files.into_iter().collect()),
+        )
+    }
+
+    fn load_from(root_path: &Path, overrides: &Settings, source: Source) -> Result<Self> {
+        let config_path = root_path.join(CONFIG_FILE_NAME);
+        let mut settings = source
+            .settings(root_path, Path::new(CONFIG_FILE_NAME))?
+            .unwrap_or_default();
+        settings.include.extend(overrides.include.iter().cloned());
+        settings.exclude.extend(overrides.exclude.iter().cloned());
+        let marker_override = overrides.marker.is_some()
+            || overrides.presets.is_some()
+            || !overrides.markers.is_empty();
+        if marker_override {
+            settings.marker.clone_from(&overrides.marker);
+            settings.presets.clone_from(&overrides.presets);
+            settings.markers.clone_from(&overrides.markers);
+        }
+        let root = Scope::new(&config_path, PathBuf::new(), &settings)?;
+
+        Ok(Self {
+            root_path: root_path.to_owned(),
+            root: Arc::new(root),
+            nested: Mutex::new(HashMap::new()),
+            source,
+            overrides: overrides.clone(),
+            marker_override,
+            ignore: overrides.ignore.or(settings.ignore).unwrap_or(true),
+            comments_only: overrides
+                .comments_only
+                .or(settings.comments_only)
+                .unwrap_or(false),
+            glob_targets: overrides
+                .glob_targets
+                .or(settings.glob_targets)
+                .unwrap_or_default(),
+            transitive: overrides
+                .transitive
+                .or(settings.transitive)
+                .unwrap_or(false),
+            max_files_to_display: overrides
+                .max_files_to_display
+                .or(settings.max_files_to_display)
+                .unwrap_or(DEFAULT_MAX_FILES_TO_DISPLAY),
+            max_violations_to_display: overrides
+                .max_violations_to_display
+                .or(settings.max_violations_to_display)
+                .unwrap_or(DEFAULT_MAX_VIOLATIONS_TO_DISPLAY),
+        })
+    }
+
+    /// Returns the config file in the given directory (relative to the root path), loading it
+    /// if needed.
+    ///
+    /// An invalid config file is only reported once, after which it is treated as missing.
+    fn nested_scope(&self, dir: &Path) -> Result<Option<Arc<Scope>>> {
+        if let Some(scope) = self.nested.lock().unwrap().get(dir) {
+            return Ok(scope.clone());
+        }
+        let config_path = self.root_path.join(dir).join(CONFIG_FILE_NAME);
+        let scope = match self
+            .source
+            .settings(&self.root_path, &dir.join(CONFIG_FILE_NAME))
+        {
+            Ok(Some(settings)) => {
+                if settings.ignore.is_some()
+                    || settings.comments_only.is_some()
+                    || settings.glob_targets.is_some()
+                    || settings.transitive.is_some()
+                    || settings.max_files_to_display.is_some()
+                    || settings.max_violations_to_display.is_some()
+                {
+                    log::warn!("Ignoring root-only settings in {}", config_path.display());
+                }
+                Scope::new(&config_path, dir.to_owned(), &settings)
+                    .map(|scope| Some(Arc::new(scope)))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => Err(e),
+        };
+        let cached = scope.as_ref().ok().cloned().flatten();
+        self.nested.lock().unwrap().insert(dir.to_owned(), cached);
+        scope
+    }
+
+    /// Returns the scopes that apply to the given path (relative to the root path), from the
+    /// root down.
+    fn scopes(&self, path: &Path) -> Result<Vec<Arc<Scope>>> {
+        let mut scopes = vec![self.root.clone()];
+        let mut dirs: Vec<&Path> = path
+            .ancestors()
+            .skip(1)
+            .filter(|d| !d.as_os_str().is_empty())
+            .collect();
+        dirs.reverse();
+        for dir in dirs {
+            if let Some(scope) = self.nested_scope(dir)? {
+                scopes.push(scope);
+            }
+        }
+        Ok(scopes)
+    }
+
+    /// Returns how markers are found in the given file (relative to the root path), or None if
+    /// the file is excluded.
+    pub(crate) fn marker(&self, path: &Path) -> Result<Option<Marker>> {
+        let scopes = self.scopes(path)?;
+        if !scopes.iter().all(|s| s.is_included(path)) {
+            return Ok(None);
+        }
+        let marker = if self.marker_override {
+            self.root.marker.as_ref()
+        } else {
+            scopes.iter().rev().find_map(|s| s.marker.as_ref())
+        };
+        // Clone the regex to reduce contention.
+        // See: https://docs.rs/regex/1.9.6/regex/index.html#sharing-a-regex-across-threads-can-result-in-contention
+        Ok(Some(Marker {
+            pat: marker.unwrap_or(&ON_CHANGE_PAT).clone(),
+            comments: if self.comments_only {
+                comment_syntax(path)
+            } else {
+                None
+            },
+        }))
+    }
+
+    /// Returns true if the given file (relative to the root path) should be parsed.
+    pub fn is_included<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        Ok(self.marker(path.as_ref())?.is_some())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_helpers::*;
+
+    #[test]
+    fn test_nested_config() {
+        let d = TestDir::from_files(&[
+            (
+                CONFIG_FILE_NAME,
+                "include = [\"src/**\", \"docs/*.md\"]\nexclude = [\"**/gen/**\"]\nmax_files_to_display = 3\n",
+            ),
+            (
+                "src/sub/.onchg.toml",
+                "exclude = [\"*.txt\"]\nmarker = 'X\\((?<on_change>.*?)\\)|Y\\((?<then_change>.*?)\\)'\n",
+            ),
+        ]);
+        let config = Config::load(
+            d.path(),
+            &Settings {
+                exclude: vec!["src/skip.rs".to_string()],
+                max_violations_to_display: Some(1),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(config.max_files_to_display, 3);
+        assert_eq!(config.max_violations_to_display, 1);
+        assert!(config.ignore);
+
+        for (path, included) in [
+            ("src/a.rs", true),
+            ("src/skip.rs", false),
+            ("src/gen/a.rs", false),
+            ("docs/a.md", true),
+            ("docs/sub/a.md", false),
+            ("README.md", false),
+            ("src/sub/a.rs", true),
+            ("src/sub/a.txt", false),
+            ("src/sub/deeper/a.txt", true),
+        ] {
+            assert_eq!(config.is_included(path).unwrap(), included, "{}", path);
+        }
+
+        let marker = config.marker(Path::new("src/sub/a.rs")).unwrap().unwrap();
+        assert!(marker.pat.is_match(b"X(a)"));
+        let marker = config.marker(Path::new("src/a.rs")).unwrap().unwrap();
+        assert!(!marker.pat.is_match(b"X(a)"));
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let d = TestDir::from_files(&[(CONFIG_FILE_NAME, "marker = 'abc'\n")]);
+        let err = Config::load(d.path(), &Settings::default()).unwrap_err();
+        assert!(matches!(err, Error::InvalidConfig { .. }), "{}", err);
+
+        let d =
+            TestDir::from_files(&[(CONFIG_FILE_NAME, ""), ("sub/.onchg.toml", "unknown = 1\n")]);
+        let config = Config::load(d.path(), &Settings::default()).unwrap();
+        assert!(matches!(
+            config.is_included("sub/a.txt"),
+            Err(Error::InvalidConfig { .. })
+        ));
+        // Only reported once.
+        assert!(config.is_included("sub/b.txt").unwrap());
+    }
+}
@@ -0,0 +1,86 @@
+use crate::Error;
+
+/// A collection of all errors found while parsing and validating a set of files.
+///
+/// Parsing continues past errors so that every problem in the tree can be reported in a single
+/// run. This is the error returned when a [crate::Parser] fails to build.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    errors: Vec<Error>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, error: Error) {
+        self.errors.push(error);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Error> {
+        self.errors.iter()
+    }
+
+    /// Returns Ok if there are no errors, or this collection as an error otherwise.
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl Extend<Error> for Diagnostics {
+    fn extend<T: IntoIterator<Item = Error>>(&mut self, iter: T) {
+        self.errors.extend(iter);
+    }
+}
+
+impl From<Vec<Error>> for Diagnostics {
+    fn from(errors: Vec<Error>) -> Self {
+        Self { errors }
+    }
+}
+
+impl From<Error> for Diagnostics {
+    fn from(error: Error) -> Self {
+        Self {
+            errors: vec![error],
+        }
+    }
+}
+
+impl IntoIterator for Diagnostics {
+    type Item = Error;
+    type IntoIter = std::vec::IntoIter<Error>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.errors.into_iter()
+    }
+}
+
+impl std::fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // A single error is displayed as-is.
+        if let [error] = self.errors.as_slice() {
+            return write!(f, "{}", error);
+        }
+        write!(f, "found {} errors:", self.errors.len())?;
+        for error in &self.errors {
+            write!(f, "\n  * {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
@@ -0,0 +1,404 @@
+use std::path::{Path, PathBuf};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// An error found while parsing or validating files.
+///
+/// All paths are relative to the root path of the [crate::Parser], and all lines are 1-indexed.
+///
+/// New kinds of errors can be added in minor releases, so matches must have a wildcard arm.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Two blocks in the same file have the same name.
+    DuplicateBlock {
+        path: PathBuf,
+        /// Line of the duplicate OnChange.
+        line: u32,
+        block: String,
+        /// Line of the first OnChange with the same name.
+        first_line: u32,
+    },
+    /// A ThenChange targets a file that does not exist (or was not parsed).
+    MissingTargetFile {
+        path: PathBuf,
+        /// Line of the ThenChange.
+        line: u32,
+        block: String,
+        target_file: PathBuf,
+    },
+    /// A ThenChange targets a block that does not exist in the target file.
+    MissingTargetBlock {
+        path: PathBuf,
+        /// Line of the ThenChange.
+        line: u32,
+        block: String,
+        target_file: PathBuf,
+        target_block: String,
+    },
+    /// An OnChange has no matching ThenChange.
+    UnclosedBlock {
+        path: PathBuf,
+        /// Line of the OnChange.
+        line: u32,
+        block: String,
+    },
+    /// A ThenChange has no matching OnChange.
+    OrphanThenChange { path: PathBuf, line: u32 },
+    /// A ThenChange target cannot be parsed or uses an unsupported (e.g., absolute) path.
+    InvalidTargetPath {
+        path: PathBuf,
+        line: u32,
+        target: String,
+    },
+    /// A glob or directory ThenChange target does not match any files (directory mode only).
+    UnmatchedTargetGlob {
+        path: PathBuf,
+        /// Line of the ThenChange.
+        line: u32,
+        block: String,
+        pattern: String,
+    },
+    /// A ThenChange target is malformed, e.g., it has an unterminated quote.
+    InvalidTargetSyntax {
+        path: PathBuf,
+        line: u32,
+        /// Column of the offending character (1-indexed, in characters).
+        column: u32,
+        message: String,
+    },
+    /// An OnChange has an unknown or malformed attribute (e.g., `group=`).
+    InvalidOnChange {
+        path: PathBuf,
+        line: u32,
+        message: String,
+    },
+    /// A block is the only member of its sync group (directory mode only).
+    SingleMemberGroup {
+        path: PathBuf,
+        /// Line of the OnChange.
+        line: u32,
+        block: String,
+        group: String,
+    },
+    /// The `onchg:allow-unpaired` suppression of a block has expired.
+    ExpiredSuppression {
+        path: PathBuf,
+        /// Line of the OnChange.
+        line: u32,
+        block: String,
+        until: chrono::NaiveDate,
+    },
+    /// An OnChange or ThenChange contains invalid UTF-8.
+    InvalidUtf8 { path: PathBuf, line: u32 },
+    /// The root path is not a directory.
+    InvalidRootPath { path: PathBuf },
+    /// A unified diff could not be parsed.
+    InvalidPatch { message: String },
+    /// A line passed to the pre-push hook could not be parsed.
+    InvalidPushUpdate { line: String },
+    /// An `Onchg-Skip` commit message trailer does not name a block or file and a reason.
+    InvalidSkipTrailer { line: String },
+    /// A config file could not be parsed, or contains an invalid glob or marker.
+    InvalidConfig { path: PathBuf, message: String },
+    /// Failed to read a file.
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// A Git command or libgit2 call failed.
+    Git(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl Error {
+    pub(crate) fn io(path: &Path, source: std::io::Error) -> Self {
+        Self::Io {
+            path: path.to_owned(),
+            source,
+        }
+    }
+
+    /// Returns a stable identifier for the kind of this error, for use in machine-readable
+    /// output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::DuplicateBlock { .. } => "duplicate_block",
+            Self::MissingTargetFile { .. } => "missing_target_file",
+            Self::MissingTargetBlock { .. } => "missing_target_block",
+            Self::UnclosedBlock { .. } => "unclosed_block",
+            Self::OrphanThenChange { .. } => "orphan_then_change",
+            Self::InvalidTargetPath { .. } => "invalid_target_path",
+            Self::InvalidTargetSyntax { .. } => "invalid_target_syntax",
+            Self::UnmatchedTargetGlob { .. } => "unmatched_target_glob",
+            Self::InvalidOnChange { .. } => "invalid_on_change",
+            Self::SingleMemberGroup { .. } => "single_member_group",
+            Self::ExpiredSuppression { .. } => "expired_suppression",
+            Self::InvalidUtf8 { .. } => "invalid_utf8",
+            Self::InvalidRootPath { .. } => "invalid_root_path",
+            Self::InvalidPatch { .. } => "invalid_patch",
+            Self::InvalidPushUpdate { .. } => "invalid_push_update",
+            Self::InvalidSkipTrailer { .. } => "invalid_skip_trailer",
+            Self::InvalidConfig { .. } => "invalid_config",
+            Self::Io { .. } => "io",
+            Self::Git(_) => "git",
+        }
+    }
+
+    /// Returns the path of the file this error refers to, if any.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::DuplicateBlock { path, .. }
+            | Self::MissingTargetFile { path, .. }
+            | Self::MissingTargetBlock { path, .. }
+            | Self::UnclosedBlock { path, .. }
+            | Self::OrphanThenChange { path, .. }
+            | Self::InvalidTargetPath { path, .. }
+            | Self::InvalidTargetSyntax { path, .. }
+            | Self::UnmatchedTargetGlob { path, .. }
+            | Self::InvalidOnChange { path, .. }
+            | Self::SingleMemberGroup { path, .. }
+            | Self::ExpiredSuppression { path, .. }
+            | Self::InvalidUtf8 { path, .. }
+            | Self::InvalidRootPath { path }
+            | Self::InvalidConfig { path, .. }
+            | Self::Io { path, .. } => Some(path),
+            Self::InvalidPatch { .. }
+            | Self::InvalidPushUpdate { .. }
+            | Self::InvalidSkipTrailer { .. }
+            | Self::Git(_) => None,
+        }
+    }
+
+    /// Returns this error with the root path prepended to its path, e.g., to print it along
+    /// with violations, whose paths are absolute. Errors whose path is not relative to the root
+    /// path (e.g., a config file) are returned as is.
+    pub fn with_root_path(mut self, root_path: &Path) -> Self {
+        match &mut self {
+            Self::DuplicateBlock { path, .. }
+            | Self::MissingTargetFile { path, .. }
+            | Self::MissingTargetBlock { path, .. }
+            | Self::UnclosedBlock { path, .. }
+            | Self::OrphanThenChange { path, .. }
+            | Self::InvalidTargetPath { path, .. }
+            | Self::InvalidTargetSyntax { path, .. }
+            | Self::UnmatchedTargetGlob { path, .. }
+            | Self::InvalidOnChange { path, .. }
+            | Self::SingleMemberGroup { path, .. }
+            | Self::ExpiredSuppression { path, .. }
+            | Self::InvalidUtf8 { path, .. } => *path = root_path.join(&*path),
+            _ => (),
+        }
+        self
+    }
+
+    /// Returns the line this error refers to, if any.
+    pub fn line(&self) -> Option<u32> {
+        match self {
+            Self::DuplicateBlock { line, .. }
+            | Self::MissingTargetFile { line, .. }
+            | Self::MissingTargetBlock { line, .. }
+            | Self::UnclosedBlock { line, .. }
+            | Self::OrphanThenChange { line, .. }
+            | Self::InvalidTargetPath { line, .. }
+            | Self::InvalidTargetSyntax { line, .. }
+            | Self::UnmatchedTargetGlob { line, .. }
+            | Self::InvalidOnChange { line, .. }
+            | Self::SingleMemberGroup { line, .. }
+            | Self::ExpiredSuppression { line, .. }
+            | Self::InvalidUtf8 { line, .. } => Some(*line),
+            _ => None,
+        }
+    }
+
+    /// Returns the column this error refers to, if any.
+    pub fn column(&self) -> Option<u32> {
+        match self {
+            Self::InvalidTargetSyntax { column, .. } => Some(*column),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the block this error refers to, if any.
+    pub fn block(&self) -> Option<&str> {
+        match self {
+            Self::DuplicateBlock { block, .. }
+            | Self::MissingTargetFile { block, .. }
+            | Self::MissingTargetBlock { block, .. }
+            | Self::UnclosedBlock { block, .. }
+            | Self::UnmatchedTargetGlob { block, .. }
+            | Self::SingleMemberGroup { block, .. }
+            | Self::ExpiredSuppression { block, .. } => Some(block),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DuplicateBlock {
+                path,
+                line,
+                block,
+                first_line,
+            } => write!(
+                f,
+                r#"duplicate block name "{}" found on {}:{} and {}:{}"#,
+                block,
+                path.display(),
+                first_line,
+                path.display(),
+                line,
+            ),
+            Self::MissingTargetFile {
+                path,
+                line,
+                target_file,
+                ..
+            } => write!(
+                f,
+                r#"ThenChange target file "{}" at {}:{} does not exist"#,
+                target_file.display(),
+                path.display(),
+                line,
+            ),
+            Self::MissingTargetBlock {
+                path,
+                line,
+                block,
+                target_file,
+                target_block,
+            } => write!(
+                f,
+                r#"block "{}" at "{}:{}" has non-existent ThenChange target "{}:{}""#,
+                block,
+                path.display(),
+                line,
+                target_file.display(),
+                target_block,
+            ),
+            Self::UnclosedBlock { path, line, block } => write!(
+                f,
+                r#"reached end of file {} while looking for ThenChange for block "{}" which started on line {}"#,
+                path.display(),
+                block,
+                line,
+            ),
+            Self::OrphanThenChange { path, line } => write!(
+                f,
+                r#"found ThenChange at "{}:{}" with no matching OnChange"#,
+                path.display(),
+                line,
+            ),
+            Self::InvalidTargetPath { path, line, target } => write!(
+                f,
+                r#"ThenChange target file "{}" at {}:{} is invalid"#,
+                target,
+                path.display(),
+                line,
+            ),
+            Self::UnmatchedTargetGlob {
+                path,
+                line,
+                block,
+                pattern,
+            } => write!(
+                f,
+                r#"block "{}" at {}:{} has ThenChange target "{}" which does not match any files"#,
+                block,
+                path.display(),
+                line,
+                pattern,
+            ),
+            Self::InvalidTargetSyntax {
+                path,
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "invalid ThenChange target at {}:{}:{}: {}",
+                path.display(),
+                line,
+                column,
+                message,
+            ),
+            Self::InvalidOnChange {
+                path,
+                line,
+                message,
+            } => write!(
+                f,
+                "invalid OnChange at {}:{}: {}",
+                path.display(),
+                line,
+                message,
+            ),
+            Self::SingleMemberGroup {
+                path,
+                line,
+                block,
+                group,
+            } => write!(
+                f,
+                r#"block "{}" at {}:{} is the only member of group "{}""#,
+                block,
+                path.display(),
+                line,
+                group,
+            ),
+            Self::ExpiredSuppression {
+                path,
+                line,
+                block,
+                until,
+            } => write!(
+                f,
+                r#"block "{}" at {}:{} has an onchg:allow-unpaired suppression that expired on {}"#,
+                block,
+                path.display(),
+                line,
+                until,
+            ),
+            Self::InvalidUtf8 { path, line } => {
+                write!(f, "invalid UTF-8 at {}:{}", path.display(), line)
+            }
+            Self::InvalidRootPath { path } => {
+                write!(f, "root path {} is not a directory", path.display())
+            }
+            Self::InvalidPatch { message } => write!(f, "invalid patch: {}", message),
+            Self::InvalidPushUpdate { line } => write!(f, r#"invalid pre-push line: "{}""#, line),
+            Self::InvalidSkipTrailer { line } => write!(
+                f,
+                r#"invalid trailer "{}" (expected "Onchg-Skip: <block or file> <reason>")"#,
+                line,
+            ),
+            Self::InvalidConfig { path, message } => {
+                write!(f, "invalid config file {}: {}", path.display(), message)
+            }
+            Self::Io { path, source } => write!(f, "failed to read {}: {}", path.display(), source),
+            Self::Git(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::Git(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Errors from the Git backends are reported as [anyhow::Error]s. A backend can still return
+/// one of the errors above (e.g., [Error::Io] for a file read from disk), which is unwrapped.
+impl From<anyhow::Error> for Error {
+    fn from(e: anyhow::Error) -> Self {
+        match e.downcast::<Self>() {
+            Ok(e) => e,
+            Err(e) => Self::Git(e.into()),
+        }
+    }
+}
@@ -3,45 +3,253 @@ use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use anyhow::Result;
 use bstr::ByteSlice;
+use chrono::NaiveDate;
+use globset::{GlobBuilder, GlobMatcher};
 use regex::bytes::{Captures, Regex};
+use serde::Serialize;
 
 use crate::git::{Hunk, Line};
-
-const ON_CHANGE_GROUP: &str = "on_change";
-const THEN_CHANGE_GROUP: &str = "then_change";
-pub const ON_CHANGE_PAT_STR: &str =
-    r"LINT\.OnChange\((?<on_change>.*?)\)|LINT\.ThenChange\((?<then_change>.*?)\)";
+use crate::marker::Marker;
+use crate::{Error, Result};
+
+pub(crate) const ON_CHANGE_GROUP: &str = "on_change";
+pub(crate) const THEN_CHANGE_GROUP: &str = "then_change";
+/// Group for the name of an OnChange that can be used without parentheses.
+pub(crate) const ON_CHANGE_OPT_GROUP: &str = "on_change_opt";
+/// OnChange attributes and ThenChange targets can contain quoted strings with parentheses
+/// (see [File::parse_on_change_args] and [File::build_then_change]), and the targets can span
+/// multiple lines. A lone quote is matched so that it can be reported as unterminated.
+pub const ON_CHANGE_PAT_STR: &str = r#"LINT\.OnChange\((?<on_change>(?:[^)"\n]|"(?:[^"\\\n]|\\.)*"|")*)\)|LINT\.ThenChange\((?<then_change>(?:[^)"]|"(?:[^"\\\n]|\\.)*"|")*)\)"#;
+/// Comment prefixes stripped from each line of a multi-line ThenChange.
+const THEN_CHANGE_COMMENT_PREFIXES: &[&str] = &["///", "//", "#", "*", "--"];
 lazy_static::lazy_static! {
-    static ref ON_CHANGE_PAT: Regex = Regex::new(ON_CHANGE_PAT_STR).unwrap();
+    pub(crate) static ref ON_CHANGE_PAT: Regex = Regex::new(ON_CHANGE_PAT_STR).unwrap();
+}
+
+/// A glob pattern matching a set of target files, relative to the root path.
+///
+/// A directory target (e.g., `//clients/`) is a glob matching all files under it.
+#[derive(Clone, Debug)]
+pub struct TargetGlob {
+    pattern: String,
+    matcher: GlobMatcher,
+}
+
+impl TargetGlob {
+    /// Compiles the given pattern. As with config globs, `*` does not match `/`.
+    pub fn new(pattern: &str) -> Result<Self, globset::Error> {
+        let matcher = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()?
+            .compile_matcher();
+        Ok(Self {
+            pattern: pattern.to_string(),
+            matcher,
+        })
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Returns true if the given path (relative to the root path) matches this glob.
+    pub fn is_match<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.matcher.is_match(path)
+    }
+}
+
+/// A block name pattern, where `*` matches any sequence of characters and `?` matches a
+/// single character (e.g., `api-v1-*`).
+#[derive(Clone, Debug)]
+pub struct BlockPattern {
+    pattern: String,
+    pat: regex::Regex,
+}
+
+impl BlockPattern {
+    pub fn new(pattern: &str) -> Self {
+        let mut re = String::from("^");
+        for c in pattern.chars() {
+            match c {
+                '*' => re.push_str(".*"),
+                '?' => re.push('.'),
+                c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+            }
+        }
+        re.push('$');
+        Self {
+            pattern: pattern.to_string(),
+            // All other characters are escaped, so this is always a valid regex.
+            pat: regex::Regex::new(&re).unwrap(),
+        }
+    }
+
+    /// Returns true if the given (unquoted) block name contains a wildcard.
+    pub fn is_pattern(block: &str) -> bool {
+        block.contains(['*', '?'])
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn is_match(&self, block: &str) -> bool {
+        self.pat.is_match(block)
+    }
 }
 
+/// A ThenChange target, along with the line it is on (which differs from the line of the
+/// ThenChange in a multi-line list).
 #[derive(Clone, Debug)]
 pub enum ThenChangeTarget {
-    File(PathBuf),
+    File {
+        file: PathBuf,
+        line: u32,
+    },
     Block {
         block: String,
         file: Option<PathBuf>,
+        line: u32,
+    },
+    /// All named blocks in a file that match a pattern.
+    BlockPattern {
+        pattern: BlockPattern,
+        file: Option<PathBuf>,
+        line: u32,
+    },
+    /// All files matching a glob, or under a directory.
+    Glob {
+        glob: TargetGlob,
+        line: u32,
     },
 }
 
 impl ThenChangeTarget {
+    /// Returns the line that the target is on.
+    pub fn line(&self) -> u32 {
+        match self {
+            ThenChangeTarget::File { line, .. }
+            | ThenChangeTarget::Block { line, .. }
+            | ThenChangeTarget::BlockPattern { line, .. }
+            | ThenChangeTarget::Glob { line, .. } => *line,
+        }
+    }
+
+    /// Returns the target file, or None for a same-file block (or block pattern) target or a
+    /// glob.
     pub fn file(&self) -> Option<&Path> {
         match self {
-            ThenChangeTarget::File(file) => Some(file.as_path()),
-            ThenChangeTarget::Block { file, .. } => file.as_deref(),
+            ThenChangeTarget::File { file, .. } => Some(file.as_path()),
+            ThenChangeTarget::Block { file, .. } | ThenChangeTarget::BlockPattern { file, .. } => {
+                file.as_deref()
+            }
+            ThenChangeTarget::Glob { .. } => None,
         }
     }
 
+    /// Returns the target block name, or None if the target is not a single block.
     pub fn block(&self) -> Option<&str> {
         match self {
-            ThenChangeTarget::File(_) => None,
-            ThenChangeTarget::Block { block, .. } => Some(&block),
+            ThenChangeTarget::File { .. }
+            | ThenChangeTarget::Glob { .. }
+            | ThenChangeTarget::BlockPattern { .. } => None,
+            ThenChangeTarget::Block { block, .. } => Some(block),
+        }
+    }
+
+    pub fn block_pattern(&self) -> Option<&BlockPattern> {
+        match self {
+            ThenChangeTarget::BlockPattern { pattern, .. } => Some(pattern),
+            _ => None,
+        }
+    }
+
+    pub fn glob(&self) -> Option<&TargetGlob> {
+        match self {
+            ThenChangeTarget::Glob { glob, .. } => Some(glob),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ThenChangeTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let file = self
+            .file()
+            .map(|p| p.display().to_string())
+            .unwrap_or_default();
+        match self {
+            ThenChangeTarget::File { .. } => write!(f, "{}", file),
+            ThenChangeTarget::Block { block, .. } => write!(f, "{}:{}", file, block),
+            ThenChangeTarget::BlockPattern { pattern, .. } => {
+                write!(f, "{}:{}", file, pattern.pattern())
+            }
+            ThenChangeTarget::Glob { glob, .. } => write!(f, "{}", glob.pattern()),
+        }
+    }
+}
+
+/// Whether the violations of a block fail the run, set with the `severity` attribute.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    Error,
+    /// Violations are reported, but do not fail the run.
+    Warn,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warn => "warn",
         }
     }
 }
 
+/// Directive after an OnChange that allows the block to change without its targets, and its
+/// targets without it, e.g., `LINT.OnChange(name) onchg:allow-unpaired until=2026-12-31`.
+pub const ALLOW_UNPAIRED: &str = "onchg:allow-unpaired";
+
+/// Tokens that close a block comment, which may follow a suppression on the same line.
+const COMMENT_TERMINATORS: &[&str] = &["*/", "-->", "]]", "-}"];
+
+/// An `onchg:allow-unpaired` suppression on a block, for blocks that are temporarily allowed
+/// to diverge (e.g., during a migration).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Suppression {
+    /// Last day on which the suppression applies.
+    until: NaiveDate,
+}
+
+impl Suppression {
+    pub fn until(&self) -> NaiveDate {
+        self.until
+    }
+
+    /// Returns true if the suppression no longer applies on the given day.
+    pub fn is_expired(&self, today: NaiveDate) -> bool {
+        today > self.until
+    }
+
+    /// Returns the reason given for the violations waived by this suppression.
+    pub fn reason(&self) -> String {
+        format!("allowed unpaired until {}", self.until)
+    }
+}
+
+/// How many ThenChange targets of a block must change along with it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Quantifier {
+    /// Every target (the default).
+    #[default]
+    All,
+    /// At least this many targets, set with `at-least=N:`. `any:` is the same as `at-least=1:`.
+    AtLeast(u32),
+}
+
 #[derive(Clone, Debug)]
 pub enum ThenChange {
     Unset,
@@ -70,6 +278,15 @@ pub struct OnChangeBlock {
     start_line: u32,
     end_line: u32,
     then_change: ThenChange,
+    /// Sync group of this block. Every block in a group implicitly targets all others.
+    group: Option<String>,
+    quantifier: Quantifier,
+    /// If set, all blocks reachable through the targets of this block must change too.
+    transitive: bool,
+    severity: Severity,
+    owner: Option<String>,
+    reason: Option<String>,
+    suppression: Option<Suppression>,
 }
 
 impl OnChangeBlock {
@@ -86,6 +303,13 @@ impl OnChangeBlock {
             start_line,
             end_line,
             then_change,
+            group: None,
+            quantifier: Quantifier::All,
+            transitive: false,
+            severity: Severity::Error,
+            owner: None,
+            reason: None,
+            suppression: None,
         }
     }
 
@@ -117,7 +341,43 @@ impl OnChangeBlock {
         &self.then_change
     }
 
+    pub fn group(&self) -> Option<&str> {
+        self.group.as_deref()
+    }
+
+    /// Returns true if the block is marked as `transitive`, i.e., all blocks reachable through
+    /// its targets must change along with it.
+    pub fn is_transitive(&self) -> bool {
+        self.transitive
+    }
+
+    /// Returns whether violations of this block fail the run or are only warnings.
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// Returns the owner of this block (e.g., `@team-api`), shown in violations.
+    pub fn owner(&self) -> Option<&str> {
+        self.owner.as_deref()
+    }
+
+    /// Returns why this block has targets (e.g., "wire compat"), shown in violations.
+    pub fn reason(&self) -> Option<&str> {
+        self.reason.as_deref()
+    }
+
+    /// Returns the `onchg:allow-unpaired` suppression of this block, if any.
+    pub fn suppression(&self) -> Option<Suppression> {
+        self.suppression
+    }
+
+    /// Returns how many of the ThenChange targets must change along with this block.
+    pub fn quantifier(&self) -> Quantifier {
+        self.quantifier
+    }
+
     /// Fast check to see if a hunk overlaps with this block.
+    #[allow(clippy::nonminimal_bool)]
     pub fn is_hunk_overlap(&self, hunk: &Hunk) -> bool {
         // Block contains hunk.
         hunk.start_line >= self.start_line && hunk.end_line <= self.end_line ||
@@ -190,6 +450,9 @@ impl OnChangeBlock {
 
     /// Returns an iterator over ThenChangeTarget(s) as tuples of (file_path, block_name).
     /// If a target has no path set, it will be replaced with this block's file path.
+    ///
+    /// Glob and block pattern targets are skipped (see [OnChangeBlock::glob_targets] and
+    /// [OnChangeBlock::block_pattern_targets]).
     pub fn get_then_change_targets_as_keys<'a>(
         &'a self,
     ) -> Box<dyn Iterator<Item = (&'a Path, Option<&'a str>)> + 'a> {
@@ -198,30 +461,81 @@ impl OnChangeBlock {
             ThenChange::Targets(targets) => Box::new(
                 targets
                     .iter()
+                    .filter(|t| t.glob().is_none() && t.block_pattern().is_none())
                     .map(move |t| (t.file().unwrap_or_else(|| self.file()), t.block())),
             ),
         }
     }
+
+    /// Returns the ThenChange targets of this block, if any.
+    pub fn then_change_targets(&self) -> &[ThenChangeTarget] {
+        match &self.then_change {
+            ThenChange::Targets(targets) => targets.as_slice(),
+            ThenChange::NoTarget | ThenChange::Unset => &[],
+        }
+    }
+
+    /// Returns an iterator over the glob (and directory) targets of this block.
+    pub fn glob_targets(&self) -> impl Iterator<Item = &TargetGlob> {
+        self.then_change_targets().iter().filter_map(|t| t.glob())
+    }
+
+    /// Returns an iterator over the block pattern targets of this block as tuples of
+    /// (file_path, pattern). As with [OnChangeBlock::get_then_change_targets_as_keys], a
+    /// target without a path is in this block's file.
+    pub fn block_pattern_targets(&self) -> impl Iterator<Item = (&Path, &BlockPattern)> {
+        self.then_change_targets().iter().filter_map(move |t| {
+            t.block_pattern()
+                .map(|p| (t.file().unwrap_or_else(|| self.file()), p))
+        })
+    }
 }
 
+/// The file and block parts of a ThenChange target, with any quotes and escapes removed.
+#[derive(Debug)]
+struct RawTarget {
+    file: String,
+    /// Quoted files are never treated as globs.
+    quoted: bool,
+    block: Option<String>,
+    /// Quoted block names are never treated as patterns.
+    block_quoted: bool,
+}
+
+/// The name and attributes of an OnChange.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct OnChangeArgs<'a> {
+    name: Option<&'a str>,
+    group: Option<String>,
+    transitive: bool,
+    severity: Severity,
+    owner: Option<String>,
+    reason: Option<String>,
+}
+
+/// A malformed ThenChange target, as a byte position in the line and a message.
+type SyntaxError = (usize, String);
+
 #[derive(Debug)]
 enum LineMatch<'a> {
-    OnChange(usize, &'a [u8]),
-    ThenChange(usize, &'a [u8]),
+    /// Also holds the rest of the line after the OnChange, for suppressions.
+    OnChange(usize, &'a [u8], &'a [u8]),
+    /// Also holds the byte position of the targets, for error columns.
+    ThenChange(usize, &'a [u8], usize),
 }
 
 impl<'a> LineMatch<'a> {
     #[inline(always)]
     fn pos(&self) -> usize {
         match *self {
-            LineMatch::OnChange(p, _) | LineMatch::ThenChange(p, _) => p,
+            LineMatch::OnChange(p, ..) | LineMatch::ThenChange(p, ..) => p,
         }
     }
 
     #[inline(always)]
     fn data(&self) -> &[u8] {
         match *self {
-            LineMatch::OnChange(_, d) | LineMatch::ThenChange(_, d) => d,
+            LineMatch::OnChange(_, d, _) | LineMatch::ThenChange(_, d, _) => d,
         }
     }
 }
@@ -232,11 +546,15 @@ pub struct File {
     pub(crate) path: PathBuf,
     /// List of parsed blocks in the file.
     pub(crate) blocks: Vec<OnChangeBlock>,
+    /// Errors found while parsing the file.
+    errors: Vec<Error>,
 }
 
 impl File {
     /// Parse the file path specified in the ThenChange and convert it into a useable path
-    /// that is _relative_ to the provided root path.
+    /// that is _relative_ to the root path.
+    ///
+    /// Note that this does not check that the file exists. See [File::validate_target_files].
     ///
     /// Supported cases:
     ///
@@ -256,7 +574,6 @@ impl File {
     /// 3. ThenChange(//hello.txt:hello): Path is "hello.txt"
     fn parse_then_target_file_path(
         path: &Path,
-        root_path: &Path,
         then_change_target: &str,
         line_num: usize,
     ) -> Result<PathBuf> {
@@ -293,21 +610,11 @@ impl File {
             // Case 3.
             file_path = PathBuf::from(raw_path_str.strip_prefix("//").unwrap());
         } else {
-            return Err(anyhow::anyhow!(
-                r#"ThenChange target file "{}" at {}:{} is invalid"#,
-                raw_path.display(),
-                path.display(),
-                line_num,
-            ));
-        }
-
-        if !root_path.join(&file_path).exists() {
-            return Err(anyhow::anyhow!(
-                r#"ThenChange target file "{}" at {}:{} does not exist"#,
-                file_path.display(),
-                path.display(),
-                line_num,
-            ));
+            return Err(Error::InvalidTargetPath {
+                path: path.to_owned(),
+                line: line_num as u32,
+                target: raw_path_str.to_string(),
+            });
         }
 
         Ok(file_path)
@@ -315,101 +622,492 @@ impl File {
 
     fn parse_single_then_change_target(
         path: &Path,
-        root_path: &Path,
-        then_change_target: &str,
+        then_change_target: RawTarget,
         line_num: usize,
     ) -> Result<ThenChangeTarget> {
-        if !then_change_target.contains(":") {
-            // Try to parse as just a file target.
-            let file_path =
-                Self::parse_then_target_file_path(path, root_path, then_change_target, line_num)?;
-            return Ok(ThenChangeTarget::File(file_path).into());
-        }
-
-        let split_target: Vec<&str> = then_change_target.split(":").collect();
-        if split_target.len() < 2 {
-            return Err(anyhow::anyhow!(
-                "invalid ThenChange target on line {}: \"{}\"",
-                line_num,
-                then_change_target
-            ));
+        let RawTarget {
+            file,
+            quoted,
+            block,
+            block_quoted,
+        } = then_change_target;
+        let line = line_num as u32;
+        if !quoted && Self::is_glob(&file) {
+            if block.is_some() {
+                return Err(Error::InvalidTargetPath {
+                    path: path.to_owned(),
+                    line: line_num as u32,
+                    target: file,
+                });
+            }
+            return Self::parse_then_target_glob(path, &file, line_num)
+                .map(|glob| ThenChangeTarget::Glob { glob, line });
         }
-        let block_name = split_target[1];
-        if split_target[0] == "" {
-            // Block target in same file.
-            return Ok(ThenChangeTarget::Block {
-                block: block_name.to_string(),
-                file: None,
+        let block_name = match block {
+            Some(block_name) => block_name,
+            None => {
+                // Just a file target.
+                let file = Self::parse_then_target_file_path(path, &file, line_num)?;
+                return Ok(ThenChangeTarget::File { file, line });
+            }
+        };
+        // An empty file is a block target in the same file.
+        let file = if file.is_empty() {
+            None
+        } else {
+            Some(Self::parse_then_target_file_path(path, &file, line_num)?)
+        };
+        if !block_quoted && BlockPattern::is_pattern(&block_name) {
+            return Ok(ThenChangeTarget::BlockPattern {
+                pattern: BlockPattern::new(&block_name),
+                file,
+                line,
             });
         }
-
-        // Block target in another file.
-        let file_path =
-            Self::parse_then_target_file_path(path, root_path, split_target[0], line_num)?;
-
         Ok(ThenChangeTarget::Block {
-            block: block_name.to_string(),
-            file: Some(file_path),
+            block: block_name,
+            file,
+            line,
         })
     }
 
+    /// Returns true if the given (unquoted) target file is a glob or a directory.
+    fn is_glob(file: &str) -> bool {
+        file.ends_with('/') || file.contains(['*', '?', '[', '{'])
+    }
+
+    /// Parses a glob or directory target. The pattern is resolved like a file path (see
+    /// [File::parse_then_target_file_path]), and a directory matches all files under it.
+    fn parse_then_target_glob(path: &Path, pattern: &str, line_num: usize) -> Result<TargetGlob> {
+        let invalid = || Error::InvalidTargetPath {
+            path: path.to_owned(),
+            line: line_num as u32,
+            target: pattern.to_string(),
+        };
+        let resolved = match pattern.strip_suffix('/') {
+            Some(dir) if !dir.is_empty() && !dir.ends_with('/') => {
+                Self::parse_then_target_file_path(path, dir, line_num)?.join("**")
+            }
+            Some(_) => return Err(invalid()),
+            None => Self::parse_then_target_file_path(path, pattern, line_num)?,
+        };
+        TargetGlob::new(&resolved.to_string_lossy()).map_err(|_| invalid())
+    }
+
+    /// Reads the file or block part of a target starting at the given byte position, and
+    /// advances the position past it (and any trailing whitespace).
+    ///
+    /// A part is either bare, in which case it ends at the next `:`, `,`, `"` or `(`, or
+    /// quoted, in which case it can contain any character, and `\"` and `\\` are the only
+    /// valid escapes. Returns the part along with whether it was quoted.
+    fn read_target_part(
+        line: &str,
+        pos: &mut usize,
+    ) -> std::result::Result<(String, bool), SyntaxError> {
+        let bytes = line.as_bytes();
+        *pos = line.len() - line[*pos..].trim_start().len();
+        if bytes.get(*pos) != Some(&b'"') {
+            let start = *pos;
+            while bytes
+                .get(*pos)
+                .is_some_and(|b| !matches!(b, b':' | b',' | b'"' | b'('))
+            {
+                *pos += 1;
+            }
+            return Ok((line[start..*pos].trim_end().to_string(), false));
+        }
+
+        let quote_pos = *pos;
+        let mut part = String::new();
+        let mut chars = line[quote_pos + 1..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    let end = quote_pos + 1 + i + 1;
+                    *pos = line.len() - line[end..].trim_start().len();
+                    return Ok((part, true));
+                }
+                '\\' => match chars.next() {
+                    Some((_, c @ ('"' | '\\'))) => part.push(c),
+                    _ => {
+                        return Err((
+                            quote_pos + 1 + i,
+                            r#"invalid escape sequence (only \" and \\ are supported)"#.to_string(),
+                        ))
+                    }
+                },
+                c => part.push(c),
+            }
+        }
+        Err((quote_pos, "unterminated quote".to_string()))
+    }
+
+    /// Splits a line of comma-separated ThenChange targets into their file and block parts.
+    /// Empty targets are skipped to allow for trailing commas.
+    fn split_then_change_targets(line: &str) -> std::result::Result<Vec<RawTarget>, SyntaxError> {
+        let mut targets = Vec::new();
+        let mut pos = 0;
+        loop {
+            let start = line.len() - line[pos..].trim_start().len();
+            let (file, quoted) = Self::read_target_part(line, &mut pos)?;
+            let (block, block_quoted) = if line.as_bytes().get(pos) == Some(&b':') {
+                pos += 1;
+                let (block, block_quoted) = Self::read_target_part(line, &mut pos)?;
+                (Some(block), block_quoted)
+            } else {
+                (None, false)
+            };
+            match line[pos..].chars().next() {
+                None | Some(',') => {
+                    if !file.is_empty() || block.is_some() {
+                        targets.push(RawTarget {
+                            file,
+                            quoted,
+                            block,
+                            block_quoted,
+                        });
+                    } else if quoted {
+                        return Err((start, "empty target".to_string()));
+                    }
+                    if pos == line.len() {
+                        return Ok(targets);
+                    }
+                    pos += 1;
+                }
+                Some(':') => {
+                    return Err((
+                        pos,
+                        r#"unexpected ':' (quote the block name if it contains a ':')"#.to_string(),
+                    ))
+                }
+                // Since the list ends at the first ')', an unquoted '(' means that the list
+                // was not closed, and has swallowed the lines (and markers) that follow it.
+                Some('(') => {
+                    return Err((
+                        pos,
+                        "unexpected '(' (is a ')' missing at the end of the ThenChange?)"
+                            .to_string(),
+                    ))
+                }
+                Some(c) => return Err((pos, format!("unexpected '{}'", c))),
+            }
+        }
+    }
+
+    /// Strips a comment prefix from a line of a multi-line ThenChange. The prefix must be
+    /// followed by whitespace so that a //-prefixed target path is left as is.
+    fn strip_comment_prefix(line: &str) -> &str {
+        for prefix in THEN_CHANGE_COMMENT_PREFIXES {
+            if let Some(rest) = line.strip_prefix(prefix) {
+                if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+                    return rest.trim_start();
+                }
+            }
+        }
+        line
+    }
+
+    /// Builds the ThenChange for a block. Invalid targets are skipped and added to errors.
+    ///
+    /// Targets are separated by commas and/or newlines, so a long list can be split with one
+    /// target per (comment) line:
+    ///
+    /// ```text
+    /// // LINT.ThenChange(
+    /// //   a.txt,
+    /// //   b.txt:b
+    /// // )
+    /// ```
+    ///
+    /// Paths or block names containing special characters can be quoted, e.g.,
+    /// `"a, b.txt":"block:name"`.
+    ///
+    /// Errors are reported on the line of the target, starting at the given line number. The
+    /// given column is the column of the first line's content, i.e., right after the `(`.
     fn build_then_change(
         path: &Path,
-        root_path: &Path,
         then_change_target: &str,
         line_num: usize,
-    ) -> Result<ThenChange> {
-        let then_change_target = then_change_target.trim();
-        if then_change_target.is_empty() {
-            return Ok(ThenChange::NoTarget);
+        column: usize,
+        errors: &mut Vec<Error>,
+    ) -> (ThenChange, Quantifier) {
+        if then_change_target.trim().is_empty() {
+            return (ThenChange::NoTarget, Quantifier::All);
         }
 
-        // Split on comma to build a list of targets.
-        let split_by_comma: Vec<&str> = then_change_target.split(",").collect();
-        let split_by_comma = if split_by_comma.len() == 0 {
-            // Single target.
-            vec![then_change_target]
-        } else {
-            split_by_comma
+        let mut then_change_targets = Vec::new();
+        let mut num_targets = 0;
+        let mut quantifier = Quantifier::All;
+        // Line and column of the quantifier, for errors.
+        let mut quantifier_pos = None;
+        for (i, raw_line) in then_change_target.split('\n').enumerate() {
+            let line = raw_line.trim_start();
+            let mut line = if i == 0 {
+                line
+            } else {
+                Self::strip_comment_prefix(line)
+            };
+            // Convert a byte position in the stripped line to a column in the file.
+            let to_column = |line: &str, pos: usize| {
+                let pos = raw_line.len() - line.len() + pos;
+                let first_column = if i == 0 { column } else { 1 };
+                (first_column + raw_line[..pos].chars().count()) as u32
+            };
+            let parsed_quantifier = if num_targets == 0 && quantifier_pos.is_none() {
+                Self::parse_quantifier(line)
+            } else {
+                Ok(None)
+            };
+            let targets = match parsed_quantifier {
+                Ok(Some((q, len))) => {
+                    quantifier = q;
+                    quantifier_pos = Some((line_num + i, to_column(line, 0)));
+                    line = &line[len..];
+                    Self::split_then_change_targets(line)
+                }
+                Ok(None) => Self::split_then_change_targets(line),
+                Err(e) => Err(e),
+            };
+            let targets = match targets {
+                Ok(targets) => targets,
+                Err((pos, message)) => {
+                    errors.push(Error::InvalidTargetSyntax {
+                        path: path.to_owned(),
+                        line: (line_num + i) as u32,
+                        column: to_column(line, pos),
+                        message,
+                    });
+                    num_targets += 1;
+                    continue;
+                }
+            };
+            for target in targets {
+                num_targets += 1;
+                match Self::parse_single_then_change_target(path, target, line_num + i) {
+                    Ok(t) => then_change_targets.push(t),
+                    Err(e) => errors.push(e),
+                }
+            }
+        }
+
+        if let (Quantifier::AtLeast(n), Some((line, column))) = (quantifier, quantifier_pos) {
+            if n as usize > num_targets {
+                errors.push(Error::InvalidTargetSyntax {
+                    path: path.to_owned(),
+                    line: line as u32,
+                    column,
+                    message: format!("expected at least {} targets, found {}", n, num_targets),
+                });
+            }
+        }
+        if num_targets == 0 {
+            // Only comment prefixes or commas, e.g., an empty multi-line ThenChange.
+            return (ThenChange::NoTarget, Quantifier::All);
+        }
+        (then_change_targets.into(), quantifier)
+    }
+
+    /// Parses a quantifier (`any:` or `at-least=N:`) at the start of a ThenChange target list.
+    /// Returns the quantifier and its length in bytes, or None if the line does not start
+    /// with one. The `:` must be followed by whitespace or the end of the line so that a
+    /// same-file block target such as `any:block` is not mistaken for a quantifier.
+    fn parse_quantifier(
+        line: &str,
+    ) -> std::result::Result<Option<(Quantifier, usize)>, SyntaxError> {
+        let is_end = |rest: &str| rest.chars().next().is_none_or(char::is_whitespace);
+        if let Some(rest) = line.strip_prefix("any:") {
+            if is_end(rest) {
+                return Ok(Some((Quantifier::AtLeast(1), line.len() - rest.len())));
+            }
+            return Ok(None);
+        }
+        let Some(rest) = line.strip_prefix("at-least=") else {
+            return Ok(None);
+        };
+        let Some((n, rest)) = rest.split_once(':').filter(|(_, rest)| is_end(rest)) else {
+            return Ok(None);
         };
+        match n.parse::<u32>() {
+            Ok(n) if n > 0 => Ok(Some((Quantifier::AtLeast(n), line.len() - rest.len()))),
+            _ => Err((
+                "at-least=".len(),
+                format!(r#"expected a positive number, found "{}""#, n),
+            )),
+        }
+    }
 
-        let mut then_change_targets = Vec::new();
-        for target in split_by_comma {
-            let target = target.trim();
-            let t = Self::parse_single_then_change_target(path, root_path, target, line_num)?;
-            then_change_targets.push(t);
+    /// Parses the contents of an OnChange: an optional block name followed by comma-separated
+    /// `key=value` attributes and flags, e.g., `name, group=wire-format, transitive`. Without
+    /// attributes, the whole string is the name.
+    ///
+    /// Values can be quoted to contain commas or parentheses, in which case `\"` and `\\` are
+    /// the only valid escapes.
+    fn parse_on_change_args(parsed: &str) -> std::result::Result<OnChangeArgs<'_>, String> {
+        if !parsed.contains(['=', ',']) {
+            return Ok(OnChangeArgs {
+                name: Some(parsed).filter(|s| !s.is_empty()),
+                ..Default::default()
+            });
+        }
+        let mut args = OnChangeArgs::default();
+        let mut seen = Vec::new();
+        for (i, part) in Self::split_on_change_args(parsed)?.into_iter().enumerate() {
+            let Some((key, value)) = part.split_once('=') else {
+                match part {
+                    "transitive" if i > 0 => args.transitive = true,
+                    _ if i == 0 && !part.is_empty() => args.name = Some(part),
+                    _ => return Err(format!(r#"expected an attribute, found "{}""#, part)),
+                }
+                continue;
+            };
+            let key = key.trim();
+            if seen.contains(&key) {
+                return Err(format!(r#"duplicate attribute "{}""#, key));
+            }
+            seen.push(key);
+            let value = Self::unquote_attribute(value.trim())?;
+            if value.is_empty() {
+                return Err(format!(r#"attribute "{}" has no value"#, key));
+            }
+            match key {
+                "group" => args.group = Some(value),
+                "owner" => args.owner = Some(value),
+                "reason" => args.reason = Some(value),
+                "transitive" => {
+                    args.transitive = match value.as_str() {
+                        "true" => true,
+                        "false" => false,
+                        _ => return Err(format!(r#"expected true or false, found "{}""#, value)),
+                    }
+                }
+                "severity" => {
+                    args.severity = match value.as_str() {
+                        "error" => Severity::Error,
+                        "warn" => Severity::Warn,
+                        _ => return Err(format!(r#"expected error or warn, found "{}""#, value)),
+                    }
+                }
+                _ => return Err(format!(r#"unknown attribute "{}""#, key)),
+            }
+        }
+        Ok(args)
+    }
+
+    /// Splits the contents of an OnChange on commas that are not inside quotes, and trims each
+    /// part.
+    fn split_on_change_args(parsed: &str) -> std::result::Result<Vec<&str>, String> {
+        let mut parts = Vec::new();
+        let (mut start, mut quoted, mut escaped) = (0, false, false);
+        for (i, c) in parsed.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if quoted => escaped = true,
+                '"' => quoted = !quoted,
+                ',' if !quoted => {
+                    parts.push(parsed[start..i].trim());
+                    start = i + 1;
+                }
+                _ => (),
+            }
+        }
+        if quoted {
+            return Err("unterminated quote".to_string());
+        }
+        parts.push(parsed[start..].trim());
+        Ok(parts)
+    }
+
+    /// Removes the quotes and escapes from an attribute value, if it is quoted.
+    fn unquote_attribute(value: &str) -> std::result::Result<String, String> {
+        let inner = match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            Some(inner) => inner,
+            None if value.contains('"') => {
+                return Err(format!(r#"unexpected quote in "{}""#, value));
+            }
+            None => return Ok(value.to_string()),
+        };
+        let mut unquoted = String::with_capacity(inner.len());
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some(c @ ('"' | '\\')) => unquoted.push(c),
+                    _ => return Err(format!(r#"invalid escape in "{}""#, value)),
+                },
+                c => unquoted.push(c),
+            }
         }
+        Ok(unquoted)
+    }
 
-        Ok(then_change_targets.into())
+    /// Parses an `onchg:allow-unpaired` suppression in the rest of the line after an OnChange.
+    /// The directive must be followed by `until=YYYY-MM-DD`, and then only by the end of a
+    /// block comment (e.g., `-->`).
+    fn parse_suppression(rest: &str) -> std::result::Result<Option<Suppression>, String> {
+        let mut words = rest.split_whitespace();
+        if !words.any(|w| w == ALLOW_UNPAIRED) {
+            return Ok(None);
+        }
+        let Some(date) = words.next().and_then(|w| w.strip_prefix("until=")) else {
+            return Err(format!(
+                "expected until=YYYY-MM-DD after {}, as suppressions must expire",
+                ALLOW_UNPAIRED
+            ));
+        };
+        let until = NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| {
+            format!(
+                r#"expected a date (YYYY-MM-DD) for until, found "{}""#,
+                date
+            )
+        })?;
+        if let Some(word) = words.find(|w| !COMMENT_TERMINATORS.contains(w)) {
+            return Err(format!(r#"unexpected "{}" after {}"#, word, ALLOW_UNPAIRED));
+        }
+        Ok(Some(Suppression { until }))
     }
 
     fn handle_on_change(
         file: Arc<PathBuf>,
         parsed: &str,
+        rest: &str,
         line_num: usize,
         block_name_to_start_line: &mut HashMap<String, usize>,
         block_stack: &mut Vec<OnChangeBlock>,
-    ) -> Result<()> {
-        let block_name = if parsed.is_empty() {
-            // An unnamed OnChange block is untargetable by other blocks.
+        errors: &mut Vec<Error>,
+    ) {
+        // An unnamed OnChange block is untargetable by other blocks. An invalid block is still
+        // pushed so that its ThenChange is matched up correctly.
+        let args = Self::parse_on_change_args(parsed).unwrap_or_else(|message| {
+            errors.push(Error::InvalidOnChange {
+                path: file.to_path_buf(),
+                line: line_num as u32,
+                message,
+            });
+            OnChangeArgs::default()
+        });
+        let block_name = args.name;
+        let suppression = Self::parse_suppression(rest).unwrap_or_else(|message| {
+            errors.push(Error::InvalidOnChange {
+                path: file.to_path_buf(),
+                line: line_num as u32,
+                message,
+            });
             None
-        } else {
-            Some(parsed)
-        };
+        });
 
-        // Check for a duplicate block in the file.
+        // Check for a duplicate block in the file. The block is still pushed so that its
+        // ThenChange is matched up correctly.
         if let Some(block_name) = block_name {
-            if block_name_to_start_line.contains_key(block_name) {
-                return Err(anyhow::anyhow!(
-                    "duplicate block name \"{}\" found on {}:{} and {}:{}",
-                    block_name,
-                    file.display(),
-                    block_name_to_start_line[block_name],
-                    file.display(),
-                    line_num,
-                ));
+            if let Some(start_line) = block_name_to_start_line.get(block_name) {
+                errors.push(Error::DuplicateBlock {
+                    path: file.to_path_buf(),
+                    line: line_num as u32,
+                    block: block_name.to_string(),
+                    first_line: *start_line as u32,
+                });
+            } else {
+                block_name_to_start_line.insert(block_name.to_string(), line_num);
             }
-            block_name_to_start_line.insert(block_name.to_string(), line_num);
         }
 
         block_stack.push(OnChangeBlock {
@@ -418,30 +1116,38 @@ impl File {
             start_line: line_num as u32,
             end_line: 0,
             then_change: ThenChange::Unset,
+            group: args.group,
+            quantifier: Quantifier::All,
+            transitive: args.transitive,
+            severity: args.severity,
+            owner: args.owner,
+            reason: args.reason,
+            suppression,
         });
-
-        Ok(())
     }
 
     fn handle_then_change(
         path: &Path,
-        root_path: &Path,
         parsed: &str,
         line_num: usize,
+        column: usize,
         block_stack: &mut Vec<OnChangeBlock>,
-    ) -> Result<OnChangeBlock> {
+        errors: &mut Vec<Error>,
+    ) -> Option<OnChangeBlock> {
         let mut block = if let Some(block) = block_stack.pop() {
             block
         } else {
-            return Err(anyhow::anyhow!(
-                r#"found ThenChange at "{}:{}" with no matching OnChange"#,
-                path.display(),
-                line_num,
-            ));
+            errors.push(Error::OrphanThenChange {
+                path: path.to_owned(),
+                line: line_num as u32,
+            });
+            return None;
         };
-        block.end_line = line_num as u32;
-        block.then_change = Self::build_then_change(path, root_path, &parsed, line_num)?;
-        Ok(block)
+        // A multi-line ThenChange ends at the closing parenthesis.
+        block.end_line = (line_num + parsed.matches('\n').count()) as u32;
+        (block.then_change, block.quantifier) =
+            Self::build_then_change(path, parsed, line_num, column, errors);
+        Some(block)
     }
 
     fn try_find_on_change_captures<'a>(
@@ -458,10 +1164,8 @@ impl File {
     fn build_byte_pos_to_line_mapping(data: &[u8]) -> Vec<(usize, usize)> {
         let mut v = Vec::new();
         let mut pos = 0;
-        let mut line_num = 1;
-        for l in data.lines_with_terminator() {
+        for (line_num, l) in (1..).zip(data.lines_with_terminator()) {
             v.push((pos, line_num));
-            line_num += 1;
             pos += l.len();
         }
         v
@@ -479,80 +1183,128 @@ impl File {
         mapping[idx].1
     }
 
-    pub fn parse_internal(path: Arc<PathBuf>, root_path: &Path) -> Result<Vec<OnChangeBlock>> {
-        // Read the entire file into memory. Since we're mostly working with text files,
-        // this shouldn't be an issue.
-        let mut f = std::fs::File::open(root_path.join(path.as_path()))?;
-        let mut buf = Vec::new();
-        f.read_to_end(&mut buf)?;
+    /// Parses all blocks in the given file content, failing on the first error.
+    ///
+    /// This does not touch the filesystem, so it can also be used to parse content that
+    /// does not exist on disk (e.g., an older revision of the file).
+    pub fn parse_internal(
+        path: Arc<PathBuf>,
+        buf: &[u8],
+        marker: &Marker,
+    ) -> Result<Vec<OnChangeBlock>> {
+        let (blocks, errors) = Self::parse_blocks(path, buf, marker);
+        match errors.into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(blocks),
+        }
+    }
 
+    /// Parses all blocks in the given file content. Unlike [File::parse_internal], parsing
+    /// continues past errors, and all valid blocks are returned along with the errors found.
+    ///
+    /// Markers are found using the given pattern (see [crate::Config]). If the marker has a
+    /// comment syntax, markers outside of comments are skipped.
+    pub fn parse_blocks(
+        path: Arc<PathBuf>,
+        buf: &[u8],
+        marker: &Marker,
+    ) -> (Vec<OnChangeBlock>, Vec<Error>) {
+        let mut errors = Vec::new();
         let mut blocks: Vec<OnChangeBlock> = Vec::new();
         let mut block_stack: Vec<OnChangeBlock> = Vec::new();
         let mut block_name_to_start_line: HashMap<String, usize> = HashMap::new();
 
-        // Clone the regex to reduce contention.
-        // See: https://docs.rs/regex/1.9.6/regex/index.html#sharing-a-regex-across-threads-can-result-in-contention
-        let pat = ON_CHANGE_PAT.clone();
+        // Masking keeps byte positions intact, so the line mapping below still applies.
+        let masked;
+        let data = match marker.comments {
+            Some(comments) => {
+                masked = comments.mask(buf);
+                &masked[..]
+            }
+            None => buf,
+        };
 
         // Build set of line matches based on byte position in the file.
         let mut matches: Vec<LineMatch> = Vec::new();
-        if let Some(captures) = Self::try_find_on_change_captures(&buf, &pat) {
+        let pat = &marker.pat;
+        let has_bare_on_change = pat.capture_names().any(|n| n == Some(ON_CHANGE_OPT_GROUP));
+        if let Some(captures) = Self::try_find_on_change_captures(data, pat) {
             for c in captures {
                 // Use start of the overall match as the byte position.
-                let pos = c.get(0).unwrap().start();
-                if let Some(m) = c.name(ON_CHANGE_GROUP) {
-                    matches.push(LineMatch::OnChange(pos, m.as_bytes()));
-                } else if let Some(m) = c.name(THEN_CHANGE_GROUP) {
-                    matches.push(LineMatch::ThenChange(pos, m.as_bytes()));
+                let (pos, end) = (c.get(0).unwrap().start(), c.get(0).unwrap().end());
+                let rest = data[end..].lines().next().unwrap_or_default();
+                if let Some(m) = c.name(THEN_CHANGE_GROUP) {
+                    matches.push(LineMatch::ThenChange(pos, m.as_bytes(), m.start()));
+                } else if let Some(m) = c.name(ON_CHANGE_GROUP) {
+                    matches.push(LineMatch::OnChange(pos, m.as_bytes(), rest));
+                } else if has_bare_on_change {
+                    // An OnChange without parentheses is an unnamed block.
+                    let name = c
+                        .name(ON_CHANGE_OPT_GROUP)
+                        .map_or(&[][..], |m| m.as_bytes());
+                    matches.push(LineMatch::OnChange(pos, name, rest));
                 }
             }
         }
 
         if matches.is_empty() {
-            return Ok(blocks);
+            return (blocks, errors);
         }
 
         // Build a mapping from byte position in the file to line number.
-        let byte_pos_to_line_mapping = Self::build_byte_pos_to_line_mapping(&buf);
+        let byte_pos_to_line_mapping = Self::build_byte_pos_to_line_mapping(buf);
 
         for m in matches {
             let line_num = Self::byte_to_line(&byte_pos_to_line_mapping, m.pos());
-            let parsed = std::str::from_utf8(m.data())?;
+            let parsed = match std::str::from_utf8(m.data()) {
+                Ok(parsed) => parsed,
+                Err(_) => {
+                    errors.push(Error::InvalidUtf8 {
+                        path: path.to_path_buf(),
+                        line: line_num as u32,
+                    });
+                    continue;
+                }
+            };
             match m {
-                LineMatch::OnChange(..) => {
+                LineMatch::OnChange(_, _, rest) => {
                     Self::handle_on_change(
                         path.clone(),
                         parsed,
+                        &rest.to_str_lossy(),
                         line_num,
                         &mut block_name_to_start_line,
                         &mut block_stack,
-                    )?;
+                        &mut errors,
+                    );
                 }
-                LineMatch::ThenChange(..) => {
-                    let block = Self::handle_then_change(
+                LineMatch::ThenChange(_, _, targets_pos) => {
+                    let line_start = byte_pos_to_line_mapping[line_num - 1].0;
+                    let column = buf[line_start..targets_pos].chars().count() + 1;
+                    if let Some(block) = Self::handle_then_change(
                         &path,
-                        root_path,
-                        &parsed,
+                        parsed,
                         line_num,
+                        column,
                         &mut block_stack,
-                    )?;
-                    blocks.push(block);
+                        &mut errors,
+                    ) {
+                        blocks.push(block);
+                    }
                 }
             }
         }
 
-        if block_stack.len() > 0 {
-            // We've hit EOF with an unclosed OnChange block.
-            let block = block_stack.last().unwrap();
-            return Err(anyhow::anyhow!(
-                "reached end of file {} while looking for ThenChange for block \"{}\" which started on line {}",
-                path.display(),
-                block.name(),
-                block.start_line,
-            ));
+        // We've hit EOF with unclosed OnChange block(s).
+        for block in block_stack.iter().rev() {
+            errors.push(Error::UnclosedBlock {
+                path: path.to_path_buf(),
+                line: block.start_line,
+                block: block.name().to_string(),
+            });
         }
 
-        Ok(blocks)
+        (blocks, errors)
     }
 
     fn filter_unchanged_blocks(blocks: Vec<OnChangeBlock>, hunks: &[Hunk]) -> Vec<OnChangeBlock> {
@@ -591,24 +1343,94 @@ impl File {
         path: PathBuf,
         root_path: P,
         hunks: Option<&[Hunk]>,
+        marker: &Marker,
     ) -> Result<Option<(Self, HashSet<PathBuf>)>> {
         let root_path = root_path.as_ref();
 
-        let mut blocks = Self::parse_internal(Arc::new(path.clone()), root_path.as_ref())?;
+        // Read the entire file into memory. Since we're mostly working with text files,
+        // this shouldn't be an issue.
+        let mut buf = Vec::new();
+        std::fs::File::open(root_path.join(path.as_path()))
+            .and_then(|mut f| f.read_to_end(&mut buf))
+            .map_err(|e| Error::io(&path, e))?;
+
+        Self::parse_from_bytes(path, &buf, hunks, marker, |p| root_path.join(p).exists())
+    }
+
+    /// Same as [File::parse], but uses the provided file content instead of reading
+    /// the file from disk. This is used when the content comes from a Git revision, in
+    /// which case `exists` must check whether a target file exists in that same revision.
+    pub fn parse_from_bytes(
+        path: PathBuf,
+        buf: &[u8],
+        hunks: Option<&[Hunk]>,
+        marker: &Marker,
+        exists: impl Fn(&Path) -> bool,
+    ) -> Result<Option<(Self, HashSet<PathBuf>)>> {
+        let (mut blocks, mut errors) = Self::parse_blocks(Arc::new(path.clone()), buf, marker);
+        errors.extend(Self::validate_target_files(&blocks, &exists));
 
         // If a set of hunks was provided, filter out blocks that have not been changed by a hunk.
         if let Some(hunks) = hunks {
             blocks = Self::filter_unchanged_blocks(blocks, hunks);
         }
 
-        let mut files_to_parse = HashSet::new();
+        let file = File {
+            path,
+            blocks,
+            errors,
+        };
+        // Missing target files have already been reported above.
+        let files_to_parse = file
+            .target_files()
+            .into_iter()
+            .filter(|p| exists(p))
+            .collect();
+
+        Ok(Some((file, files_to_parse)))
+    }
+
+    /// Returns an error for each ThenChange target file that does not exist.
+    fn validate_target_files(
+        blocks: &[OnChangeBlock],
+        exists: impl Fn(&Path) -> bool,
+    ) -> Vec<Error> {
+        let mut errors = Vec::new();
+        for block in blocks {
+            if let ThenChange::Targets(targets) = block.then_change() {
+                for target in targets {
+                    let Some(file_path) = target.file() else {
+                        continue;
+                    };
+                    if !exists(file_path) {
+                        errors.push(Error::MissingTargetFile {
+                            path: block.file().to_owned(),
+                            line: target.line(),
+                            block: block.name().to_string(),
+                            target_file: file_path.to_owned(),
+                        });
+                    }
+                }
+            }
+        }
+        errors
+    }
+
+    /// Takes the errors found while parsing this file.
+    pub(crate) fn take_errors(&mut self) -> Vec<Error> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Returns the set of files targeted by blocks in this file.
+    fn target_files(&self) -> HashSet<PathBuf> {
+        let mut files = HashSet::new();
 
-        for block in &blocks {
+        for block in &self.blocks {
             match block.then_change() {
                 ThenChange::Targets(targets) => {
                     for target in targets {
                         if let Some(file_path) = target.file() {
-                            files_to_parse.insert(file_path.to_owned());
+                            files.insert(file_path.to_owned());
                         }
                     }
                 }
@@ -617,6 +1439,37 @@ impl File {
             }
         }
 
-        Ok(Some((File { path, blocks }, files_to_parse)))
+        files
+    }
+
+    /// Returns the blocks in the old version of a file that no longer exist in the new version.
+    ///
+    /// A named block is removed if no block with the same name exists in the new version. Since
+    /// unnamed blocks cannot be matched by name, an unnamed block is considered removed if both of
+    /// its OnChange and ThenChange lines were removed by the given hunks.
+    pub fn find_removed_blocks(
+        old_blocks: Vec<OnChangeBlock>,
+        new_blocks: &[OnChangeBlock],
+        hunks: &[Hunk],
+    ) -> Vec<OnChangeBlock> {
+        let new_names: HashSet<&str> = new_blocks.iter().filter_map(|b| b.name_raw()).collect();
+        let removed_lines: HashSet<u32> = hunks
+            .iter()
+            .flat_map(|h| h.lines.iter())
+            .filter_map(|l| match *l {
+                Line::Remove(l) => Some(l),
+                _ => None,
+            })
+            .collect();
+
+        old_blocks
+            .into_iter()
+            .filter(|b| match b.name_raw() {
+                Some(name) => !new_names.contains(name),
+                None => {
+                    removed_lines.contains(&b.start_line) && removed_lines.contains(&b.end_line)
+                }
+            })
+            .collect()
     }
 }
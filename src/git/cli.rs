@@ -1,30 +1,106 @@
 use std::collections::BTreeMap;
+use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
 
 use anyhow::Result;
+use bstr::ByteSlice;
 use patch::Patch;
 
-use super::{Hunk, Line, Repo};
+use super::{patch_error, Changes, Commit, Hunk, Line, Repo};
 
-// Returns the names of non-deleted staged files.
-const STAGED_FILES_CMD: &[&str] = &[
+// Passed to every command, so that paths are printed as-is. By default, git quotes paths with
+// non-ASCII characters, e.g., "\303\251.txt".
+const CONFIG_ARGS: &[&str] = &["-c", "core.quotePath=false"];
+// Returns the names of non-deleted changed files.
+const CHANGED_FILES_CMD: &[&str] = &[
     // Disable the pager.
     "--no-pager",
     "diff",
-    "--cached",
     "--name-only",
+    // Detect renames regardless of the user's diff.renames config.
+    "--find-renames",
     // Render paths relative to pwd.
     "--relative",
     // Ignore deleted files.
     "--diff-filter=d",
 ];
-// Returns all staged hunks for non-deleted files.
-const STAGED_HUNKS_CMD: &[&str] = &[
+// Returns the names of deleted files.
+const DELETED_FILES_CMD: &[&str] = &[
     // Disable the pager.
     "--no-pager",
     "diff",
-    "--cached",
+    "--name-only",
+    // Detect renames regardless of the user's diff.renames config.
+    "--find-renames",
+    // Render paths relative to pwd.
+    "--relative",
+    // Only include deleted files.
+    "--diff-filter=D",
+];
+// Returns the status and old/new names of renamed files.
+const RENAMED_FILES_CMD: &[&str] = &[
+    // Disable the pager.
+    "--no-pager",
+    "diff",
+    "--name-status",
+    "--find-renames",
+    // Render paths relative to pwd.
+    "--relative",
+    // Only include renamed files.
+    "--diff-filter=R",
+];
+// Returns the names of files that contain a fixed string.
+const GREP_FILES_CMD: &[&str] = &[
+    // Disable the pager.
+    "--no-pager",
+    "grep",
+    // Only print file names.
+    "--files-with-matches",
+    // Treat patterns as fixed strings.
+    "--fixed-strings",
+    // Never match binary files.
+    "-I",
+];
+// Returns the names of all files in the index.
+const LIST_FILES_CMD: &[&str] = &[
+    // Disable the pager.
+    "--no-pager",
+    "ls-files",
+];
+// Returns the names of all files in a revision.
+const LIST_TREE_FILES_CMD: &[&str] = &[
+    // Disable the pager.
+    "--no-pager",
+    "ls-tree",
+    "-r",
+    "--name-only",
+];
+// Reads the objects passed on stdin, e.g., "HEAD:./a.txt".
+const CAT_FILE_CMD: &[&str] = &[
+    // Disable the pager.
+    "--no-pager",
+    "cat-file",
+    "--batch",
+];
+// Returns the ID, parent IDs and summary of commits, oldest first.
+const COMMITS_CMD: &[&str] = &[
+    // Disable the pager.
+    "--no-pager",
+    "log",
+    "--reverse",
+    "--topo-order",
+    // Fields and commits are separated by NUL.
+    "-z",
+    "--format=%H%x00%P%x00%s%x00%B",
+];
+// Returns all changed hunks for non-deleted files.
+const CHANGED_HUNKS_CMD: &[&str] = &[
+    // Disable the pager.
+    "--no-pager",
+    "diff",
+    // Detect renames regardless of the user's diff.renames config.
+    "--find-renames",
     // Render paths relative to pwd.
     "--relative",
     // Omits the path prefix for the old and new files (a/ and b/, respectively).
@@ -37,61 +113,221 @@ pub struct Cli<'a> {
     pub repo_path: &'a Path,
 }
 
-impl<'a> Repo for Cli<'a> {
-    fn get_staged_files(&self) -> Result<Vec<PathBuf>> {
-        let output = Command::new("git")
+impl<'a> Cli<'a> {
+    /// Returns the revision arguments to pass to "git diff" for the given changes.
+    fn diff_args(changes: &Changes) -> Result<Vec<String>> {
+        match changes {
+            Changes::Staged => Ok(vec!["--cached".to_string()]),
+            Changes::Range {
+                base,
+                head,
+                merge_base: true,
+            } => Ok(vec![format!("{}...{}", base, head)]),
+            Changes::Range {
+                base,
+                head,
+                merge_base: false,
+            } => Ok(vec![base.to_string(), head.to_string()]),
+            Changes::Patch => Err(patch_error()),
+        }
+    }
+
+    /// Returns the revision containing the new version of files for the given changes. An
+    /// empty revision refers to the index (i.e., the staged content).
+    fn new_rev(changes: &Changes) -> Result<&str> {
+        match changes {
+            Changes::Staged => Ok(""),
+            Changes::Range { head, .. } => Ok(head.as_str()),
+            Changes::Patch => Err(patch_error()),
+        }
+    }
+
+    /// Returns the revision containing the old version of files for the given changes.
+    fn old_rev(&self, changes: &Changes) -> Result<String> {
+        match changes {
+            Changes::Staged => Ok("HEAD".to_string()),
+            Changes::Range {
+                base,
+                merge_base: false,
+                ..
+            } => Ok(base.to_string()),
+            Changes::Range {
+                base,
+                head,
+                merge_base: true,
+            } => {
+                let stdout = self.run(&["merge-base"], &[base.to_string(), head.to_string()])?;
+                Ok(std::str::from_utf8(&stdout)?.trim().to_string())
+            }
+            Changes::Patch => Err(patch_error()),
+        }
+    }
+
+    /// Returns the commit ID of the given revision.
+    fn rev_parse(&self, rev: &str) -> Result<String> {
+        let stdout = self.run(&["rev-parse", "--verify"], &[format!("{}^{{commit}}", rev)])?;
+        Ok(std::str::from_utf8(&stdout)?.trim().to_string())
+    }
+
+    /// Reads the given files from a revision using a single "git cat-file" process. Files that
+    /// do not exist in the revision are omitted.
+    fn cat_files(&self, rev: &str, paths: &[PathBuf]) -> Result<BTreeMap<PathBuf, Vec<u8>>> {
+        let mut contents = BTreeMap::new();
+        if paths.is_empty() {
+            return Ok(contents);
+        }
+        // The "./" prefix makes each path relative to pwd, which matches the paths returned by
+        // "--relative" above.
+        let mut input = String::new();
+        for path in paths {
+            input.push_str(&format!("{}:./{}\n", rev, path.display()));
+        }
+        let mut child = Command::new("git")
             .current_dir(self.repo_path)
-            .args(STAGED_FILES_CMD)
-            .output()?;
-        let (stdout, stderr) = (
-            std::str::from_utf8(&output.stdout)?,
-            std::str::from_utf8(&output.stderr)?,
-        );
+            .args(CONFIG_ARGS)
+            .args(CAT_FILE_CMD)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // Write from another thread, since git stops reading once its stdout pipe is full.
+        let mut stdin = child.stdin.take().expect("stdin should be piped");
+        let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+        let output = child.wait_with_output()?;
+        writer.join().expect("writer thread should not panic")?;
         if !output.status.success() {
-            return Err(anyhow::anyhow!("git diff failed: {}", stderr));
+            return Err(anyhow::anyhow!(
+                "git cat-file failed: {}",
+                std::str::from_utf8(&output.stderr)?,
+            ));
+        }
+
+        // Each object is printed as "<id> <type> <size>\n<content>\n", or "<name> missing\n".
+        let mut rest = output.stdout.as_slice();
+        for path in paths {
+            let end = rest
+                .find_byte(b'\n')
+                .ok_or_else(|| anyhow::anyhow!("unexpected end of git cat-file output"))?;
+            let header = &rest[..end];
+            rest = &rest[end + 1..];
+            // The object name can contain spaces, so this is checked first.
+            if header.ends_with(b" missing") || header.ends_with(b" ambiguous") {
+                continue;
+            }
+            let header: Vec<&[u8]> = header.split_str(" ").collect();
+            let [_, kind, size] = header.as_slice() else {
+                return Err(anyhow::anyhow!("unexpected git cat-file output"));
+            };
+            let size: usize = std::str::from_utf8(size)?.parse()?;
+            if rest.len() <= size {
+                return Err(anyhow::anyhow!("unexpected end of git cat-file output"));
+            }
+            if *kind == b"blob" {
+                contents.insert(path.to_owned(), rest[..size].to_vec());
+            }
+            rest = &rest[size + 1..];
         }
+        Ok(contents)
+    }
+
+    fn parse_paths(stdout: &[u8]) -> Result<Vec<PathBuf>> {
+        let stdout = std::str::from_utf8(stdout)?;
 
         let mut paths = Vec::new();
-        for p in stdout.split("\n") {
+        for p in stdout.split('\n') {
             let p = p.trim();
             if p.is_empty() {
                 continue;
             }
             let p = Path::new(p);
-            let absolute = self.repo_path.join(p).canonicalize()?;
-            if !absolute.exists() {
-                return Err(anyhow::anyhow!(
-                    "file {} does not exist",
-                    absolute.display()
-                ));
-            }
             paths.push(p.to_owned());
         }
 
         Ok(paths)
     }
 
-    fn get_staged_hunks(&self) -> Result<BTreeMap<PathBuf, Vec<Hunk>>> {
+    /// Removes file diffs that have no content hunks (e.g., pure renames, mode changes, and
+    /// binary files). The patch crate cannot parse these since they have no ---/+++ header.
+    /// Submodules are removed as well, since their "hunks" are commit IDs, not file content.
+    fn strip_diffs_without_hunks(stdout: &str) -> String {
+        let mut sections: Vec<&str> = Vec::new();
+        let mut start = None;
+        for (pos, _) in stdout.match_indices("diff --git ") {
+            if pos != 0 && !stdout[..pos].ends_with('\n') {
+                continue;
+            }
+            if let Some(start) = start {
+                sections.push(&stdout[start..pos]);
+            }
+            start = Some(pos);
+        }
+        if let Some(start) = start {
+            sections.push(&stdout[start..]);
+        }
+
+        sections
+            .into_iter()
+            .filter(|section| {
+                // Only look at the header, i.e., everything before the first hunk.
+                let mut header = section.lines().take_while(|l| !l.starts_with("@@"));
+                header.clone().any(|l| l.starts_with("--- "))
+                    && !header.any(|l| l.ends_with(" 160000"))
+            })
+            .collect()
+    }
+
+    /// Runs a git command in the repo and returns its stdout.
+    fn run(&self, args: &[&str], extra_args: &[String]) -> Result<Vec<u8>> {
         let output = Command::new("git")
             .current_dir(self.repo_path)
-            .args(STAGED_HUNKS_CMD)
+            .args(CONFIG_ARGS)
+            .args(args)
+            .args(extra_args)
             .output()?;
-        let (raw_stdout, raw_stderr) = (output.stdout, output.stderr);
-        let (stdout, stderr) = (
-            std::str::from_utf8(&raw_stdout)?,
-            std::str::from_utf8(&raw_stderr)?,
-        );
         if !output.status.success() {
-            return Err(anyhow::anyhow!("git diff failed: {}", stderr));
+            return Err(anyhow::anyhow!(
+                "git {} failed: {}",
+                args.iter().find(|a| !a.starts_with('-')).unwrap_or(&""),
+                std::str::from_utf8(&output.stderr)?,
+            ));
         }
+        Ok(output.stdout)
+    }
+}
+
+impl<'a> Repo for Cli<'a> {
+    fn resolve(&self, changes: &Changes) -> Result<Changes> {
+        match changes {
+            Changes::Staged => Ok(Changes::Staged),
+            Changes::Range { head, .. } => Ok(Changes::Range {
+                base: self.old_rev(changes)?,
+                head: self.rev_parse(head)?,
+                merge_base: false,
+            }),
+            Changes::Patch => Err(patch_error()),
+        }
+    }
+
+    fn get_changed_files(&self, changes: &Changes) -> Result<Vec<PathBuf>> {
+        let raw_stdout = self.run(CHANGED_FILES_CMD, &Self::diff_args(changes)?)?;
+        Self::parse_paths(&raw_stdout)
+    }
+
+    fn get_changed_hunks(&self, changes: &Changes) -> Result<BTreeMap<PathBuf, Vec<Hunk>>> {
+        let raw_stdout = self.run(CHANGED_HUNKS_CMD, &Self::diff_args(changes)?)?;
+        let stdout = std::str::from_utf8(&raw_stdout)?;
         let mut hunk_map: BTreeMap<PathBuf, Vec<Hunk>> = BTreeMap::new();
 
         if stdout.trim().is_empty() {
             return Ok(hunk_map);
         }
 
-        // TODO(aksiksi): Handle deleted files, binary files, etc.
-        let patch = Patch::from_multiple(stdout).map_err(|e| anyhow::anyhow!("{}", e))?;
+        // TODO(aksiksi): Handle deleted files.
+        let stdout = Self::strip_diffs_without_hunks(stdout);
+        if stdout.is_empty() {
+            return Ok(hunk_map);
+        }
+        let patch = Patch::from_multiple(&stdout).map_err(|e| anyhow::anyhow!("{}", e))?;
         for diff_file in patch {
             // We only look at the new file.
             let path = PathBuf::from(diff_file.new.path.as_ref());
@@ -101,6 +337,144 @@ impl<'a> Repo for Cli<'a> {
 
         Ok(hunk_map)
     }
+
+    fn get_file_contents(
+        &self,
+        changes: &Changes,
+        paths: &[PathBuf],
+    ) -> Result<BTreeMap<PathBuf, Vec<u8>>> {
+        self.cat_files(Self::new_rev(changes)?, paths)
+    }
+
+    fn get_deleted_files(&self, changes: &Changes) -> Result<Vec<PathBuf>> {
+        let raw_stdout = self.run(DELETED_FILES_CMD, &Self::diff_args(changes)?)?;
+        Self::parse_paths(&raw_stdout)
+    }
+
+    fn get_renamed_files(&self, changes: &Changes) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let raw_stdout = self.run(RENAMED_FILES_CMD, &Self::diff_args(changes)?)?;
+        let stdout = std::str::from_utf8(&raw_stdout)?;
+
+        let mut renames = Vec::new();
+        for line in stdout.lines() {
+            // Each line looks like: R<similarity>\t<old path>\t<new path>
+            let parts: Vec<&str> = line.split('\t').collect();
+            if let [_, old, new] = parts.as_slice() {
+                renames.push((PathBuf::from(old), PathBuf::from(new)));
+            }
+        }
+
+        Ok(renames)
+    }
+
+    fn get_old_file_contents(
+        &self,
+        changes: &Changes,
+        paths: &[PathBuf],
+    ) -> Result<BTreeMap<PathBuf, Vec<u8>>> {
+        // The old revision does not exist in an empty repo, in which case every file is new.
+        self.cat_files(&self.old_rev(changes)?, paths)
+    }
+
+    fn find_files_containing(&self, changes: &Changes, needles: &[String]) -> Result<Vec<PathBuf>> {
+        if needles.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut args = Vec::new();
+        for needle in needles {
+            args.push("-e".to_string());
+            args.push(needle.to_string());
+        }
+        let rev_prefix = match changes {
+            Changes::Staged => {
+                args.push("--cached".to_string());
+                None
+            }
+            Changes::Range { head, .. } => {
+                args.push(head.to_string());
+                // Matches in a revision are printed as "<rev>:<path>".
+                Some(format!("{}:", head))
+            }
+            Changes::Patch => return Err(patch_error()),
+        };
+        let output = Command::new("git")
+            .current_dir(self.repo_path)
+            .args(CONFIG_ARGS)
+            .args(GREP_FILES_CMD)
+            .args(&args)
+            .output()?;
+        // git grep exits with 1 if there are no matches.
+        match output.status.code() {
+            Some(0) => (),
+            Some(1) => return Ok(Vec::new()),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "git grep failed: {}",
+                    std::str::from_utf8(&output.stderr)?
+                ))
+            }
+        }
+        let stdout = std::str::from_utf8(&output.stdout)?;
+        let stdout = match rev_prefix {
+            Some(prefix) => stdout
+                .lines()
+                .map(|l| l.strip_prefix(&prefix).unwrap_or(l))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            None => stdout.to_string(),
+        };
+        Self::parse_paths(stdout.as_bytes())
+    }
+
+    fn list_files(&self, changes: &Changes) -> Result<Vec<PathBuf>> {
+        let stdout = match changes {
+            Changes::Staged => self.run(LIST_FILES_CMD, &[])?,
+            // Make sure that the revision is never treated as a path.
+            Changes::Range { head, .. } => {
+                self.run(LIST_TREE_FILES_CMD, &[head.to_string(), "--".to_string()])?
+            }
+            Changes::Patch => return Err(patch_error()),
+        };
+        Self::parse_paths(&stdout)
+    }
+
+    fn get_commits(&self, head: &str, base: Option<&str>) -> Result<Vec<Commit>> {
+        let mut args = vec![head.to_string()];
+        match base {
+            Some(base) => args.push(format!("^{}", base)),
+            None => args.extend(["--not".to_string(), "--remotes".to_string()]),
+        }
+        // Make sure that revisions are never treated as paths.
+        args.push("--".to_string());
+        let raw_stdout = self.run(COMMITS_CMD, &args)?;
+        let stdout = std::str::from_utf8(&raw_stdout)?;
+
+        let mut commits = Vec::new();
+        let fields: Vec<&str> = stdout.split('\0').collect();
+        for commit in fields.chunks_exact(4) {
+            if let [id, parents, summary, message] = commit {
+                commits.push(Commit {
+                    id: id.trim().to_string(),
+                    summary: summary.to_string(),
+                    message: message.to_string(),
+                    root: parents.trim().is_empty(),
+                });
+            }
+        }
+
+        Ok(commits)
+    }
+
+    fn has_commit(&self, id: &str) -> Result<bool> {
+        // Exits with 1 (without printing anything) if the object does not exist.
+        let status = Command::new("git")
+            .current_dir(self.repo_path)
+            .args(CONFIG_ARGS)
+            .args(["cat-file", "-e", &format!("{}^{{commit}}", id)])
+            .stderr(Stdio::null())
+            .status()?;
+        Ok(status.success())
+    }
 }
 
 impl From<&patch::Hunk<'_>> for Hunk {
@@ -111,7 +485,8 @@ impl From<&patch::Hunk<'_>> for Hunk {
         let mut num_context = 0;
         let (start_line, end_line) = (
             h.new_range.start as u32,
-            (h.new_range.start + h.new_range.count - 1) as u32,
+            // The count is 0 if all lines were removed from the file.
+            (h.new_range.start + h.new_range.count).saturating_sub(1) as u32,
         );
         let old_start_line = h.old_range.start as u32;
         for line in &h.lines {
@@ -1,17 +1,24 @@
+use std::cell::RefCell;
 use std::collections::{BTreeMap, HashMap};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 use anyhow::Result;
-use git2::{Delta, DiffHunk, DiffLine, Repository, StatusOptions};
+use bstr::ByteSlice;
+use git2::{
+    Delta, Diff, DiffFindOptions, DiffHunk, DiffLine, ObjectType, Oid, Repository, Sort, Tree,
+    TreeWalkMode, TreeWalkResult,
+};
 
-use super::{Hunk, Line, Repo};
+use super::{patch_error, Changes, Commit, Hunk, Line, Repo};
 
 impl From<DiffHunk<'_>> for Hunk {
     fn from(h: DiffHunk<'_>) -> Self {
         Self {
             lines: Vec::new(),
             start_line: h.new_start(),
-            end_line: h.new_start() + h.new_lines() - 1,
+            // The count is 0 if all lines were removed from the file.
+            end_line: (h.new_start() + h.new_lines()).saturating_sub(1),
         }
     }
 }
@@ -27,28 +34,107 @@ impl From<DiffLine<'_>> for Line {
     }
 }
 
-impl Repo for Repository {
-    fn get_staged_files(&self) -> Result<Vec<PathBuf>> {
-        let mut opts = StatusOptions::new();
-        let mut paths = Vec::new();
-        for entry in self
-            .statuses(Some(opts.show(git2::StatusShow::Index)))?
-            .iter()
-        {
-            match entry.status() {
-                // We only care about modified and new files.
-                git2::Status::INDEX_NEW | git2::Status::INDEX_MODIFIED => (),
-                _ => continue,
+/// Returns the (base, head) trees for a revision range.
+fn range_trees<'r>(
+    repo: &'r Repository,
+    base: &str,
+    head: &str,
+    merge_base: bool,
+) -> Result<(Tree<'r>, Tree<'r>)> {
+    let head_commit = repo.revparse_single(head)?.peel_to_commit()?;
+    let mut base_commit = repo.revparse_single(base)?.peel_to_commit()?;
+    if merge_base {
+        let oid = repo.merge_base(base_commit.id(), head_commit.id())?;
+        base_commit = repo.find_commit(oid)?;
+    }
+    Ok((base_commit.tree()?, head_commit.tree()?))
+}
+
+/// Returns the tree containing the old version of files, or None if HEAD is unborn.
+fn old_tree<'r>(repo: &'r Repository, changes: &Changes) -> Result<Option<Tree<'r>>> {
+    match changes {
+        Changes::Staged => match repo.head() {
+            Ok(head) => Ok(Some(head.peel_to_tree()?)),
+            Err(e) if e.code() == git2::ErrorCode::UnbornBranch => Ok(None),
+            Err(e) => Err(e.into()),
+        },
+        Changes::Range {
+            base,
+            head,
+            merge_base,
+        } => Ok(Some(range_trees(repo, base, head, *merge_base)?.0)),
+        Changes::Patch => Err(patch_error()),
+    }
+}
+
+fn diff_for_changes<'r>(repo: &'r Repository, changes: &Changes) -> Result<Diff<'r>> {
+    let mut diff = match changes {
+        Changes::Staged => {
+            let s = std::time::Instant::now();
+            let tree = old_tree(repo, changes)?;
+            log::info!("Got tree in {:?}", s.elapsed());
+
+            let s = std::time::Instant::now();
+            let diff = repo.diff_tree_to_index(tree.as_ref(), None, None)?;
+            log::info!("Diff tree to index in {:?}", s.elapsed());
+            diff
+        }
+        Changes::Range {
+            base,
+            head,
+            merge_base,
+        } => {
+            let (base_tree, head_tree) = range_trees(repo, base, head, *merge_base)?;
+            let s = std::time::Instant::now();
+            let diff = repo.diff_tree_to_tree(Some(&base_tree), Some(&head_tree), None)?;
+            log::info!("Diff tree to tree in {:?}", s.elapsed());
+            diff
+        }
+        Changes::Patch => return Err(patch_error()),
+    };
+    // Detect renames so that a moved file does not show up as a delete + add.
+    diff.find_similar(Some(DiffFindOptions::new().renames(true)))?;
+    Ok(diff)
+}
+
+/// Returns the (path, blob ID) of every file on the new side of the changes.
+fn new_side_blobs(repo: &Repository, changes: &Changes) -> Result<Vec<(PathBuf, Oid)>> {
+    let mut blobs: Vec<(PathBuf, Oid)> = Vec::new();
+    match changes {
+        Changes::Staged => {
+            for entry in repo.index()?.iter() {
+                let path = PathBuf::from(std::str::from_utf8(&entry.path)?);
+                blobs.push((path, entry.id));
             }
-            let file_path = match entry.path() {
-                Some(p) => p,
-                None => continue,
-            };
-            paths.push(PathBuf::from(file_path));
         }
-        Ok(paths)
+        Changes::Range { head, .. } => {
+            let tree = repo.revparse_single(head)?.peel_to_tree()?;
+            tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+                if entry.kind() == Some(ObjectType::Blob) {
+                    if let Some(name) = entry.name() {
+                        blobs.push((Path::new(root).join(name), entry.id()));
+                    }
+                }
+                TreeWalkResult::Ok
+            })?;
+        }
+        Changes::Patch => return Err(patch_error()),
     }
+    Ok(blobs)
+}
 
+/// Everything that is needed from the diff of a set of changes.
+#[derive(Debug, Default)]
+struct DiffSummary {
+    /// Added, modified and renamed files (new paths).
+    changed_files: Vec<PathBuf>,
+    deleted_files: Vec<PathBuf>,
+    /// (old, new) paths of renamed files.
+    renamed_files: Vec<(PathBuf, PathBuf)>,
+    hunks: BTreeMap<PathBuf, Vec<Hunk>>,
+}
+
+impl DiffSummary {
     // NOTE(aksiksi): This is 2x slower than the CLI-based diff.
     //
     // For the "repo" benchmark, which consists of ~2300 diff lines, the CLI takes ~250ms,
@@ -60,23 +146,28 @@ impl Repo for Repository {
     //
     // But is there even another way to get hunk content? Based on the API, using the line_cb is
     // the only way to see diff content.
-    fn get_staged_hunks(&self) -> Result<BTreeMap<PathBuf, Vec<Hunk>>> {
-        // Sanity check, but also kind of required because the methods below
-        // fail on an empty repo.
-        if self.get_staged_files()?.len() == 0 {
-            return Ok(BTreeMap::new());
+    fn new(diff: &Diff) -> Result<Self> {
+        let mut summary = Self::default();
+        for delta in diff.deltas() {
+            let (old, new) = (delta.old_file().path(), delta.new_file().path());
+            match delta.status() {
+                // We only care about modified, new and renamed files.
+                Delta::Added | Delta::Modified => {
+                    summary.changed_files.extend(new.map(Path::to_owned))
+                }
+                Delta::Renamed => {
+                    summary.changed_files.extend(new.map(Path::to_owned));
+                    if let (Some(old), Some(new)) = (old, new) {
+                        summary.renamed_files.push((old.to_owned(), new.to_owned()));
+                    }
+                }
+                Delta::Deleted => summary.deleted_files.extend(old.map(Path::to_owned)),
+                _ => (),
+            }
         }
 
         let mut hunk_map: BTreeMap<PathBuf, HashMap<(u32, u32), Hunk>> = BTreeMap::new();
 
-        let s = std::time::Instant::now();
-        let tree = self.head()?.peel_to_tree()?;
-        log::info!("Got tree in {:?}", s.elapsed());
-
-        let s = std::time::Instant::now();
-        let diff = self.diff_tree_to_index(Some(&tree), None, None)?;
-        log::info!("Diff tree to index in {:?}", s.elapsed());
-
         let s = std::time::Instant::now();
         let mut num_lines = 0;
 
@@ -90,11 +181,10 @@ impl Repo for Repository {
                     return true;
                 }
                 let raw_hunk = raw_hunk.unwrap();
-                let valid = if let Delta::Added | Delta::Modified = delta.status() {
-                    true
-                } else {
-                    false
-                };
+                let valid = matches!(
+                    delta.status(),
+                    Delta::Added | Delta::Modified | Delta::Renamed
+                );
                 if !valid {
                     return true;
                 }
@@ -116,9 +206,7 @@ impl Repo for Repository {
                     hunk_map.insert(file_path.clone(), HashMap::new());
                 }
                 let file_map = hunk_map.get_mut(&file_path).unwrap();
-                if !file_map.contains_key(&(start_line, end_line)) {
-                    file_map.insert((start_line, end_line), this_hunk);
-                }
+                file_map.entry((start_line, end_line)).or_insert(this_hunk);
 
                 file_map
                     .get_mut(&(start_line, end_line))
@@ -138,11 +226,195 @@ impl Repo for Repository {
 
         log::info!("Processed {} lines in {:?}", num_lines, s.elapsed());
 
-        let hunk_map = hunk_map
+        summary.hunks = hunk_map
             .into_iter()
             .map(|(k, v)| (k, v.into_values().collect()))
             .collect();
+        Ok(summary)
+    }
+}
+
+/// A repo backed by libgit2.
+///
+/// Computing a diff is expensive, and most methods need one, so the summary of the diff of
+/// the last set of changes is cached.
+pub struct Git2Repo {
+    repo: Repository,
+    diff: RefCell<Option<(Changes, Rc<DiffSummary>)>>,
+}
+
+impl Git2Repo {
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            repo: Repository::open(path)?,
+            diff: RefCell::new(None),
+        })
+    }
+
+    fn diff(&self, changes: &Changes) -> Result<Rc<DiffSummary>> {
+        if let Some((cached, summary)) = self.diff.borrow().as_ref() {
+            if cached == changes {
+                return Ok(summary.clone());
+            }
+        }
+        let summary = Rc::new(DiffSummary::new(&diff_for_changes(&self.repo, changes)?)?);
+        *self.diff.borrow_mut() = Some((changes.clone(), summary.clone()));
+        Ok(summary)
+    }
+
+    /// Reads the given files from a tree, or the index if the tree is None. Files that do not
+    /// exist are omitted.
+    fn read_files(
+        &self,
+        tree: Option<&Tree>,
+        paths: &[PathBuf],
+    ) -> Result<BTreeMap<PathBuf, Vec<u8>>> {
+        let index = match tree {
+            Some(_) => None,
+            None => Some(self.repo.index()?),
+        };
+        let mut contents = BTreeMap::new();
+        for path in paths {
+            let id = match (tree, &index) {
+                (Some(tree), _) => match tree.get_path(path) {
+                    Ok(entry) if entry.kind() == Some(ObjectType::Blob) => entry.id(),
+                    Ok(_) => continue,
+                    Err(e) if e.code() == git2::ErrorCode::NotFound => continue,
+                    Err(e) => return Err(e.into()),
+                },
+                // Stage 0 is the normal (i.e., non-conflicted) index entry.
+                (None, Some(index)) => match index.get_path(path, 0) {
+                    Some(entry) => entry.id,
+                    None => continue,
+                },
+                (None, None) => unreachable!(),
+            };
+            contents.insert(path.to_owned(), self.repo.find_blob(id)?.content().to_vec());
+        }
+        Ok(contents)
+    }
+}
+
+impl Repo for Git2Repo {
+    fn resolve(&self, changes: &Changes) -> Result<Changes> {
+        match changes {
+            Changes::Staged => Ok(Changes::Staged),
+            Changes::Range {
+                base,
+                head,
+                merge_base,
+            } => {
+                let head = self.repo.revparse_single(head)?.peel_to_commit()?.id();
+                let mut base = self.repo.revparse_single(base)?.peel_to_commit()?.id();
+                if *merge_base {
+                    base = self.repo.merge_base(base, head)?;
+                }
+                Ok(Changes::Range {
+                    base: base.to_string(),
+                    head: head.to_string(),
+                    merge_base: false,
+                })
+            }
+            Changes::Patch => Err(patch_error()),
+        }
+    }
+
+    fn get_changed_files(&self, changes: &Changes) -> Result<Vec<PathBuf>> {
+        Ok(self.diff(changes)?.changed_files.clone())
+    }
+
+    fn get_changed_hunks(&self, changes: &Changes) -> Result<BTreeMap<PathBuf, Vec<Hunk>>> {
+        Ok(self.diff(changes)?.hunks.clone())
+    }
+
+    fn get_file_contents(
+        &self,
+        changes: &Changes,
+        paths: &[PathBuf],
+    ) -> Result<BTreeMap<PathBuf, Vec<u8>>> {
+        match changes {
+            Changes::Staged => self.read_files(None, paths),
+            Changes::Range { head, .. } => {
+                let head_tree = self.repo.revparse_single(head)?.peel_to_tree()?;
+                self.read_files(Some(&head_tree), paths)
+            }
+            Changes::Patch => Err(patch_error()),
+        }
+    }
+
+    fn get_deleted_files(&self, changes: &Changes) -> Result<Vec<PathBuf>> {
+        Ok(self.diff(changes)?.deleted_files.clone())
+    }
+
+    fn get_renamed_files(&self, changes: &Changes) -> Result<Vec<(PathBuf, PathBuf)>> {
+        Ok(self.diff(changes)?.renamed_files.clone())
+    }
+
+    fn get_old_file_contents(
+        &self,
+        changes: &Changes,
+        paths: &[PathBuf],
+    ) -> Result<BTreeMap<PathBuf, Vec<u8>>> {
+        match old_tree(&self.repo, changes)? {
+            Some(tree) => self.read_files(Some(&tree), paths),
+            // HEAD is unborn, so every file is new.
+            None => Ok(BTreeMap::new()),
+        }
+    }
+
+    fn find_files_containing(&self, changes: &Changes, needles: &[String]) -> Result<Vec<PathBuf>> {
+        if needles.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut paths = Vec::new();
+        for (path, id) in new_side_blobs(&self.repo, changes)? {
+            let blob = self.repo.find_blob(id)?;
+            if blob.is_binary() {
+                continue;
+            }
+            let content = blob.content();
+            if needles.iter().any(|n| content.find(n.as_bytes()).is_some()) {
+                paths.push(path);
+            }
+        }
+        Ok(paths)
+    }
+
+    fn list_files(&self, changes: &Changes) -> Result<Vec<PathBuf>> {
+        Ok(new_side_blobs(&self.repo, changes)?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect())
+    }
+
+    fn get_commits(&self, head: &str, base: Option<&str>) -> Result<Vec<Commit>> {
+        let mut walk = self.repo.revwalk()?;
+        walk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+        walk.push(self.repo.revparse_single(head)?.peel_to_commit()?.id())?;
+        match base {
+            Some(base) => walk.hide(self.repo.revparse_single(base)?.peel_to_commit()?.id())?,
+            None => walk.hide_glob("refs/remotes/*")?,
+        }
 
-        Ok(hunk_map)
+        let mut commits = Vec::new();
+        for id in walk {
+            let commit = self.repo.find_commit(id?)?;
+            commits.push(Commit {
+                id: commit.id().to_string(),
+                summary: commit.summary().unwrap_or_default().to_string(),
+                message: commit.message().unwrap_or_default().to_string(),
+                root: commit.parent_count() == 0,
+            });
+        }
+        Ok(commits)
+    }
+
+    fn has_commit(&self, id: &str) -> Result<bool> {
+        match self.repo.revparse_single(&format!("{}^{{commit}}", id)) {
+            Ok(_) => Ok(true),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
     }
 }
@@ -1,19 +1,141 @@
 use std::collections::BTreeMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 
+// The CLI backend is unused when libgit2 is enabled.
+#[cfg_attr(feature = "git", allow(dead_code))]
 pub mod cli;
 #[cfg(feature = "git")]
 mod lib;
+mod unified;
+
+pub use unified::UnifiedDiff;
+
+/// The set of changes in a repo that should be validated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Changes {
+    /// Changes staged in the index relative to HEAD.
+    Staged,
+    /// Changes between two revisions.
+    Range {
+        base: String,
+        head: String,
+        /// If set, the diff is computed from the merge-base of base and head.
+        merge_base: bool,
+    },
+    /// Changes described by a patch, which only a [UnifiedDiff] can read.
+    Patch,
+}
+
+/// Returns the error for reading the changes in a patch from a Git repo.
+fn patch_error() -> anyhow::Error {
+    anyhow::anyhow!("the changes in a patch can only be read from the patch itself")
+}
+
+impl Changes {
+    /// Parses a revision range into a set of changes.
+    ///
+    /// Supported forms (mirroring `git diff`):
+    ///
+    /// 1. `<base>..<head>`: Changes between base and head
+    /// 2. `<base>...<head>`: Changes between the merge-base of base and head, and head
+    /// 3. `<base>`: Same as `<base>...HEAD`
+    ///
+    /// An empty base or head defaults to HEAD.
+    pub fn from_range(range: &str) -> Result<Self> {
+        let range = range.trim();
+        if range.is_empty() {
+            return Err(anyhow::anyhow!("revision range cannot be empty"));
+        }
+        let (base, head, merge_base) = if let Some((base, head)) = range.split_once("...") {
+            (base, head, true)
+        } else if let Some((base, head)) = range.split_once("..") {
+            (base, head, false)
+        } else {
+            (range, "HEAD", true)
+        };
+        let (base, head) = (base.trim(), head.trim());
+        if base.is_empty() && head.is_empty() {
+            return Err(anyhow::anyhow!("invalid revision range \"{}\"", range));
+        }
+        let or_head = |r: &str| {
+            if r.is_empty() {
+                "HEAD".to_string()
+            } else {
+                r.to_string()
+            }
+        };
+        Ok(Self::Range {
+            base: or_head(base),
+            head: or_head(head),
+            merge_base,
+        })
+    }
+}
+
+/// A single commit in the repo.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Commit {
+    /// Full object ID of the commit.
+    pub id: String,
+    /// First line of the commit message.
+    pub summary: String,
+    /// Full commit message.
+    pub message: String,
+    /// True if the commit has no parents.
+    pub root: bool,
+}
+
+/// Opens the repo at the given path using the configured Git backend.
+pub fn open(path: &Path) -> Result<impl Repo + '_> {
+    #[cfg(feature = "git")]
+    let repo = lib::Git2Repo::open(path)?;
+    #[cfg(not(feature = "git"))]
+    let repo = cli::Cli { repo_path: path };
+    Ok(repo)
+}
 
 pub trait Repo {
-    fn get_staged_files(&self) -> Result<Vec<PathBuf>>;
+    /// Resolves the revisions of a range to commit IDs. If the range is diffed from the
+    /// merge-base, the merge-base is computed here and used as the base, so that it is only
+    /// computed once.
+    fn resolve(&self, changes: &Changes) -> Result<Changes>;
+    fn get_changed_files(&self, changes: &Changes) -> Result<Vec<PathBuf>>;
     // NOTE: We could optimize by having it accept a list of files to check.
-    fn get_staged_hunks(&self) -> Result<BTreeMap<PathBuf, Vec<Hunk>>>;
+    fn get_changed_hunks(&self, changes: &Changes) -> Result<BTreeMap<PathBuf, Vec<Hunk>>>;
+    /// Returns the content of each of the given files as of the "new" side of the changes,
+    /// i.e., the index for staged changes and the head revision for a range. Hunks always
+    /// describe this version of a file, so block lines must be computed from it too. Files
+    /// that do not exist are omitted.
+    fn get_file_contents(
+        &self,
+        changes: &Changes,
+        paths: &[PathBuf],
+    ) -> Result<BTreeMap<PathBuf, Vec<u8>>>;
+    fn get_deleted_files(&self, changes: &Changes) -> Result<Vec<PathBuf>>;
+    /// Returns the (old, new) paths of renamed files.
+    fn get_renamed_files(&self, changes: &Changes) -> Result<Vec<(PathBuf, PathBuf)>>;
+    /// Returns the content of each of the given files as of the "old" side of the changes,
+    /// i.e., HEAD for staged changes and the base revision (or merge-base) for a range. Files
+    /// that did not exist are omitted.
+    fn get_old_file_contents(
+        &self,
+        changes: &Changes,
+        paths: &[PathBuf],
+    ) -> Result<BTreeMap<PathBuf, Vec<u8>>>;
+    /// Returns all files on the "new" side of the changes that contain any of the given strings.
+    fn find_files_containing(&self, changes: &Changes, needles: &[String]) -> Result<Vec<PathBuf>>;
+    /// Returns all files on the "new" side of the changes.
+    fn list_files(&self, changes: &Changes) -> Result<Vec<PathBuf>>;
+    /// Returns the commits reachable from head but not from base, oldest first. If base is
+    /// None, commits reachable from any remote-tracking branch are excluded instead.
+    fn get_commits(&self, head: &str, base: Option<&str>) -> Result<Vec<Commit>>;
+    /// Returns true if the given commit is in the local object store.
+    fn has_commit(&self, id: &str) -> Result<bool>;
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Hunk {
     /// Start line of this hunk in the _new_ file.
     pub start_line: u32,
@@ -23,7 +145,7 @@ pub struct Hunk {
     pub lines: Vec<Line>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Line {
     /// Added line number (in _new_ file).
     Add(u32),
@@ -32,3 +154,35 @@ pub enum Line {
     /// Context line number (old, new).
     Context(u32, u32),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_changes_from_range() {
+        let range = |base: &str, head: &str, merge_base| Changes::Range {
+            base: base.to_string(),
+            head: head.to_string(),
+            merge_base,
+        };
+        assert_eq!(
+            Changes::from_range("main..feature").unwrap(),
+            range("main", "feature", false)
+        );
+        assert_eq!(
+            Changes::from_range("main...feature").unwrap(),
+            range("main", "feature", true)
+        );
+        assert_eq!(
+            Changes::from_range("main").unwrap(),
+            range("main", "HEAD", true)
+        );
+        assert_eq!(
+            Changes::from_range("HEAD~2..").unwrap(),
+            range("HEAD~2", "HEAD", false)
+        );
+        assert!(Changes::from_range("..").is_err());
+        assert!(Changes::from_range("").is_err());
+    }
+}
@@ -0,0 +1,413 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use bstr::ByteSlice;
+use patch::Patch;
+
+use super::{Changes, Commit, Hunk, Repo};
+use crate::Error;
+
+/// Path used by unified diffs for the missing side of an added or deleted file.
+const DEV_NULL: &str = "/dev/null";
+
+/// The old side of a hunk, used to reconstruct the old version of a file.
+#[derive(Debug)]
+struct OldLines {
+    /// Start line of the hunk in the _new_ file.
+    new_start: usize,
+    /// Number of lines covered by the hunk in the _new_ file.
+    new_count: usize,
+    /// Context and removed lines of the hunk.
+    lines: Vec<String>,
+}
+
+#[derive(Debug)]
+struct FileDiff {
+    /// None if the file was added.
+    old_path: Option<PathBuf>,
+    /// None if the file was deleted.
+    new_path: Option<PathBuf>,
+    hunks: Vec<Hunk>,
+    old_lines: Vec<OldLines>,
+}
+
+/// A set of changes described by a unified diff, with file content read from disk.
+///
+/// This allows changes to be validated without access to the VCS they came from. Since a
+/// diff describes exactly one set of changes, the [Changes] passed to each method are ignored.
+#[derive(Debug)]
+pub struct UnifiedDiff<'a> {
+    root_path: &'a Path,
+    files: Vec<FileDiff>,
+}
+
+/// Splits a Git diff into one section per file. Returns None if this is not a Git diff.
+fn split_git_diff(input: &str) -> Option<Vec<&str>> {
+    let mut sections: Vec<&str> = Vec::new();
+    let mut start = None;
+    for (pos, _) in input.match_indices("diff --git ") {
+        if pos != 0 && !input[..pos].ends_with('\n') {
+            continue;
+        }
+        if let Some(start) = start {
+            sections.push(&input[start..pos]);
+        }
+        start = Some(pos);
+    }
+    sections.push(&input[start?..]);
+    Some(sections)
+}
+
+/// Converts a path in the diff into a path relative to the root, stripping the given Git
+/// prefix (if any). Returns None for /dev/null.
+fn diff_path(path: &str, prefix: Option<&str>) -> Option<PathBuf> {
+    if path == DEV_NULL {
+        return None;
+    }
+    let path = prefix.and_then(|p| path.strip_prefix(p)).unwrap_or(path);
+    Some(PathBuf::from(path))
+}
+
+impl<'a> UnifiedDiff<'a> {
+    /// Parses a unified diff. Paths in the diff are relative to the root path.
+    pub fn parse(root_path: &'a Path, input: &str) -> Result<Self> {
+        let mut files = Vec::new();
+        let sections = split_git_diff(input).unwrap_or_else(|| vec![input]);
+        for section in sections {
+            // Only look at the header, i.e., everything before the first hunk.
+            let header = section.lines().take_while(|l| !l.starts_with("@@"));
+            let (mut rename_from, mut rename_to, mut has_hunks) = (None, None, false);
+            for line in header {
+                if let Some(p) = line.strip_prefix("rename from ") {
+                    rename_from = Some(PathBuf::from(p));
+                } else if let Some(p) = line.strip_prefix("rename to ") {
+                    rename_to = Some(PathBuf::from(p));
+                } else if line.starts_with("--- ") {
+                    has_hunks = true;
+                }
+            }
+            if !has_hunks {
+                // The patch crate cannot parse diffs without a ---/+++ header. The only one of
+                // these we care about is a pure rename.
+                if let (Some(old), Some(new)) = (rename_from, rename_to) {
+                    files.push(FileDiff {
+                        old_path: Some(old),
+                        new_path: Some(new),
+                        hunks: Vec::new(),
+                        old_lines: Vec::new(),
+                    });
+                }
+                continue;
+            }
+            let patches = Patch::from_multiple(section).map_err(|e| anyhow::anyhow!("{}", e))?;
+            for patch in patches {
+                files.push(Self::file_diff(&patch));
+            }
+        }
+        Ok(Self { root_path, files })
+    }
+
+    fn file_diff(patch: &Patch) -> FileDiff {
+        let (old, new) = (patch.old.path.as_ref(), patch.new.path.as_ref());
+        // Git prefixes paths with "a/" and "b/", unless run with --no-prefix.
+        let git_prefixed = (old == DEV_NULL || old.starts_with("a/"))
+            && (new == DEV_NULL || new.starts_with("b/"));
+        let (old_prefix, new_prefix) = if git_prefixed {
+            (Some("a/"), Some("b/"))
+        } else {
+            (None, None)
+        };
+
+        let hunks = patch.hunks.iter().map(Hunk::from).collect();
+        let old_lines = patch
+            .hunks
+            .iter()
+            .map(|h| OldLines {
+                new_start: h.new_range.start as usize,
+                new_count: h.new_range.count as usize,
+                lines: h
+                    .lines
+                    .iter()
+                    .filter_map(|l| match l {
+                        patch::Line::Context(l) | patch::Line::Remove(l) => Some(l.to_string()),
+                        patch::Line::Add(_) => None,
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        FileDiff {
+            old_path: diff_path(old, old_prefix),
+            new_path: diff_path(new, new_prefix),
+            hunks,
+            old_lines,
+        }
+    }
+
+    /// Reads a file (relative to the root path) from disk. Failures are reported as an
+    /// [Error::Io] for the file, which the parser passes through as is.
+    fn read_file(&self, path: &Path) -> Result<Vec<u8>> {
+        std::fs::read(self.root_path.join(path)).map_err(|e| Error::io(path, e).into())
+    }
+
+    /// Returns the old content of a file, or None if it is new.
+    fn old_file_content(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+        let file = self
+            .files
+            .iter()
+            .find(|f| f.old_path.as_deref() == Some(path));
+        let file = match file {
+            Some(file) => file,
+            None if self
+                .files
+                .iter()
+                .any(|f| f.new_path.as_deref() == Some(path)) =>
+            {
+                // This is a new file.
+                return Ok(None);
+            }
+            // The file is not part of the diff, so it has not changed.
+            None => return Ok(Some(self.read_file(path)?)),
+        };
+        let new_content = match &file.new_path {
+            Some(new_path) => self.read_file(new_path)?,
+            // The diff of a deleted file contains all of its lines.
+            None => Vec::new(),
+        };
+        Ok(Some(Self::reverse_apply(&new_content, &file.old_lines)))
+    }
+
+    /// Reconstructs the old version of a file by replacing each hunk in the new version with
+    /// its old side.
+    fn reverse_apply(new_content: &[u8], old_lines: &[OldLines]) -> Vec<u8> {
+        let new_lines: Vec<&[u8]> = new_content.lines().collect();
+        let mut content: Vec<u8> = Vec::with_capacity(new_content.len());
+        let mut next = 0;
+        for hunk in old_lines {
+            // If the hunk is empty in the new file, the start line is the line _before_ it.
+            let start = if hunk.new_count == 0 {
+                hunk.new_start
+            } else {
+                hunk.new_start.saturating_sub(1)
+            };
+            for line in new_lines.iter().take(start).skip(next) {
+                content.extend_from_slice(line);
+                content.push(b'\n');
+            }
+            for line in &hunk.lines {
+                content.extend_from_slice(line.as_bytes());
+                content.push(b'\n');
+            }
+            next = start + hunk.new_count;
+        }
+        for line in new_lines.iter().skip(next) {
+            content.extend_from_slice(line);
+            content.push(b'\n');
+        }
+        content
+    }
+}
+
+impl<'a> Repo for UnifiedDiff<'a> {
+    fn resolve(&self, changes: &Changes) -> Result<Changes> {
+        Ok(changes.clone())
+    }
+
+    fn get_changed_files(&self, _changes: &Changes) -> Result<Vec<PathBuf>> {
+        Ok(self
+            .files
+            .iter()
+            .filter_map(|f| f.new_path.clone())
+            .collect())
+    }
+
+    fn get_changed_hunks(&self, _changes: &Changes) -> Result<BTreeMap<PathBuf, Vec<Hunk>>> {
+        let mut hunk_map = BTreeMap::new();
+        for f in &self.files {
+            if let Some(path) = &f.new_path {
+                if !f.hunks.is_empty() {
+                    hunk_map.insert(path.clone(), f.hunks.clone());
+                }
+            }
+        }
+        Ok(hunk_map)
+    }
+
+    fn get_file_contents(
+        &self,
+        _changes: &Changes,
+        paths: &[PathBuf],
+    ) -> Result<BTreeMap<PathBuf, Vec<u8>>> {
+        let mut contents = BTreeMap::new();
+        for path in paths {
+            if self.root_path.join(path).is_file() {
+                contents.insert(path.clone(), self.read_file(path)?);
+            }
+        }
+        Ok(contents)
+    }
+
+    fn get_deleted_files(&self, _changes: &Changes) -> Result<Vec<PathBuf>> {
+        Ok(self
+            .files
+            .iter()
+            .filter(|f| f.new_path.is_none())
+            .filter_map(|f| f.old_path.clone())
+            .collect())
+    }
+
+    fn get_renamed_files(&self, _changes: &Changes) -> Result<Vec<(PathBuf, PathBuf)>> {
+        Ok(self
+            .files
+            .iter()
+            .filter_map(|f| match (&f.old_path, &f.new_path) {
+                (Some(old), Some(new)) if old != new => Some((old.clone(), new.clone())),
+                _ => None,
+            })
+            .collect())
+    }
+
+    fn get_old_file_contents(
+        &self,
+        _changes: &Changes,
+        paths: &[PathBuf],
+    ) -> Result<BTreeMap<PathBuf, Vec<u8>>> {
+        let mut contents = BTreeMap::new();
+        for path in paths {
+            if let Some(content) = self.old_file_content(path)? {
+                contents.insert(path.clone(), content);
+            }
+        }
+        Ok(contents)
+    }
+
+    fn find_files_containing(
+        &self,
+        _changes: &Changes,
+        needles: &[String],
+    ) -> Result<Vec<PathBuf>> {
+        if needles.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut paths = Vec::new();
+        for entry in ignore::WalkBuilder::new(self.root_path).build() {
+            let entry = entry?;
+            if !entry.path().is_file() {
+                continue;
+            }
+            let path = entry.path().strip_prefix(self.root_path)?;
+            let content = self.read_file(path)?;
+            // Skip binary files, like "git grep -I".
+            if content.contains(&0) {
+                continue;
+            }
+            if needles.iter().any(|n| content.find(n.as_bytes()).is_some()) {
+                paths.push(path.to_owned());
+            }
+        }
+        Ok(paths)
+    }
+
+    fn list_files(&self, _changes: &Changes) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for entry in ignore::WalkBuilder::new(self.root_path).build() {
+            let entry = entry?;
+            if entry.path().is_file() {
+                paths.push(entry.path().strip_prefix(self.root_path)?.to_owned());
+            }
+        }
+        Ok(paths)
+    }
+
+    fn get_commits(&self, _head: &str, _base: Option<&str>) -> Result<Vec<Commit>> {
+        Err(anyhow::anyhow!(
+            "a unified diff does not contain any commits"
+        ))
+    }
+
+    fn has_commit(&self, _id: &str) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reverse_apply() {
+        let root_path = Path::new(".");
+        let diff = UnifiedDiff::parse(
+            root_path,
+            "diff --git a/f1.txt b/f1.txt\n\
+             --- a/f1.txt\n\
+             +++ b/f1.txt\n\
+             @@ -1,3 +1,3 @@\n \
+              a\n\
+             -b\n\
+             +x\n \
+              c\n\
+             @@ -5,2 +4,0 @@\n\
+             -e\n\
+             -f\n",
+        )
+        .unwrap();
+        assert_eq!(diff.files.len(), 1);
+        let file = &diff.files[0];
+        assert_eq!(file.old_path.as_deref(), Some(Path::new("f1.txt")));
+        assert_eq!(file.new_path.as_deref(), Some(Path::new("f1.txt")));
+
+        let old = UnifiedDiff::reverse_apply(b"a\nx\nc\nd\ng\n", &file.old_lines);
+        assert_eq!(old.to_str().unwrap(), "a\nb\nc\nd\ne\nf\ng\n");
+    }
+
+    #[test]
+    fn test_parse_renames_and_deletes() {
+        let root_path = Path::new(".");
+        let diff = UnifiedDiff::parse(
+            root_path,
+            "diff --git a/old.txt b/new.txt\n\
+             similarity index 100%\n\
+             rename from old.txt\n\
+             rename to new.txt\n\
+             diff --git a/gone.txt b/gone.txt\n\
+             deleted file mode 100644\n\
+             --- a/gone.txt\n\
+             +++ /dev/null\n\
+             @@ -1,2 +0,0 @@\n\
+             -a\n\
+             -b\n",
+        )
+        .unwrap();
+        let changes = Changes::Patch;
+        assert_eq!(
+            diff.get_renamed_files(&changes).unwrap(),
+            vec![(PathBuf::from("old.txt"), PathBuf::from("new.txt"))]
+        );
+        assert_eq!(
+            diff.get_deleted_files(&changes).unwrap(),
+            vec![PathBuf::from("gone.txt")]
+        );
+        assert_eq!(
+            diff.get_old_file_contents(&changes, &[PathBuf::from("gone.txt")])
+                .unwrap(),
+            BTreeMap::from([(PathBuf::from("gone.txt"), b"a\nb\n".to_vec())])
+        );
+
+        // A plain (non-Git) unified diff.
+        let diff = UnifiedDiff::parse(
+            root_path,
+            "--- src/f1.txt\t2024-01-01 00:00:00.000000000 +0000\n\
+             +++ src/f1.txt\t2024-01-02 00:00:00.000000000 +0000\n\
+             @@ -1 +1 @@\n\
+             -a\n\
+             +b\n",
+        )
+        .unwrap();
+        assert_eq!(
+            diff.get_changed_files(&changes).unwrap(),
+            vec![PathBuf::from("src/f1.txt")]
+        );
+    }
+}
@@ -1,7 +1,36 @@
+mod annotations;
+mod comment;
+mod config;
+mod diagnostics;
+mod error;
 mod file;
 mod git;
+mod marker;
 mod parser;
+mod push;
+mod report;
+mod sarif;
 pub mod test_helpers;
+mod waiver;
 
-pub use file::{OnChangeBlock, ThenChange, ThenChangeTarget, ON_CHANGE_PAT_STR};
-pub use parser::{OnChangeViolation, Parser};
+pub use annotations::{github_annotations, gitlab_code_quality};
+pub use config::{
+    Config, GlobMode, Settings, CONFIG_FILE_NAME, DEFAULT_MAX_FILES_TO_DISPLAY,
+    DEFAULT_MAX_VIOLATIONS_TO_DISPLAY,
+};
+pub use diagnostics::Diagnostics;
+pub use error::{Error, Result};
+pub use file::{
+    BlockPattern, OnChangeBlock, Quantifier, Severity, Suppression, TargetGlob, ThenChange,
+    ThenChangeTarget, ALLOW_UNPAIRED, ON_CHANGE_PAT_STR,
+};
+pub use git::Commit;
+pub use marker::{MarkerSyntax, DEFAULT_PRESET, PRESETS};
+pub use parser::{OnChangeViolation, OnChangeViolationKind, Parser};
+pub use push::PushUpdate;
+pub use report::{
+    BlockEntry, CommitEntry, ErrorEntry, FileEntry, Report, TargetEntry, ViolationEntry,
+    REPORT_VERSION,
+};
+pub use sarif::sarif_log;
+pub use waiver::{apply_waivers, Waiver, SKIP_TRAILER};
@@ -1,11 +1,13 @@
+use std::collections::HashSet;
+use std::io::Read;
 use std::path::{Path, PathBuf};
 
 use clap::Parser as CliParser;
 
-use onchg::Parser;
-
-const DEFAULT_MAX_FILES_TO_DISPLAY: usize = 15;
-const DEFAULT_MAX_VIOLATIONS_TO_DISPLAY: usize = 10;
+use onchg::{
+    CommitEntry, Config, Diagnostics, GlobMode, OnChangeViolation, Parser, PushUpdate, Report,
+    Settings, Severity, Waiver,
+};
 
 fn default_path() -> PathBuf {
     PathBuf::from(".")
@@ -22,6 +24,63 @@ enum Mode {
         #[arg(required = false, default_value = default_path().into_os_string())]
         path: PathBuf,
     },
+    /// Validate staged changes, waiving the violations named in the commit message. Meant to
+    /// be run as a Git commit-msg hook.
+    ///
+    /// Each "Onchg-Skip: <block or file> <reason>" trailer in the message waives the
+    /// violations of that block or file. Waived violations are still listed, but do not fail
+    /// the commit.
+    CommitMsg {
+        /// File containing the commit message (passed by Git).
+        file: PathBuf,
+
+        #[arg(long, default_value = default_path().into_os_string())]
+        path: PathBuf,
+    },
+    /// Validate changes between two revisions in a Git repo.
+    ///
+    /// This is meant for CI, where nothing is staged. The range can be one of
+    /// "<base>..<head>", "<base>...<head>" (changes since the merge-base), or
+    /// "<base>" (same as "<base>...HEAD"). Files are read from the head revision.
+    Diff {
+        range: String,
+
+        #[arg(required = false, default_value = default_path().into_os_string())]
+        path: PathBuf,
+    },
+    /// Validate the changes in a unified diff against the files on disk.
+    ///
+    /// The diff is used in place of Git to find changed hunks, so this works with any VCS
+    /// that can produce a patch. File paths in the diff must be relative to the path.
+    Patch {
+        /// Patch file to read, or "-" for stdin.
+        #[arg(required = false, default_value = "-")]
+        file: PathBuf,
+
+        #[arg(long, default_value = default_path().into_os_string())]
+        path: PathBuf,
+    },
+    /// Validate every commit that is about to be pushed. Meant to be run as a Git pre-push hook.
+    ///
+    /// The refs being pushed are read from stdin using the pre-push hook protocol. Each
+    /// outgoing commit is validated on its own against its parent, and violations are reported
+    /// along with the commit that introduced them. If the PRE_COMMIT_FROM_REF and
+    /// PRE_COMMIT_TO_REF environment variables are set (by the pre-commit framework), they
+    /// are used instead of stdin.
+    PrePush {
+        /// Name of the remote (passed by Git, unused).
+        remote: Option<String>,
+
+        /// URL of the remote (passed by Git, unused).
+        url: Option<String>,
+
+        #[arg(long, default_value = default_path().into_os_string())]
+        path: PathBuf,
+
+        /// Validate all outgoing commits of each ref together instead of one at a time.
+        #[arg(long, default_value_t = false)]
+        squash: bool,
+    },
     /// Check all files in a directory. By default, this will skip parsing any files
     /// specified in the various ignore files.
     ///
@@ -36,6 +95,67 @@ enum Mode {
     },
 }
 
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Format {
+    /// Human-readable output.
+    #[default]
+    Text,
+    /// A JSON report of all parsed blocks, violations and errors, printed to stdout.
+    Json,
+    /// A SARIF 2.1.0 log of all violations and errors, printed to stdout.
+    Sarif,
+    /// GitHub Actions workflow commands that annotate each violation and error.
+    Github,
+    /// A GitLab Code Quality report of all violations and errors, printed to stdout.
+    Gitlab,
+}
+
+/// Semantics of glob and directory ThenChange targets. Mirrors [GlobMode].
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum GlobTargets {
+    /// Every file matching the glob must change.
+    All,
+    /// At least one file matching the glob must change.
+    Any,
+}
+
+impl From<GlobTargets> for GlobMode {
+    fn from(mode: GlobTargets) -> Self {
+        match mode {
+            GlobTargets::All => GlobMode::All,
+            GlobTargets::Any => GlobMode::Any,
+        }
+    }
+}
+
+/// Prints the given reports to stdout in a machine-readable format. If `single` is set, the
+/// only report is printed on its own instead of as part of an array.
+fn print_reports(reports: &[Report], format: Format, single: bool) {
+    match format {
+        Format::Text => (),
+        Format::Json if single => println!("{}", reports[0].to_json()),
+        Format::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(reports).expect("reports should serialize to JSON")
+        ),
+        Format::Sarif => println!(
+            "{}",
+            serde_json::to_string_pretty(&onchg::sarif_log(reports))
+                .expect("SARIF log should serialize to JSON")
+        ),
+        Format::Github => {
+            for annotation in onchg::github_annotations(reports) {
+                println!("{}", annotation);
+            }
+        }
+        Format::Gitlab => println!(
+            "{}",
+            serde_json::to_string_pretty(&onchg::gitlab_code_quality(reports))
+                .expect("Code Quality report should serialize to JSON")
+        ),
+    }
+}
+
 #[derive(clap::Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
@@ -45,86 +165,414 @@ struct Cli {
     #[arg(short, long)]
     verbose: bool,
 
-    #[arg(long, default_value_t = DEFAULT_MAX_FILES_TO_DISPLAY, global = true)]
-    max_files_to_display: usize,
+    /// Maximum number of parsed files to list [default: 15].
+    #[arg(long, global = true)]
+    max_files_to_display: Option<usize>,
+
+    /// Maximum number of violations to list [default: 10].
+    #[arg(long, global = true)]
+    max_violations_to_display: Option<usize>,
+
+    /// Only parse files matching this glob (relative to the root path). Can be repeated.
+    #[arg(long, global = true)]
+    include: Vec<String>,
+
+    /// Skip files matching this glob (relative to the root path). Can be repeated.
+    #[arg(long, global = true)]
+    exclude: Vec<String>,
+
+    /// Marker syntax preset to accept. Can be repeated.
+    #[arg(
+        long,
+        global = true,
+        value_parser = clap::builder::PossibleValuesParser::new(onchg::PRESETS.iter().copied()),
+    )]
+    preset: Vec<String>,
+
+    /// Regex used to find markers, instead of the presets. Must contain the named groups
+    /// "on_change" and "then_change".
+    #[arg(long, global = true)]
+    marker: Option<String>,
+
+    /// Only recognize markers inside comments, based on the file extension. Files in unknown
+    /// languages are matched as a whole.
+    #[arg(long, global = true)]
+    comments_only: bool,
+
+    /// Whether every file matching a glob or directory ThenChange target must change, or at
+    /// least one of them [default: all].
+    #[arg(long, global = true, value_enum)]
+    glob_targets: Option<GlobTargets>,
+
+    /// Require every block reachable through the ThenChange targets of a changed block to
+    /// change, not just its direct targets.
+    #[arg(long, global = true)]
+    transitive: bool,
 
     /// Do not log anything to stdout.
     #[arg(short, long, global = true)]
     quiet: bool,
+
+    /// Output format. All formats other than "text" imply --quiet.
+    #[arg(long, value_enum, default_value_t = Format::Text, global = true)]
+    format: Format,
+}
+
+/// Reads a patch from the given file, or stdin if the file is "-".
+fn read_patch(file: &Path) -> anyhow::Result<String> {
+    if file == Path::new("-") {
+        let mut patch = String::new();
+        std::io::stdin().read_to_string(&mut patch)?;
+        Ok(patch)
+    } else {
+        Ok(std::fs::read_to_string(file)?)
+    }
+}
+
+/// Reads the refs being pushed from the environment (if run by pre-commit) or stdin.
+fn read_push_updates() -> anyhow::Result<Vec<PushUpdate>> {
+    if let (Ok(from), Ok(to)) = (
+        std::env::var("PRE_COMMIT_FROM_REF"),
+        std::env::var("PRE_COMMIT_TO_REF"),
+    ) {
+        return Ok(vec![PushUpdate::from_range(&from, &to)]);
+    }
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input)?;
+    Ok(PushUpdate::parse(&input)?)
+}
+
+/// Prints a report for a run that failed to parse and exits.
+fn exit_with_diagnostics(diagnostics: &Diagnostics, format: Format) -> ! {
+    match format {
+        Format::Text => eprintln!("Parsing failed: {}", diagnostics),
+        _ => print_reports(&[Report::from_diagnostics(diagnostics)], format, true),
+    }
+    std::process::exit(1);
+}
+
+/// Formats a violation for text output. Warnings and waived violations are prefixed since
+/// they do not fail the run.
+fn format_violation(v: &OnChangeViolation) -> String {
+    match (v.waiver(), v.severity()) {
+        (Some(reason), _) => format!("waived ({}): {}", reason, v),
+        (None, Severity::Error) => v.to_string(),
+        (None, Severity::Warn) => format!("warning: {}", v),
+    }
+}
+
+/// Reads the waivers in the given commit message file.
+fn read_waivers(file: &Path, format: Format) -> Vec<Waiver> {
+    let message = match std::fs::read_to_string(file) {
+        Ok(message) => message,
+        Err(e) => {
+            eprintln!("Failed to read commit message: {}", e);
+            std::process::exit(1);
+        }
+    };
+    parse_waivers_or_exit(Waiver::parse(&message), format)
+}
+
+/// Exits if the waivers cannot be read, i.e., a commit message has an invalid trailer or the
+/// commits cannot be listed.
+fn parse_waivers_or_exit(waivers: onchg::Result<Vec<Waiver>>, format: Format) -> Vec<Waiver> {
+    match waivers {
+        Ok(waivers) => waivers,
+        Err(e @ onchg::Error::InvalidSkipTrailer { .. }) if format == Format::Text => {
+            eprintln!("Failed to parse commit message: {}", e);
+            std::process::exit(1);
+        }
+        Err(e) => exit_with_diagnostics(&e.into(), format),
+    }
+}
+
+/// Validates the given parser, waives the violations named by the given waivers, and prints
+/// the violations under a header describing what was checked (if any). Returns a report of
+/// the run.
+fn report_violations(
+    parser: &Parser,
+    config: &Config,
+    header: Option<&str>,
+    waivers: &[Waiver],
+    format: Format,
+) -> Report {
+    let mut violations = match parser.validate_git_repo() {
+        Ok(violations) => violations,
+        Err(e) => {
+            eprintln!("Failed to validate Git repo state: {}", e);
+            std::process::exit(1);
+        }
+    };
+    for waiver in onchg::apply_waivers(&mut violations, waivers) {
+        if format == Format::Text {
+            eprintln!(
+                "Warning: {} trailer for \"{}\" does not match any violations.",
+                onchg::SKIP_TRAILER,
+                waiver.target,
+            );
+        }
+    }
+    let expired = parser.expired_suppressions();
+    if format == Format::Text && (!violations.is_empty() || !expired.is_empty()) {
+        match header {
+            Some(header) => eprintln!("Violations in {}:", header),
+            None => eprintln!("Violations:"),
+        }
+        for v in violations.iter().take(config.max_violations_to_display) {
+            eprintln!("  * {}", format_violation(v));
+        }
+        if violations.len() > config.max_violations_to_display {
+            eprintln!(
+                "  ... {} violations omitted",
+                violations.len() - config.max_violations_to_display,
+            );
+        }
+        // Paths are absolute, like those of the violations.
+        for e in expired {
+            eprintln!("  * {}", e.with_root_path(parser.root_path()));
+        }
+    }
+    Report::new(parser, &violations)
+}
+
+/// Warns that a root commit is not checked, since it has no parent to diff against.
+fn warn_root_commit(commit: &onchg::Commit) {
+    eprintln!(
+        "Warning: skipping root commit {:.10} \"{}\", which has no parent to diff against.",
+        commit.id, commit.summary,
+    );
+}
+
+fn pre_push(path: &Path, config: &Config, squash: bool, quiet: bool, format: Format) {
+    let updates = match read_push_updates() {
+        Ok(updates) => updates,
+        Err(e) => {
+            eprintln!("Failed to read pushed refs: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let parse_or_exit = |parser: Result<Parser, Diagnostics>| match parser {
+        Ok(parser) => parser,
+        Err(e) => exit_with_diagnostics(&e, format),
+    };
+
+    let mut seen = HashSet::new();
+    let mut reports = Vec::new();
+    let mut num_checked = 0;
+    for update in updates.iter().filter(|u| !u.is_delete()) {
+        let commits = match update.outgoing_commits(path) {
+            Ok(commits) => commits,
+            Err(e) => {
+                eprintln!("Failed to list commits for {}: {}", update.local_ref, e);
+                std::process::exit(1);
+            }
+        };
+        if squash {
+            // The range starts at the first commit, so a root commit's own changes are not
+            // part of it.
+            if let Some(commit) = commits.first().filter(|c| c.root) {
+                warn_root_commit(commit);
+            }
+            let range = match update.outgoing_range(path, &commits) {
+                Ok(range) => range,
+                Err(e) => {
+                    eprintln!("Failed to list commits for {}: {}", update.local_ref, e);
+                    std::process::exit(1);
+                }
+            };
+            if let Some(range) = range {
+                let parser =
+                    parse_or_exit(Parser::from_git_range_with_config(path, &range, config));
+                let header = format!("{} ({})", update.local_ref, range);
+                // The commits are checked as one, so a waiver in any of them applies.
+                let waivers: Vec<Waiver> = commits
+                    .iter()
+                    .flat_map(|c| parse_waivers_or_exit(Waiver::parse(&c.message), format))
+                    .collect();
+                reports.push(report_violations(
+                    &parser,
+                    config,
+                    Some(&header),
+                    &waivers,
+                    format,
+                ));
+                num_checked += commits.len();
+            }
+            continue;
+        }
+        // Commits shared by multiple refs only need to be checked once.
+        for commit in commits.iter().filter(|c| seen.insert(c.id.clone())) {
+            if commit.root {
+                warn_root_commit(commit);
+                continue;
+            }
+            let parser = parse_or_exit(Parser::from_git_commit_with_config(
+                path, &commit.id, config,
+            ));
+            let header = format!("commit {:.10} \"{}\"", commit.id, commit.summary);
+            let waivers = parse_waivers_or_exit(Waiver::parse(&commit.message), format);
+            let mut report = report_violations(&parser, config, Some(&header), &waivers, format);
+            report.commit = Some(CommitEntry {
+                id: commit.id.clone(),
+                summary: commit.summary.clone(),
+            });
+            reports.push(report);
+            num_checked += 1;
+        }
+    }
+
+    print_reports(&reports, format, false);
+    if reports.iter().any(|r| !r.is_ok()) {
+        std::process::exit(1);
+    }
+    if !quiet {
+        println!("Checked {} outgoing commits.\n\nOK.", num_checked);
+    }
 }
 
 fn main() {
     env_logger::init();
 
     let cli = Cli::parse();
+    let quiet = cli.quiet || cli.format != Format::Text;
 
-    let parser = match &cli.mode {
-        Mode::Directory { path, no_ignore } => Parser::from_directory(path, !no_ignore),
-        Mode::Repo { path, .. } => Parser::from_git_repo(path),
+    // Flags take precedence over the config file.
+    let overrides = Settings {
+        include: cli.include.clone(),
+        exclude: cli.exclude.clone(),
+        presets: if cli.preset.is_empty() {
+            None
+        } else {
+            Some(cli.preset.clone())
+        },
+        markers: Vec::new(),
+        marker: cli.marker.clone(),
+        ignore: match cli.mode {
+            Mode::Directory {
+                no_ignore: true, ..
+            } => Some(false),
+            _ => None,
+        },
+        comments_only: cli.comments_only.then_some(true),
+        glob_targets: cli.glob_targets.map(GlobMode::from),
+        transitive: cli.transitive.then_some(true),
+        max_files_to_display: cli.max_files_to_display,
+        max_violations_to_display: cli.max_violations_to_display,
     };
-    if let Err(e) = parser {
-        eprintln!("Parsing failed: {}", e);
-        std::process::exit(1);
+    // In Git modes, config files are read from the revision (or index) being checked.
+    let config = match &cli.mode {
+        Mode::Directory { path, .. } | Mode::Patch { path, .. } => Config::load(path, &overrides),
+        Mode::Repo { path } | Mode::CommitMsg { path, .. } => {
+            Config::load_from_git(path, None, &overrides)
+        }
+        Mode::Diff { path, range } => Config::load_from_git(path, Some(range), &overrides),
+        // Each pushed commit is checked with its own config files. Only the display settings
+        // of this one are used.
+        Mode::PrePush { path, .. } => Config::load_from_git(path, Some("HEAD"), &overrides),
+    };
+    let config = match config {
+        Ok(config) => config,
+        Err(e) => exit_with_diagnostics(&e.into(), cli.format),
+    };
+
+    if let Mode::PrePush { path, squash, .. } = &cli.mode {
+        pre_push(path, &config, *squash, quiet, cli.format);
+        return;
     }
-    let parser = parser.unwrap();
+
+    let waivers = match &cli.mode {
+        Mode::CommitMsg { file, .. } => read_waivers(file, cli.format),
+        Mode::Diff { path, range } => {
+            parse_waivers_or_exit(Waiver::from_range(path, range), cli.format)
+        }
+        _ => Vec::new(),
+    };
+
+    let parser = match &cli.mode {
+        Mode::Directory { path, .. } => Parser::from_directory_with_config(path, &config),
+        Mode::Repo { path, .. } | Mode::CommitMsg { path, .. } => {
+            Parser::from_git_repo_with_config(path, &config)
+        }
+        Mode::Diff { path, range } => Parser::from_git_range_with_config(path, range, &config),
+        Mode::Patch { file, path } => match read_patch(file) {
+            Ok(patch) => Parser::from_patch_with_config(path, &patch, &config),
+            Err(e) => {
+                eprintln!("Failed to read patch: {}", e);
+                std::process::exit(1);
+            }
+        },
+        Mode::PrePush { .. } => unreachable!(),
+    };
+    let parser = match parser {
+        Ok(parser) => parser,
+        Err(e) => exit_with_diagnostics(&e, cli.format),
+    };
 
     let mut files: Vec<&Path> = parser.paths().collect();
     files.sort();
 
-    if !cli.quiet {
+    if !quiet {
         println!("Root path: {}\n", parser.root_path().display());
     }
 
-    if !cli.quiet {
-        if files.len() != 0 {
+    let (num_removed_blocks, num_deleted_files, num_renamed_files) = (
+        parser.removed_blocks().count(),
+        parser.deleted_paths().count(),
+        parser.renamed_paths().count(),
+    );
+    let nothing_removed =
+        num_removed_blocks == 0 && num_deleted_files == 0 && num_renamed_files == 0;
+
+    if !quiet {
+        if !nothing_removed {
+            println!(
+                "Found {} removed blocks, {} deleted files and {} renamed files.",
+                num_removed_blocks, num_deleted_files, num_renamed_files,
+            );
+        }
+        if !files.is_empty() {
             println!(
                 "Parsed {} files ({} blocks total):",
                 files.len(),
                 parser.num_blocks(),
             );
-            for f in files.iter().take(DEFAULT_MAX_FILES_TO_DISPLAY) {
+            for f in files.iter().take(config.max_files_to_display) {
                 println!("  * {}", parser.root_path().join(f).display());
             }
-            if files.len() > DEFAULT_MAX_FILES_TO_DISPLAY {
+            if files.len() > config.max_files_to_display {
                 println!(
                     "  ... {} files omitted",
-                    files.len() - DEFAULT_MAX_FILES_TO_DISPLAY,
+                    files.len() - config.max_files_to_display,
                 );
             }
-        } else if let Mode::Repo { .. } = cli.mode {
-            println!("No staged files to check.");
-            return;
+        } else if nothing_removed {
+            if let Mode::Repo { .. } | Mode::CommitMsg { .. } = cli.mode {
+                println!("No staged files to check.");
+                return;
+            } else if let Mode::Diff { .. } | Mode::Patch { .. } = cli.mode {
+                println!("No changed files to check.");
+                return;
+            }
         }
     }
 
-    println!();
+    if !quiet {
+        println!();
+    }
 
-    match &cli.mode {
-        Mode::Repo { .. } => {
-            let violations = parser.validate_git_repo();
-            if let Err(e) = &violations {
-                eprintln!("Failed to validate Git repo state: {}", e);
-                std::process::exit(1);
-            }
-            let violations = violations.unwrap();
-            if violations.len() != 0 {
-                eprintln!("Violations:");
-                for v in violations.iter().take(DEFAULT_MAX_VIOLATIONS_TO_DISPLAY) {
-                    eprintln!("  * {}", v.to_string());
-                }
-                if violations.len() > DEFAULT_MAX_FILES_TO_DISPLAY {
-                    println!(
-                        "  ... {} violations omitted",
-                        violations.len() - DEFAULT_MAX_VIOLATIONS_TO_DISPLAY,
-                    );
-                }
-                std::process::exit(1);
-            }
-        }
-        _ => (),
+    let report = if let Mode::Directory { .. } = &cli.mode {
+        Report::new(&parser, &[])
+    } else {
+        report_violations(&parser, &config, None, &waivers, cli.format)
     };
 
-    if !cli.quiet {
+    print_reports(std::slice::from_ref(&report), cli.format, true);
+    if !report.is_ok() {
+        std::process::exit(1);
+    }
+
+    if !quiet {
         println!("OK.");
     }
 }
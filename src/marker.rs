@@ -0,0 +1,150 @@
+use regex::bytes::Regex;
+use serde::Deserialize;
+
+use crate::comment::CommentSyntax;
+use crate::file::{ON_CHANGE_GROUP, ON_CHANGE_OPT_GROUP, THEN_CHANGE_GROUP};
+
+/// Name of the preset used if none are configured.
+pub const DEFAULT_PRESET: &str = "onchg";
+
+/// Names of the built-in presets.
+pub const PRESETS: &[&str] = &[DEFAULT_PRESET, "lint-ifchange", "ifttt"];
+
+/// The keywords that open and close a block, e.g., `LINT.OnChange` and `LINT.ThenChange`.
+///
+/// The block name and ThenChange targets are passed in parentheses after each keyword.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MarkerSyntax {
+    pub on_change: String,
+    pub then_change: String,
+    /// Allow the OnChange keyword without parentheses (i.e., an unnamed block).
+    #[serde(default)]
+    pub bare: bool,
+}
+
+impl MarkerSyntax {
+    pub fn new(on_change: &str, then_change: &str, bare: bool) -> Self {
+        Self {
+            on_change: on_change.to_string(),
+            then_change: then_change.to_string(),
+            bare,
+        }
+    }
+
+    /// Returns the syntax of the given built-in preset:
+    ///
+    /// * `onchg`: `LINT.OnChange(...)` and `LINT.ThenChange(...)`
+    /// * `lint-ifchange`: `LINT.IfChange` or `LINT.IfChange(...)`, and `LINT.ThenChange(...)`
+    /// * `ifttt`: `IF_CHANGE` or `IF_CHANGE(...)`, and `THEN_CHANGE(...)`
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            DEFAULT_PRESET => Some(Self::new("LINT.OnChange", "LINT.ThenChange", false)),
+            "lint-ifchange" => Some(Self::new("LINT.IfChange", "LINT.ThenChange", true)),
+            "ifttt" => Some(Self::new("IF_CHANGE", "THEN_CHANGE", true)),
+            _ => None,
+        }
+    }
+}
+
+/// How markers are found in a file: the pattern to match, and if markers are only
+/// recognized inside comments, the comment syntax of the file.
+#[derive(Clone, Debug)]
+pub(crate) struct Marker {
+    pub(crate) pat: Regex,
+    pub(crate) comments: Option<&'static CommentSyntax>,
+}
+
+/// Matches the contents of an OnChange on a single line, up to the first closing parenthesis
+/// that is not inside a quoted attribute value.
+const ON_CHANGE_ARGS: &str = r#"(?:[^)"\n]|"(?:[^"\\\n]|\\.)*"|")*"#;
+
+/// Builds a regex alternation of the given keywords. Longer keywords come first so that a
+/// keyword that is a prefix of another does not shadow it.
+fn alternation<'a>(keywords: impl Iterator<Item = &'a str>) -> String {
+    let mut keywords: Vec<&str> = keywords.collect();
+    keywords.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+    keywords.dedup();
+    let escaped: Vec<String> = keywords.into_iter().map(regex::escape).collect();
+    match escaped.len() {
+        0 | 1 => escaped.join(""),
+        _ => format!("(?:{})", escaped.join("|")),
+    }
+}
+
+/// Builds the pattern used to find markers for all of the given syntaxes.
+///
+/// The pattern for the default preset is the same as [crate::ON_CHANGE_PAT_STR].
+pub(crate) fn build_pattern(syntaxes: &[MarkerSyntax]) -> Result<Regex, regex::Error> {
+    let mut alternatives = Vec::new();
+    let strict = alternation(
+        syntaxes
+            .iter()
+            .filter(|s| !s.bare)
+            .map(|s| s.on_change.as_str()),
+    );
+    if !strict.is_empty() {
+        alternatives.push(format!(
+            r#"{}\((?<{}>{})\)"#,
+            strict, ON_CHANGE_GROUP, ON_CHANGE_ARGS
+        ));
+    }
+    let bare = alternation(
+        syntaxes
+            .iter()
+            .filter(|s| s.bare)
+            .map(|s| s.on_change.as_str()),
+    );
+    if !bare.is_empty() {
+        alternatives.push(format!(
+            r#"{}\b(?:\((?<{}>{})\))?"#,
+            bare, ON_CHANGE_OPT_GROUP, ON_CHANGE_ARGS
+        ));
+    }
+    let then_change = alternation(syntaxes.iter().map(|s| s.then_change.as_str()));
+    alternatives.push(format!(
+        r#"{}\((?<{}>(?:[^)"]|"(?:[^"\\\n]|\\.)*"|")*)\)"#,
+        then_change, THEN_CHANGE_GROUP
+    ));
+    Regex::new(&alternatives.join("|"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ON_CHANGE_PAT_STR;
+
+    #[test]
+    fn test_build_pattern() {
+        let default = MarkerSyntax::preset(DEFAULT_PRESET).unwrap();
+        assert_eq!(
+            build_pattern(std::slice::from_ref(&default))
+                .unwrap()
+                .as_str(),
+            ON_CHANGE_PAT_STR
+        );
+
+        let pat = build_pattern(&[
+            default,
+            MarkerSyntax::preset("lint-ifchange").unwrap(),
+            MarkerSyntax::preset("ifttt").unwrap(),
+        ])
+        .unwrap();
+        for (input, group, value) in [
+            ("// LINT.OnChange(a)", ON_CHANGE_GROUP, Some("a")),
+            ("// LINT.IfChange(b)", ON_CHANGE_OPT_GROUP, Some("b")),
+            ("// LINT.IfChange", ON_CHANGE_OPT_GROUP, None),
+            ("# IF_CHANGE", ON_CHANGE_OPT_GROUP, None),
+            ("# THEN_CHANGE(f.txt)", THEN_CHANGE_GROUP, Some("f.txt")),
+            ("// LINT.ThenChange(:a)", THEN_CHANGE_GROUP, Some(":a")),
+        ] {
+            let c = pat.captures(input.as_bytes()).expect(input);
+            assert_eq!(
+                c.name(group).map(|m| m.as_bytes()),
+                value.map(str::as_bytes)
+            );
+        }
+        assert!(!pat.is_match(b"// LINT.OnChange"));
+        assert!(!pat.is_match(b"// LINT.IfChanged"));
+    }
+}
@@ -1,12 +1,18 @@
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use anyhow::Result;
+use chrono::Local;
 use rayon::prelude::*;
 
-use crate::file::{File, OnChangeBlock};
-use crate::git::Repo;
-use crate::{ThenChange, ThenChangeTarget};
+use crate::file::{File, OnChangeBlock, TargetGlob};
+use crate::git::{Changes, Hunk, Repo, UnifiedDiff};
+use crate::marker::Marker;
+use crate::{
+    Config, Diagnostics, Error, GlobMode, Quantifier, Result, Settings, Severity, ThenChange,
+    ThenChangeTarget,
+};
 
 #[derive(Debug)]
 pub struct Parser {
@@ -16,6 +22,65 @@ pub struct Parser {
     files: BTreeMap<PathBuf, File>,
     /// Total number of blocks parsed.
     num_blocks: usize,
+    /// Files changed by the changes this parser was built from (repo and patch modes only).
+    changed_files: Option<Vec<PathBuf>>,
+    /// Blocks and files removed by the changes (repo mode only).
+    removed: Removed,
+    /// All blocks in files containing ThenChange targets of changed blocks, as well as files
+    /// reachable from transitive blocks, keyed by path (repo and patch modes only). Used to
+    /// report the location of unchanged targets.
+    target_blocks: BTreeMap<PathBuf, Vec<OnChangeBlock>>,
+    /// Semantics of glob targets (repo and patch modes only).
+    glob_mode: GlobMode,
+    /// All files matching each glob target of a changed or removed block, keyed by pattern
+    /// (repo and patch modes only, and only in [GlobMode::All]).
+    glob_target_files: BTreeMap<String, Vec<PathBuf>>,
+    /// All blocks in the sync groups of changed or removed blocks, keyed by group (repo and
+    /// patch modes only).
+    group_members: BTreeMap<String, Vec<OnChangeBlock>>,
+    /// Whether all blocks are transitive (repo and patch modes only).
+    transitive: bool,
+}
+
+#[derive(Debug, Default)]
+struct Removed {
+    /// Blocks that existed in the old version of a changed or deleted file, but not in the new one.
+    blocks: Vec<OnChangeBlock>,
+    /// Files that were deleted.
+    files: Vec<PathBuf>,
+    /// Files that were renamed, as (old, new) paths.
+    renamed: Vec<(PathBuf, PathBuf)>,
+    /// Blocks in the new version of the repo that target a removed block, or a deleted or
+    /// renamed file.
+    dependents: Vec<OnChangeBlock>,
+}
+
+impl Removed {
+    fn is_empty(&self) -> bool {
+        self.blocks.is_empty() && self.files.is_empty() && self.renamed.is_empty()
+    }
+
+    /// Returns the new path of the given file if it was renamed.
+    fn renamed_to(&self, file: &Path) -> Option<&Path> {
+        self.renamed
+            .iter()
+            .find(|(old, _)| old == file)
+            .map(|(_, new)| new.as_path())
+    }
+
+    /// Returns true if the given target refers to a removed block, or a deleted or renamed file.
+    fn contains_target(&self, file: &Path, block_name: Option<&str>) -> bool {
+        if self.files.iter().any(|f| f == file) || self.renamed_to(file).is_some() {
+            return true;
+        }
+        match block_name {
+            Some(name) => self
+                .blocks
+                .iter()
+                .any(|b| b.file() == file && b.name_raw() == Some(name)),
+            None => false,
+        }
+    }
 }
 
 impl Parser {
@@ -26,39 +91,89 @@ impl Parser {
         target: &ThenChangeTarget,
         blocks: &HashMap<(&Path, &str), &OnChangeBlock>,
     ) -> Result<()> {
+        // Missing target files are reported while parsing.
+        if let Some(file) = target.file() {
+            if !self.files.contains_key(file) && !self.root_path.join(file).exists() {
+                return Ok(());
+            }
+        }
         match target {
-            ThenChangeTarget::File(file) => {
+            ThenChangeTarget::File { file, line } => {
                 if !self.files.contains_key(file) {
-                    return Err(anyhow::anyhow!(
-                        r#"block "{}" at "{}:{}" has non-existent ThenChange target "{}""#,
-                        block.name(),
-                        path.display(),
-                        block.end_line(),
-                        file.display(),
-                    ));
+                    // The file exists, but was not parsed (e.g., it is ignored).
+                    return Err(Error::MissingTargetFile {
+                        path: path.to_owned(),
+                        line: *line,
+                        block: block.name().to_string(),
+                        target_file: file.to_owned(),
+                    });
                 }
             }
             ThenChangeTarget::Block {
                 block: target_block,
                 file,
+                line,
             } => {
                 let file = file.as_deref().unwrap_or(path);
                 let block_key = (file, target_block.as_str());
                 if !blocks.contains_key(&block_key) {
-                    return Err(anyhow::anyhow!(
-                        r#"block "{}" at "{}:{}" has non-existent ThenChange target "{}:{}""#,
-                        block.name(),
-                        path.display(),
-                        block.end_line(),
-                        file.display(),
-                        target_block,
-                    ));
+                    return Err(Error::MissingTargetBlock {
+                        path: path.to_owned(),
+                        line: *line,
+                        block: block.name().to_string(),
+                        target_file: file.to_owned(),
+                        target_block: target_block.to_string(),
+                    });
+                }
+            }
+            ThenChangeTarget::BlockPattern {
+                pattern,
+                file,
+                line,
+            } => {
+                let file = file.as_deref().unwrap_or(path);
+                let matched = blocks
+                    .keys()
+                    .any(|(f, name)| *f == file && pattern.is_match(name));
+                if !matched {
+                    return Err(Error::MissingTargetBlock {
+                        path: path.to_owned(),
+                        line: *line,
+                        block: block.name().to_string(),
+                        target_file: file.to_owned(),
+                        target_block: pattern.pattern().to_string(),
+                    });
                 }
             }
+            // Glob targets are only validated in directory mode, where all files are known.
+            ThenChangeTarget::Glob { .. } => (),
         }
         Ok(())
     }
 
+    /// Returns an error for each glob target that does not match any of the given files.
+    fn validate_glob_targets(&self, paths: &[PathBuf]) -> Vec<Error> {
+        let mut errors = Vec::new();
+        for (path, file) in &self.files {
+            for block in &file.blocks {
+                for target in block.then_change_targets() {
+                    let Some(glob) = target.glob() else {
+                        continue;
+                    };
+                    if !paths.iter().any(|p| glob.is_match(p)) {
+                        errors.push(Error::UnmatchedTargetGlob {
+                            path: path.to_owned(),
+                            line: target.line(),
+                            block: block.name().to_string(),
+                            pattern: glob.pattern().to_string(),
+                        });
+                    }
+                }
+            }
+        }
+        errors
+    }
+
     /// Returns a map of all _targetable_ blocks in the file set.
     fn on_change_blocks(&self) -> HashMap<(&Path, &str), &OnChangeBlock> {
         let mut blocks = HashMap::with_capacity(self.num_blocks);
@@ -73,8 +188,65 @@ impl Parser {
         blocks
     }
 
-    fn validate(&self) -> Result<()> {
+    /// Returns all blocks in the file set that are in a sync group, keyed by group.
+    fn groups(&self) -> BTreeMap<&str, Vec<&OnChangeBlock>> {
+        let mut groups: BTreeMap<&str, Vec<&OnChangeBlock>> = BTreeMap::new();
+        for block in self.files.values().flat_map(|f| f.blocks.iter()) {
+            if let Some(group) = block.group() {
+                groups.entry(group).or_default().push(block);
+            }
+        }
+        groups
+    }
+
+    /// Returns an error for each sync group with a single member. Like
+    /// [Parser::validate_glob_targets], this is only checked in directory mode, where all
+    /// members are known.
+    fn validate_groups(&self) -> Vec<Error> {
+        let mut errors = Vec::new();
+        for (group, members) in self.groups() {
+            if let [block] = members.as_slice() {
+                errors.push(Error::SingleMemberGroup {
+                    path: block.file().to_owned(),
+                    line: block.start_line(),
+                    block: block.name().to_string(),
+                    group: group.to_string(),
+                });
+            }
+        }
+        errors
+    }
+
+    /// Returns an error for each parsed block with an `onchg:allow-unpaired` suppression that
+    /// expired before today.
+    ///
+    /// These fail the parse in directory mode. When validating changes, they are reported
+    /// along with the violations instead (see [crate::Report::new]).
+    pub fn expired_suppressions(&self) -> Vec<Error> {
+        let today = Local::now().date_naive();
+        let mut errors = Vec::new();
+        for (path, file) in &self.files {
+            for block in &file.blocks {
+                let Some(suppression) = block.suppression() else {
+                    continue;
+                };
+                if suppression.is_expired(today) {
+                    errors.push(Error::ExpiredSuppression {
+                        path: path.to_owned(),
+                        line: block.start_line(),
+                        block: block.name().to_string(),
+                        until: suppression.until(),
+                    });
+                }
+            }
+        }
+        errors
+    }
+
+    /// Validates the targets of all blocks and returns every error found.
+    fn validate(&self) -> Vec<Error> {
         let blocks = self.on_change_blocks();
+        let mut errors = Vec::new();
 
         for (path, file) in &self.files {
             for block in &file.blocks {
@@ -82,36 +254,44 @@ impl Parser {
                     ThenChange::NoTarget => {}
                     ThenChange::Targets(targets) => {
                         for t in targets {
-                            Self::validate_block_target(&self, path, block, t, &blocks)?;
+                            if let Err(e) =
+                                Self::validate_block_target(self, path, block, t, &blocks)
+                            {
+                                errors.push(e);
+                            }
                         }
                     }
+                    // This cannot happen for a parsed block, since an OnChange without a
+                    // ThenChange is never closed.
                     ThenChange::Unset => {
-                        return Err(anyhow::anyhow!(
-                            r#"block "{}" in file "{}" has an unset OnChange target (line {})"#,
-                            block.name(),
-                            path.display(),
-                            block.end_line(),
-                        ));
+                        errors.push(Error::UnclosedBlock {
+                            path: path.to_owned(),
+                            line: block.start_line(),
+                            block: block.name().to_string(),
+                        });
                     }
                 }
             }
         }
 
-        Ok(())
+        errors
+    }
+
+    fn canonicalize(path: &Path) -> Result<PathBuf> {
+        path.canonicalize().map_err(|e| Error::io(path, e))
     }
 
     fn validate_root_path<P: AsRef<Path>>(root_path: P) -> Result<()> {
         let root_path = root_path.as_ref();
         if !root_path.exists() {
-            Err(anyhow::anyhow!(
-                "root path {} does not exist",
-                root_path.display(),
+            Err(Error::io(
+                root_path,
+                std::io::Error::new(std::io::ErrorKind::NotFound, "root path does not exist"),
             ))
         } else if !root_path.is_dir() {
-            Err(anyhow::anyhow!(
-                "root path {} is not a directory",
-                root_path.display(),
-            ))
+            Err(Error::InvalidRootPath {
+                path: root_path.to_owned(),
+            })
         } else {
             Ok(())
         }
@@ -126,13 +306,18 @@ impl Parser {
     ///
     /// TODO(aksiksi): Respect .gitignore and .ignore files via [[ignore]].
     ///
+    /// Errors in individual files do not stop parsing. Instead, they are collected and returned
+    /// alongside the parser. Files excluded by the config are skipped, and the callback is
+    /// passed the marker pattern for each file.
+    ///
     /// NOTE(aksiksi): Work to parallelize the file parsing and traversal logic.
     fn from_files_internal<P: AsRef<Path>, Q: AsRef<Path>>(
         paths: impl Iterator<Item = P>,
         root_path: Q,
-        file_callback: impl Fn(PathBuf, &Path) -> Result<Option<(File, HashSet<PathBuf>)>>,
-    ) -> Result<Self> {
-        let root_path = root_path.as_ref().canonicalize()?;
+        config: &Config,
+        file_callback: impl Fn(PathBuf, &Path, &Marker) -> Result<Option<(File, HashSet<PathBuf>)>>,
+    ) -> Result<(Self, Diagnostics)> {
+        let root_path = Self::canonicalize(root_path.as_ref())?;
         let mut files = BTreeMap::new();
 
         Self::validate_root_path(&root_path)?;
@@ -144,23 +329,31 @@ impl Parser {
             })
             .collect();
 
-        // Validate provided paths.
-        for path in &file_stack {
-            let path = root_path.join(path);
-            if !path.exists() {
-                return Err(anyhow::anyhow!(
-                    "file with path \"{}\" does not exist",
-                    path.display(),
-                ));
-            } else if !path.is_file() {
-                return Err(anyhow::anyhow!("path \"{}\" is not a file", path.display(),));
-            }
-        }
-
         let s = std::time::Instant::now();
 
+        let mut diagnostics = Diagnostics::new();
+        let mut failed_paths = HashSet::new();
+
         while let Some(path) = file_stack.pop() {
-            if let Some((file, files_to_parse)) = file_callback(path.clone(), &root_path)? {
+            // A file can be pushed more than once before it is parsed.
+            if files.contains_key(&path) || failed_paths.contains(&path) {
+                continue;
+            }
+            let parsed = match config.marker(&path) {
+                Ok(Some(marker)) => file_callback(path.clone(), &root_path, &marker),
+                Ok(None) => Ok(None),
+                Err(e) => Err(e),
+            };
+            let parsed = match parsed {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    diagnostics.push(e);
+                    failed_paths.insert(path);
+                    continue;
+                }
+            };
+            if let Some((mut file, files_to_parse)) = parsed {
+                diagnostics.extend(file.take_errors());
                 files.insert(path, file);
                 for file_path in files_to_parse {
                     if !files.contains_key(&file_path) {
@@ -182,11 +375,19 @@ impl Parser {
             s.elapsed()
         );
 
-        Ok(Self {
+        let parser = Self {
             root_path: root_path.to_owned(),
             files,
             num_blocks,
-        })
+            changed_files: None,
+            removed: Removed::default(),
+            target_blocks: BTreeMap::new(),
+            glob_mode: GlobMode::default(),
+            glob_target_files: BTreeMap::new(),
+            group_members: BTreeMap::new(),
+            transitive: false,
+        };
+        Ok((parser, diagnostics))
     }
 
     /// Builds a parser from the given set of files, as well as any files they depend
@@ -196,19 +397,71 @@ impl Parser {
     pub fn from_files<P: AsRef<Path>, Q: AsRef<Path>>(
         paths: impl Iterator<Item = P>,
         root_path: Q,
-    ) -> Result<Self> {
-        let parser = Self::from_files_internal(paths, root_path, |path, root_path| {
-            File::parse(path, root_path, None)
-        })?;
-        parser.validate()?;
+    ) -> Result<Self, Diagnostics> {
+        let config = Config::load(root_path.as_ref(), &Settings::default())?;
+        Self::from_files_with_config(paths, root_path, &config)
+    }
+
+    /// Same as [Parser::from_files], but uses the given config instead of the config file at
+    /// the root path.
+    pub fn from_files_with_config<P: AsRef<Path>, Q: AsRef<Path>>(
+        paths: impl Iterator<Item = P>,
+        root_path: Q,
+        config: &Config,
+    ) -> Result<Self, Diagnostics> {
+        let paths: Vec<PathBuf> = paths.map(|p| p.as_ref().to_owned()).collect();
+
+        // Validate provided paths.
+        let mut diagnostics = Diagnostics::new();
+        for path in &paths {
+            let full_path = root_path.as_ref().join(path);
+            if !full_path.exists() {
+                diagnostics.push(Error::io(
+                    path,
+                    std::io::Error::new(std::io::ErrorKind::NotFound, "file does not exist"),
+                ));
+            } else if !full_path.is_file() {
+                diagnostics.push(Error::io(
+                    path,
+                    std::io::Error::new(std::io::ErrorKind::InvalidInput, "path is not a file"),
+                ));
+            }
+        }
+        diagnostics.into_result()?;
+
+        let (parser, mut diagnostics) = Self::from_files_internal(
+            paths.iter(),
+            root_path,
+            config,
+            |path, root_path, marker| File::parse(path, root_path, None, marker),
+        )?;
+        diagnostics.extend(parser.validate());
+        diagnostics.extend(parser.expired_suppressions());
+        diagnostics.into_result()?;
         Ok(parser)
     }
 
     /// Recursively walks through all files in the given path and parses them.
     ///
     /// If ignore is set, this method will respect .gitignore and .ignore files (via [[ignore]]).
-    pub fn from_directory<P: AsRef<Path>>(path: P, ignore: bool) -> Result<Self> {
-        let root_path = path.as_ref().canonicalize()?;
+    /// This takes precedence over the ignore setting in the config file.
+    pub fn from_directory<P: AsRef<Path>>(path: P, ignore: bool) -> Result<Self, Diagnostics> {
+        let overrides = Settings {
+            ignore: Some(ignore),
+            ..Default::default()
+        };
+        let config = Config::load(path.as_ref(), &overrides)?;
+        Self::from_directory_with_config(path, &config)
+    }
+
+    /// Same as [Parser::from_directory], but uses the given config instead of the config file
+    /// at the root path.
+    pub fn from_directory_with_config<P: AsRef<Path>>(
+        path: P,
+        config: &Config,
+    ) -> Result<Self, Diagnostics> {
+        let ignore = config.ignore;
+        let root_path = Self::canonicalize(path.as_ref())?;
         let mut files = BTreeMap::new();
 
         Self::validate_root_path(&root_path)?;
@@ -223,16 +476,20 @@ impl Parser {
             .git_exclude(ignore)
             .parents(ignore)
             .build();
-        let paths: Vec<PathBuf> = dir_walker
-            .filter_map(|e| {
-                let path = e.as_ref().unwrap().path().to_owned();
-                if !path.is_file() {
-                    None
-                } else {
-                    Some(path.strip_prefix(&root_path).unwrap().to_owned())
+        let mut diagnostics = Diagnostics::new();
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for entry in dir_walker {
+            let path = match entry {
+                Ok(entry) => entry.into_path(),
+                Err(e) => {
+                    diagnostics.push(Error::io(&root_path, std::io::Error::other(e)));
+                    continue;
                 }
-            })
-            .collect();
+            };
+            if path.is_file() {
+                paths.push(path.strip_prefix(&root_path).unwrap().to_owned());
+            }
+        }
 
         log::info!("Walked {} file paths in {:?}", paths.len(), s.elapsed());
 
@@ -241,20 +498,24 @@ impl Parser {
         // Parse the files (multi-threaded).
         let file_items: Vec<_> = paths
             .par_iter()
-            .filter_map(|p| {
-                if let Some((f, _)) = File::parse(p.to_owned(), &root_path, None).unwrap() {
-                    Some(f)
-                } else {
-                    None
-                }
+            .map(|p| match config.marker(p)? {
+                Some(marker) => File::parse(p.to_owned(), &root_path, None, &marker),
+                None => Ok(None),
             })
             .collect();
-        for f in file_items {
-            files.insert(f.path.clone(), f);
+        for item in file_items {
+            match item {
+                Ok(Some((mut f, _))) => {
+                    diagnostics.extend(f.take_errors());
+                    files.insert(f.path.clone(), f);
+                }
+                Ok(None) => (),
+                Err(e) => diagnostics.push(e),
+            }
         }
 
         let mut num_blocks = 0;
-        for (_, f) in &files {
+        for f in files.values() {
             num_blocks += f.blocks.len();
         }
 
@@ -270,9 +531,20 @@ impl Parser {
             root_path: root_path.to_owned(),
             files,
             num_blocks,
+            changed_files: None,
+            removed: Removed::default(),
+            target_blocks: BTreeMap::new(),
+            glob_mode: GlobMode::default(),
+            glob_target_files: BTreeMap::new(),
+            group_members: BTreeMap::new(),
+            transitive: false,
         };
-        parser.validate()?;
+        diagnostics.extend(parser.validate());
+        diagnostics.extend(parser.validate_glob_targets(&paths));
+        diagnostics.extend(parser.validate_groups());
+        diagnostics.extend(parser.expired_suppressions());
         log::info!("Validated {} blocks in {:?}", num_blocks, s.elapsed());
+        diagnostics.into_result()?;
         Ok(parser)
     }
 
@@ -306,168 +578,1218 @@ impl Parser {
     pub fn num_blocks(&self) -> usize {
         self.num_blocks
     }
+
+    /// Returns an iterator over blocks removed by the changes (repo mode only).
+    pub fn removed_blocks(&self) -> impl Iterator<Item = &OnChangeBlock> {
+        self.removed.blocks.iter()
+    }
+
+    /// Returns an iterator over files deleted by the changes (repo mode only).
+    pub fn deleted_paths(&self) -> impl Iterator<Item = &Path> {
+        self.removed.files.iter().map(|p| p.as_path())
+    }
+
+    /// Returns an iterator over (old, new) paths of files renamed by the changes (repo mode only).
+    pub fn renamed_paths(&self) -> impl Iterator<Item = (&Path, &Path)> {
+        self.removed
+            .renamed
+            .iter()
+            .map(|(old, new)| (old.as_path(), new.as_path()))
+    }
+}
+
+/// The kind of an [OnChangeViolation].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnChangeViolationKind {
+    /// A block changed, but its ThenChange target did not.
+    TargetNotChanged,
+    /// A block targets a block or file that was removed.
+    TargetRemoved,
+    /// A block targets a file using its path from before it was renamed.
+    TargetRenamed,
+    /// A block changed, but its ThenChange target block does not exist.
+    TargetBlockMissing,
 }
 
 #[derive(Debug)]
 pub struct OnChangeViolation<'a> {
     root_path: &'a Path,
+    kind: OnChangeViolationKind,
     block: &'a OnChangeBlock,
     target_file: &'a Path,
     target_block_name: Option<&'a str>,
+    /// New path of the target file if it was renamed.
+    renamed_to: Option<&'a Path>,
+    /// The target block, if it was found.
+    target_block: Option<&'a OnChangeBlock>,
+    /// Pattern of the glob target, if the target file was matched by a glob.
+    target_glob: Option<&'a str>,
+    /// Sync group of the block and the target block, if the target is a group member.
+    target_group: Option<&'a str>,
+    /// Number of targets that must change, if the block has a quantifier that was not met.
+    at_least: Option<u32>,
+    /// Blocks between the block and a transitive target, in order.
+    via: Vec<&'a OnChangeBlock>,
+    /// Reason the violation was waived, if it was.
+    waiver: Option<String>,
+}
+
+impl OnChangeViolationKind {
+    /// Returns a stable identifier for this kind, for use in machine-readable output.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::TargetNotChanged => "target_not_changed",
+            Self::TargetRemoved => "target_removed",
+            Self::TargetRenamed => "target_renamed",
+            Self::TargetBlockMissing => "target_block_missing",
+        }
+    }
+}
+
+impl<'a> OnChangeViolation<'a> {
+    /// Builds a violation of a target file, without any of the optional details.
+    fn new(
+        root_path: &'a Path,
+        kind: OnChangeViolationKind,
+        block: &'a OnChangeBlock,
+        target_file: &'a Path,
+    ) -> Self {
+        Self {
+            root_path,
+            kind,
+            block,
+            target_file,
+            target_block_name: None,
+            renamed_to: None,
+            target_block: None,
+            target_glob: None,
+            target_group: None,
+            at_least: None,
+            via: Vec::new(),
+            waiver: None,
+        }
+    }
+
+    pub fn kind(&self) -> OnChangeViolationKind {
+        self.kind
+    }
+
+    /// Returns the block whose ThenChange target was violated.
+    pub fn block(&self) -> &'a OnChangeBlock {
+        self.block
+    }
+
+    /// Returns the path of the target file, relative to the root path.
+    pub fn target_file(&self) -> &'a Path {
+        self.target_file
+    }
+
+    /// Returns the name of the target block, or None if the target is a file.
+    pub fn target_block_name(&self) -> Option<&'a str> {
+        self.target_block_name
+    }
+
+    /// Returns the new path of the target file if it was renamed.
+    pub fn renamed_to(&self) -> Option<&'a Path> {
+        self.renamed_to
+    }
+
+    /// Returns the target block, if it was found. This is only set for unchanged block
+    /// targets.
+    pub fn target_block(&self) -> Option<&'a OnChangeBlock> {
+        self.target_block
+    }
+
+    /// Returns the pattern of the glob target that matched the target file, if any. In
+    /// [GlobMode::Any], the target file is the pattern itself.
+    pub fn target_glob(&self) -> Option<&'a str> {
+        self.target_glob
+    }
+
+    /// Returns the sync group shared by the block and the target block, if the target is
+    /// another member of the block's group.
+    pub fn target_group(&self) -> Option<&'a str> {
+        self.target_group
+    }
+
+    /// Returns the number of targets that must change if the block has a quantifier (e.g.,
+    /// `any:`) that was not met. All of the block's targets are then alternatives.
+    pub fn at_least(&self) -> Option<u32> {
+        self.at_least
+    }
+
+    /// Returns the severity of the block. Violations with [Severity::Warn] do not fail the run.
+    pub fn severity(&self) -> Severity {
+        self.block.severity()
+    }
+
+    /// Returns the path through the dependency graph that required a transitive target to
+    /// change, i.e., the blocks between the block and the target. Empty for direct targets.
+    pub fn via(&self) -> &[&'a OnChangeBlock] {
+        &self.via
+    }
+
+    /// Returns the reason the violation was waived (e.g., by an `Onchg-Skip` commit message
+    /// trailer), if it was. Waived violations do not fail the run.
+    pub fn waiver(&self) -> Option<&str> {
+        self.waiver.as_deref()
+    }
+
+    /// Waives the violation with the given reason.
+    pub fn waive(&mut self, reason: &str) {
+        self.waiver = Some(reason.to_string());
+    }
 }
 
-impl<'a> ToString for OnChangeViolation<'a> {
-    fn to_string(&self) -> String {
-        if let Some(target_block_name) = self.target_block_name {
-            format!(
+impl<'a> OnChangeViolation<'a> {
+    /// Formats the violated target along with the block that caused the violation.
+    fn fmt_target(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(renamed_to) = self.renamed_to {
+            return write!(
+                f,
+                r#"block "{}" at {}:{} (targets file "{}" which was renamed to "{}")"#,
+                self.block.name(),
+                self.root_path.join(self.block.file()).display(),
+                self.block.end_line(),
+                self.target_file.display(),
+                renamed_to.display(),
+            );
+        }
+
+        if self.kind == OnChangeViolationKind::TargetRemoved {
+            let target = match self.target_block_name {
+                Some(target_block_name) => format!(
+                    r#"removed block "{}" in {}"#,
+                    target_block_name,
+                    self.root_path.join(self.target_file).display(),
+                ),
+                None => format!(
+                    r#"deleted file "{}""#,
+                    self.root_path.join(self.target_file).display(),
+                ),
+            };
+            return write!(
+                f,
+                r#"block "{}" at {}:{} (targets {})"#,
+                self.block.name(),
+                self.root_path.join(self.block.file()).display(),
+                self.block.end_line(),
+                target,
+            );
+        }
+
+        if self.kind == OnChangeViolationKind::TargetBlockMissing {
+            return write!(
+                f,
+                r#"block "{}" at {}:{} has non-existent ThenChange target "{}:{}""#,
+                self.block.name(),
+                self.root_path.join(self.block.file()).display(),
+                self.block.end_line(),
+                self.target_file.display(),
+                self.target_block_name.unwrap_or_default(),
+            );
+        }
+
+        if let Some(pattern) = self.target_glob {
+            let target = if self.target_file == Path::new(pattern) {
+                format!(r#"any file matching "{}""#, pattern)
+            } else {
+                format!(
+                    r#"file "{}" (matching "{}")"#,
+                    self.root_path.join(self.target_file).display(),
+                    pattern,
+                )
+            };
+            return write!(
+                f,
+                r#"{} (due to block "{}" at {}:{})"#,
+                target,
+                self.block.name(),
+                self.root_path.join(self.block.file()).display(),
+                self.block.start_line(),
+            );
+        }
+
+        if let (Some(group), Some(target_block)) = (self.target_group, self.target_block) {
+            return write!(
+                f,
+                r#"block "{}" at {}:{} in group "{}" (due to block "{}" at {}:{})"#,
+                target_block.name(),
+                self.root_path.join(self.target_file).display(),
+                target_block.start_line(),
+                group,
+                self.block.name(),
+                self.root_path.join(self.block.file()).display(),
+                self.block.start_line(),
+            );
+        }
+
+        if let Some(target_block) = self.target_block {
+            write!(
+                f,
+                r#"block "{}" at {}:{} (due to block "{}" at {}:{})"#,
+                target_block.name(),
+                self.root_path.join(self.target_file).display(),
+                target_block.start_line(),
+                self.block.name(),
+                self.root_path.join(self.block.file()).display(),
+                self.block.start_line(),
+            )
+        } else if let Some(target_block_name) = self.target_block_name {
+            write!(
+                f,
                 r#"block "{}" in {} (due to block "{}" at {}:{})"#,
                 target_block_name,
-                self.root_path.join(&self.target_file).display(),
+                self.root_path.join(self.target_file).display(),
                 self.block.name(),
-                self.root_path.join(&self.block.file()).display(),
+                self.root_path.join(self.block.file()).display(),
                 self.block.start_line(),
             )
         } else {
-            format!(
+            write!(
+                f,
                 r#"file "{}" (due to block "{}" at {}:{})"#,
-                self.root_path.join(&self.target_file).display(),
+                self.root_path.join(self.target_file).display(),
                 self.block.name(),
-                self.root_path.join(&self.block.file()).display(),
+                self.root_path.join(self.block.file()).display(),
                 self.block.start_line(),
             )
         }
     }
 }
 
+impl<'a> std::fmt::Display for OnChangeViolation<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_target(f)?;
+        if !self.via.is_empty() {
+            let via: Vec<String> = self
+                .via
+                .iter()
+                .map(|b| format!("{}:{}", b.file().display(), b.name()))
+                .collect();
+            write!(f, " (via {})", via.join(" -> "))?;
+        }
+        if let (Some(n), ThenChange::Targets(targets)) = (self.at_least, self.block.then_change()) {
+            let alternatives: Vec<String> = targets.iter().map(|t| t.to_string()).collect();
+            write!(
+                f,
+                " (at least {} of {} must change: {})",
+                n,
+                targets.len(),
+                alternatives.join(", ")
+            )?;
+        }
+        match (self.block.owner(), self.block.reason()) {
+            (Some(owner), Some(reason)) => write!(f, " [owner: {}, reason: {}]", owner, reason),
+            (Some(owner), None) => write!(f, " [owner: {}]", owner),
+            (None, Some(reason)) => write!(f, " [reason: {}]", reason),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
 impl Parser {
     /// Builds a parser from staged files in a Git repo.
-    pub fn from_git_repo<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let path = path.as_ref();
+    pub fn from_git_repo<P: AsRef<Path>>(path: P) -> Result<Self, Diagnostics> {
+        let config = Config::without_files(path.as_ref(), &Settings::default())?;
+        Self::from_git_repo_with_config(path, &config)
+    }
 
-        let s = std::time::Instant::now();
+    /// Same as [Parser::from_git_repo], but with the overrides of the given config. Config files
+    /// are read from the index, like the staged files (see [Config::load_from_git]).
+    pub fn from_git_repo_with_config<P: AsRef<Path>>(
+        path: P,
+        config: &Config,
+    ) -> Result<Self, Diagnostics> {
+        Self::from_git_changes(path.as_ref(), Changes::Staged, config)
+    }
 
-        #[cfg(feature = "git")]
-        let (staged_files, staged_hunks) = {
-            let repo = git2::Repository::open(path)?;
-            (repo.get_staged_files()?, repo.get_staged_hunks()?)
-        };
-        #[cfg(not(feature = "git"))]
-        let (staged_files, staged_hunks) = {
-            let cli = crate::git::cli::Cli { repo_path: path };
-            (cli.get_staged_files()?, cli.get_staged_hunks()?)
-        };
+    /// Builds a parser from the files changed in a revision range of a Git repo.
+    ///
+    /// The range can be one of `<base>..<head>`, `<base>...<head>` (diff from the merge-base),
+    /// or `<base>` (same as `<base>...HEAD`). File content is read from the head revision.
+    pub fn from_git_range<P: AsRef<Path>>(path: P, range: &str) -> Result<Self, Diagnostics> {
+        let config = Config::without_files(path.as_ref(), &Settings::default())?;
+        Self::from_git_range_with_config(path, range, &config)
+    }
 
-        log::info!("Got staged files and hunks in {:?}", s.elapsed());
+    /// Same as [Parser::from_git_range], but with the overrides of the given config. Config
+    /// files are read from the head revision, like the changed files.
+    pub fn from_git_range_with_config<P: AsRef<Path>>(
+        path: P,
+        range: &str,
+        config: &Config,
+    ) -> Result<Self, Diagnostics> {
+        let changes = Changes::from_range(range).map_err(Error::from)?;
+        Self::from_git_changes(path.as_ref(), changes, config)
+    }
 
-        Self::from_files_internal(staged_files.iter(), path, |path, root_path| {
-            let hunks = staged_hunks.get(&path).map(|v| v.as_slice());
-            if let Some(hunks) = hunks {
-                File::parse(path, root_path, Some(hunks))
-            } else {
-                // If there are no staged hunks for this file, we actually don't need to parse it at all :)
-                Ok(None)
-            }
-        })
+    /// Builds a parser from the files changed by a single (non-root) commit in a Git repo.
+    pub fn from_git_commit<P: AsRef<Path>>(path: P, id: &str) -> Result<Self, Diagnostics> {
+        let config = Config::without_files(path.as_ref(), &Settings::default())?;
+        Self::from_git_commit_with_config(path, id, &config)
     }
 
-    // For each block in the set, check the ThenChange target(s) and ensure that they have also changed.
-    // This will happen _recursively_ for all ThenChange targets. If a violation is detected, it will
-    // be returned.
-    fn validate_changed_files_and_blocks<'a, 'b>(
-        &'a self,
-        files_changed: HashSet<&Path>,
-        blocks_changed: Vec<&'a OnChangeBlock>,
-        targetable_blocks_changed: HashSet<(&Path, &'a str)>,
-    ) -> Vec<OnChangeViolation<'a>>
-    where
-        'a: 'b,
-    {
-        let mut violations = Vec::new();
+    /// Same as [Parser::from_git_commit], but with the overrides of the given config. Config
+    /// files are read from the commit, like the changed files.
+    pub fn from_git_commit_with_config<P: AsRef<Path>>(
+        path: P,
+        id: &str,
+        config: &Config,
+    ) -> Result<Self, Diagnostics> {
+        let changes = Changes::Range {
+            base: format!("{}^", id),
+            head: id.to_string(),
+            merge_base: false,
+        };
+        Self::from_git_changes(path.as_ref(), changes, config)
+    }
 
-        for block in blocks_changed {
-            let blocks_to_check = block.get_then_change_targets_as_keys();
-            for (then_change_file, then_change_block_name) in blocks_to_check {
-                if let Some(then_change_block_name) = then_change_block_name {
-                    if !targetable_blocks_changed
-                        .contains(&(then_change_file, then_change_block_name))
-                    {
-                        // TODO(aksiksi): We currently only report the unchanged block name. In order to
-                        // be able to report its location, we'll need to lazily parse the then change file
-                        // at this point. We might not find the block, in which case we can report an error.
-                        // If we do find the block, we can use its location as part of the returned violation.
-                        violations.push(OnChangeViolation {
-                            root_path: &self.root_path,
-                            block,
-                            target_file: then_change_file,
-                            target_block_name: Some(then_change_block_name),
-                        });
-                    }
-                } else if !files_changed.contains(then_change_file) {
-                    violations.push(OnChangeViolation {
-                        root_path: &self.root_path,
-                        block,
-                        target_file: then_change_file,
-                        target_block_name: None,
-                    });
-                }
-            }
-        }
+    /// Builds a parser from a unified diff (e.g., the output of `git diff` or `diff -u`).
+    ///
+    /// The diff is used as the source of changed hunks, while file content is read from disk
+    /// relative to the given path. File paths in the diff must be relative to this path; the
+    /// "a/" and "b/" prefixes used by Git are stripped.
+    pub fn from_patch<P: AsRef<Path>>(path: P, patch: &str) -> Result<Self, Diagnostics> {
+        let config = Config::load(path.as_ref(), &Settings::default())?;
+        Self::from_patch_with_config(path, patch, &config)
+    }
 
-        violations
+    /// Same as [Parser::from_patch], but uses the given config instead of the config file at
+    /// the root path.
+    pub fn from_patch_with_config<P: AsRef<Path>>(
+        path: P,
+        patch: &str,
+        config: &Config,
+    ) -> Result<Self, Diagnostics> {
+        let path = path.as_ref();
+        let diff = UnifiedDiff::parse(path, patch).map_err(|e| Error::InvalidPatch {
+            message: e.to_string(),
+        })?;
+        Self::from_changes(path, &diff, &Changes::Patch, config)
     }
 
-    pub fn validate_git_repo(&self) -> Result<Vec<OnChangeViolation<'_>>> {
-        let path = self.root_path.as_path();
+    fn from_git_changes(
+        path: &Path,
+        changes: Changes,
+        config: &Config,
+    ) -> Result<Self, Diagnostics> {
+        let repo = crate::git::open(path).map_err(Error::from)?;
+        // Resolve the revisions (and merge-base) once, rather than on every read.
+        let changes = repo.resolve(&changes).map_err(Error::from)?;
+        // Config files are read from the revision being checked, like every other file.
+        let config = config.for_changes(&repo, &changes)?;
+        Self::from_changes(path, &repo, &changes, &config)
+    }
 
-        if self.files.len() == 0 {
-            return Ok(Vec::new());
-        }
+    fn from_changes(
+        path: &Path,
+        repo: &impl Repo,
+        changes: &Changes,
+        config: &Config,
+    ) -> Result<Self, Diagnostics> {
+        let s = std::time::Instant::now();
 
-        #[cfg(feature = "git")]
-        let staged_files = {
-            let repo = git2::Repository::open(path)?;
-            repo.get_staged_files()?
-        };
-        #[cfg(not(feature = "git"))]
-        let staged_files = {
-            let cli = crate::git::cli::Cli { repo_path: path };
-            cli.get_staged_files()?
-        };
+        let (changed_files, changed_hunks, deleted_files, renamed_files) = (
+            repo.get_changed_files(changes).map_err(Error::from)?,
+            repo.get_changed_hunks(changes).map_err(Error::from)?,
+            repo.get_deleted_files(changes).map_err(Error::from)?,
+            repo.get_renamed_files(changes).map_err(Error::from)?,
+        );
+        let renamed_from: HashMap<&Path, &Path> = renamed_files
+            .iter()
+            .map(|(old, new)| (new.as_path(), old.as_path()))
+            .collect();
 
-        let s = std::time::Instant::now();
+        log::info!("Got changed files and hunks in {:?}", s.elapsed());
 
-        let files_changed: HashSet<&Path> =
-            HashSet::from_iter(staged_files.iter().map(|p| p.as_path()));
-        let mut blocks_changed: Vec<&OnChangeBlock> = Vec::new();
-        let mut targetable_blocks_changed: HashSet<(&Path, &str)> = HashSet::new();
+        // Read all changed files, along with their old versions, in one go.
+        let paths: Vec<PathBuf> = changed_hunks.keys().cloned().collect();
+        let contents = repo
+            .get_file_contents(changes, &paths)
+            .map_err(Error::from)?;
+        let old_paths: Vec<PathBuf> = paths
+            .iter()
+            .map(|p| {
+                renamed_from
+                    .get(p.as_path())
+                    .copied()
+                    .unwrap_or(p)
+                    .to_owned()
+            })
+            .chain(deleted_files.iter().cloned())
+            .collect();
+        let old_contents = repo
+            .get_old_file_contents(changes, &old_paths)
+            .map_err(Error::from)?;
 
-        for path in &staged_files {
-            // Note that _all_ blocks in the file have changed, because we already filtered
-            // blocks out during file parsing using staged hunks.
-            let changed_blocks: Vec<&OnChangeBlock> =
-                if let Some(blocks) = self.on_change_blocks_in_file(path) {
-                    blocks.collect()
+        // Target files must exist on the new side of the changes, not (only) on disk.
+        let new_files: HashSet<PathBuf> = if paths.is_empty() {
+            HashSet::new()
+        } else {
+            repo.list_files(changes)
+                .map_err(Error::from)?
+                .into_iter()
+                .collect()
+        };
+
+        let removed_blocks = RefCell::new(Vec::new());
+        let (mut parser, diagnostics) = Self::from_files_internal(
+            changed_files.iter(),
+            path,
+            config,
+            |path, _root_path, marker| {
+                let hunks = changed_hunks.get(&path).map(|v| v.as_slice());
+                if let Some(hunks) = hunks {
+                    // The file changed, so it must exist on the new side.
+                    let Some(content) = contents.get(&path) else {
+                        return Err(Error::Git(
+                            format!("failed to read changed file {}", path.display()).into(),
+                        ));
+                    };
+                    let old_path = renamed_from.get(path.as_path()).copied().unwrap_or(&path);
+                    removed_blocks
+                        .borrow_mut()
+                        .extend(Self::find_removed_blocks(
+                            config,
+                            old_path,
+                            old_contents.get(old_path).map(|c| c.as_slice()),
+                            Some((&path, content, hunks, marker)),
+                        )?);
+                    File::parse_from_bytes(path, content, Some(hunks), marker, |p| {
+                        new_files.contains(p)
+                    })
                 } else {
-                    continue;
-                };
-            for block in changed_blocks {
-                blocks_changed.push(block);
-                if block.is_targetable() {
-                    targetable_blocks_changed.insert((&path, block.name()));
+                    // If there are no changed hunks for this file, we actually don't need to parse it at all :)
+                    Ok(None)
                 }
-            }
-        }
+            },
+        )?;
+        diagnostics.into_result()?;
 
-        log::info!("Found changed blocks in {:?}", s.elapsed());
+        let mut removed_blocks = removed_blocks.into_inner();
+        for path in &deleted_files {
+            removed_blocks.extend(Self::find_removed_blocks(
+                config,
+                path,
+                old_contents.get(path).map(|c| c.as_slice()),
+                None,
+            )?);
+        }
+        parser.removed = Removed {
+            blocks: removed_blocks,
+            files: deleted_files,
+            renamed: renamed_files,
+            dependents: Vec::new(),
+        };
+        parser.removed.dependents =
+            Self::find_removed_dependents(repo, changes, config, &parser.removed, &changed_files)?;
+        parser.target_blocks = Self::parse_unchanged_targets(repo, changes, config, &parser)?;
+        parser.glob_mode = config.glob_targets;
+        parser.transitive = config.transitive;
+        // Transitive blocks follow their glob targets, whatever the glob mode is.
+        let any_transitive = parser
+            .files
+            .values()
+            .flat_map(|f| f.blocks.iter())
+            .chain(parser.removed.blocks.iter())
+            .any(|b| parser.is_transitive(b));
+        if parser.glob_mode == GlobMode::All || any_transitive {
+            parser.glob_target_files = Self::find_glob_target_files(repo, changes, &parser)?;
+        }
+        parser.group_members = Self::find_group_members(repo, changes, config, &parser)?;
+        Self::parse_transitive_targets(repo, changes, config, &mut parser)?;
+
+        parser.changed_files = Some(changed_files);
+        Ok(parser)
+    }
+
+    /// Returns the blocks that were removed from the given file by the changes.
+    ///
+    /// The old path differs from the new one if the file was renamed, and the old content is
+    /// None if the file is new. The new path, content, hunks and marker pattern are None if
+    /// the file was deleted, in which case all blocks in the old version of the file are
+    /// considered removed.
+    fn find_removed_blocks(
+        config: &Config,
+        old_path: &Path,
+        old_content: Option<&[u8]>,
+        new: Option<(&Path, &[u8], &[Hunk], &Marker)>,
+    ) -> Result<Vec<OnChangeBlock>> {
+        let old_marker = match config.marker(old_path)? {
+            Some(marker) => marker,
+            // The old version was excluded, so it did not have any blocks.
+            None => return Ok(Vec::new()),
+        };
+        let Some(old_content) = old_content else {
+            // This is a new file.
+            return Ok(Vec::new());
+        };
+        let old_path = Arc::new(old_path.to_owned());
+        let old_blocks = match File::parse_internal(old_path.clone(), old_content, &old_marker) {
+            Ok(blocks) => blocks,
+            Err(e) => {
+                // There is nothing we can do if the old version is invalid.
+                log::warn!(
+                    "Failed to parse old version of {}: {}",
+                    old_path.display(),
+                    e
+                );
+                return Ok(Vec::new());
+            }
+        };
+        match new {
+            Some((path, new_content, hunks, marker)) => {
+                // Errors in the new version are reported when it is parsed.
+                let (new_blocks, _) =
+                    File::parse_blocks(Arc::new(path.to_owned()), new_content, marker);
+                Ok(File::find_removed_blocks(old_blocks, &new_blocks, hunks))
+            }
+            None => Ok(old_blocks),
+        }
+    }
+
+    /// Parses all files containing a block target of a changed or removed block that is not
+    /// itself changed, so that violations can report where the target block is. Files
+    /// targeted by a block pattern are always parsed, since the pattern is expanded to all of
+    /// the matching blocks in the file.
+    ///
+    /// Deleted files and old paths of renamed files are skipped, since their targets are
+    /// reported as removed or renamed. A file that cannot be read is treated as having no
+    /// blocks.
+    fn parse_unchanged_targets(
+        repo: &impl Repo,
+        changes: &Changes,
+        config: &Config,
+        parser: &Parser,
+    ) -> Result<BTreeMap<PathBuf, Vec<OnChangeBlock>>> {
+        let is_changed = |file: &Path, name: &str| {
+            parser
+                .files
+                .get(file)
+                .is_some_and(|f| f.blocks.iter().any(|b| b.name_raw() == Some(name)))
+                || parser
+                    .removed
+                    .blocks
+                    .iter()
+                    .any(|b| b.file() == file && b.name_raw() == Some(name))
+        };
+        let mut paths = BTreeSet::new();
+        let changed_blocks = parser
+            .files
+            .values()
+            .flat_map(|f| f.blocks.iter())
+            .chain(parser.removed.blocks.iter());
+        for block in changed_blocks {
+            let targets = block
+                .get_then_change_targets_as_keys()
+                .filter_map(|(file, name)| name.map(|name| (file, name)));
+            for (file, name) in targets {
+                if parser.removed.contains_target(file, None) || is_changed(file, name) {
+                    continue;
+                }
+                paths.insert(file.to_owned());
+            }
+            for (file, _) in block.block_pattern_targets() {
+                if !parser.removed.contains_target(file, None) {
+                    paths.insert(file.to_owned());
+                }
+            }
+        }
+
+        Self::parse_target_files(repo, changes, config, paths)
+    }
+
+    /// Parses all blocks in the given target files on the new side of the changes. Excluded
+    /// and missing files have no blocks.
+    fn parse_target_files(
+        repo: &impl Repo,
+        changes: &Changes,
+        config: &Config,
+        paths: impl IntoIterator<Item = PathBuf>,
+    ) -> Result<BTreeMap<PathBuf, Vec<OnChangeBlock>>> {
+        let mut markers = BTreeMap::new();
+        let mut blocks = BTreeMap::new();
+        for path in paths {
+            match config.marker(&path)? {
+                Some(marker) => {
+                    markers.insert(path, marker);
+                }
+                None => {
+                    blocks.insert(path, Vec::new());
+                }
+            }
+        }
+        let paths: Vec<PathBuf> = markers.keys().cloned().collect();
+        let mut contents = repo.get_file_contents(changes, &paths)?;
+        for (path, marker) in markers {
+            let file_blocks = match contents.remove(&path) {
+                // Errors in the target file are not caused by the changes, so they are ignored.
+                Some(content) => File::parse_blocks(Arc::new(path.clone()), &content, &marker).0,
+                None => Vec::new(),
+            };
+            blocks.insert(path, file_blocks);
+        }
+        Ok(blocks)
+    }
+
+    /// Parses all files reachable through the targets of transitive changed or removed
+    /// blocks, so that their own targets can be followed.
+    fn parse_transitive_targets(
+        repo: &impl Repo,
+        changes: &Changes,
+        config: &Config,
+        parser: &mut Parser,
+    ) -> Result<()> {
+        loop {
+            let missing: BTreeSet<PathBuf> = parser
+                .files
+                .values()
+                .flat_map(|f| f.blocks.iter())
+                .chain(parser.removed.blocks.iter())
+                .filter(|b| parser.is_transitive(b))
+                .flat_map(|b| parser.transitive_targets(b).1)
+                .collect();
+            if missing.is_empty() {
+                return Ok(());
+            }
+            let blocks = Self::parse_target_files(repo, changes, config, missing)?;
+            parser.target_blocks.extend(blocks);
+        }
+    }
+
+    fn is_transitive(&self, block: &OnChangeBlock) -> bool {
+        self.transitive || block.is_transitive()
+    }
+
+    /// Walks the targets of the given block, and returns every block reachable through them
+    /// other than its direct targets, along with the blocks on the path to it. Target files
+    /// that have not been parsed yet are returned separately.
+    ///
+    /// A file (or glob) target is satisfied by any change to the file, so the blocks in it
+    /// are not required to change, but their own targets are followed.
+    #[allow(clippy::type_complexity)]
+    fn transitive_targets<'a>(
+        &'a self,
+        block: &'a OnChangeBlock,
+    ) -> (
+        Vec<(Vec<&'a OnChangeBlock>, &'a OnChangeBlock)>,
+        BTreeSet<PathBuf>,
+    ) {
+        let mut reachable = Vec::new();
+        let mut missing = BTreeSet::new();
+        // Blocks are keyed by their start line, since a file target reaches unnamed blocks.
+        let mut visited: HashSet<(&Path, u32)> =
+            HashSet::from([(block.file(), block.start_line())]);
+        let mut required: HashSet<(&Path, u32)> = visited.clone();
+        let mut queue = VecDeque::from([(block, Vec::new())]);
+        while let Some((current, via)) = queue.pop_front() {
+            let keys = current
+                .get_then_change_targets_as_keys()
+                .map(|(file, name)| (file, name, None));
+            let patterns = current
+                .block_pattern_targets()
+                .map(|(file, pattern)| (file, None, Some(pattern)));
+            let globs = current.glob_targets().flat_map(|glob| {
+                self.glob_target_files
+                    .get(glob.pattern())
+                    .into_iter()
+                    .flatten()
+                    .map(|file| (file.as_path(), None, None))
+            });
+            for (file, name, pattern) in keys.chain(patterns).chain(globs) {
+                let Some(blocks) = self.target_blocks.get(file) else {
+                    missing.insert(file.to_owned());
+                    continue;
+                };
+                let whole_file = name.is_none() && pattern.is_none();
+                let targets = blocks.iter().filter(|b| match (b.name_raw(), pattern) {
+                    _ if whole_file => true,
+                    (Some(n), Some(pattern)) => pattern.is_match(n),
+                    (Some(n), None) => Some(n) == name,
+                    (None, _) => false,
+                });
+                for target in targets {
+                    let key = (target.file(), target.start_line());
+                    if !whole_file && required.insert(key) && !via.is_empty() {
+                        reachable.push((via.clone(), target));
+                    }
+                    if !visited.insert(key) {
+                        continue;
+                    }
+                    let mut via = via.clone();
+                    via.push(target);
+                    queue.push_back((target, via));
+                }
+            }
+        }
+        (reachable, missing)
+    }
+
+    /// Returns all files on the new side of the changes that match each glob target of a
+    /// changed or removed block, keyed by pattern.
+    fn find_glob_target_files(
+        repo: &impl Repo,
+        changes: &Changes,
+        parser: &Parser,
+    ) -> Result<BTreeMap<String, Vec<PathBuf>>> {
+        let globs: Vec<&TargetGlob> = parser
+            .files
+            .values()
+            .flat_map(|f| f.blocks.iter())
+            .chain(parser.removed.blocks.iter())
+            .flat_map(|b| b.glob_targets())
+            .collect();
+        if globs.is_empty() {
+            return Ok(BTreeMap::new());
+        }
+        let files = repo.list_files(changes)?;
+        Ok(globs
+            .into_iter()
+            .map(|glob| {
+                let matches = files.iter().filter(|f| glob.is_match(f)).cloned().collect();
+                (glob.pattern().to_string(), matches)
+            })
+            .collect())
+    }
+
+    /// Finds all blocks on the new side of the changes that are in the same sync group as a
+    /// changed or removed block, keyed by group.
+    ///
+    /// As with [Parser::find_removed_dependents], only files that mention the name of a group
+    /// are parsed.
+    fn find_group_members(
+        repo: &impl Repo,
+        changes: &Changes,
+        config: &Config,
+        parser: &Parser,
+    ) -> Result<BTreeMap<String, Vec<OnChangeBlock>>> {
+        let mut members: BTreeMap<String, Vec<OnChangeBlock>> = parser
+            .files
+            .values()
+            .flat_map(|f| f.blocks.iter())
+            .chain(parser.removed.blocks.iter())
+            .filter_map(|b| b.group())
+            .map(|g| (g.to_string(), Vec::new()))
+            .collect();
+        if members.is_empty() {
+            return Ok(members);
+        }
+
+        let needles: Vec<String> = members.keys().cloned().collect();
+        let paths = repo.find_files_containing(changes, &needles)?;
+        for (path, content) in repo.get_file_contents(changes, &paths)? {
+            let marker = match config.marker(&path)? {
+                Some(marker) => marker,
+                None => continue,
+            };
+            // Errors in unchanged files are not caused by the changes, so they are ignored.
+            let (blocks, _) = File::parse_blocks(Arc::new(path), &content, &marker);
+            for block in blocks {
+                if let Some(group) = block.group().and_then(|g| members.get_mut(g)) {
+                    group.push(block);
+                }
+            }
+        }
+        Ok(members)
+    }
+
+    /// Finds all blocks in the new version of the repo that target a removed block, or a deleted
+    /// or renamed file.
+    ///
+    /// To avoid parsing the entire repo, we only look at changed files (which can contain
+    /// same-file targets) and files that mention the name of a file with removed blocks.
+    fn find_removed_dependents(
+        repo: &impl Repo,
+        changes: &Changes,
+        config: &Config,
+        removed: &Removed,
+        changed_files: &[PathBuf],
+    ) -> Result<Vec<OnChangeBlock>> {
+        if removed.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let s = std::time::Instant::now();
+
+        let removed_paths = removed
+            .blocks
+            .iter()
+            .map(|b| b.file())
+            .chain(removed.files.iter().map(|p| p.as_path()))
+            .chain(removed.renamed.iter().map(|(old, _)| old.as_path()));
+        let mut needles: Vec<String> = removed_paths
+            .filter_map(|p| p.file_name())
+            .map(|n| n.to_string_lossy().to_string())
+            .collect();
+        needles.sort();
+        needles.dedup();
+
+        let mut candidates: Vec<PathBuf> = repo.find_files_containing(changes, &needles)?;
+        candidates.extend(changed_files.iter().cloned());
+        candidates.sort();
+        candidates.dedup();
+
+        let mut dependents = Vec::new();
+        for (path, content) in repo.get_file_contents(changes, &candidates)? {
+            let marker = match config.marker(&path)? {
+                Some(marker) => marker,
+                None => continue,
+            };
+            let blocks = match File::parse_internal(Arc::new(path.clone()), &content, &marker) {
+                Ok(blocks) => blocks,
+                Err(e) => {
+                    log::warn!("Failed to parse {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            for block in blocks {
+                if block
+                    .get_then_change_targets_as_keys()
+                    .any(|(file, block_name)| removed.contains_target(file, block_name))
+                {
+                    dependents.push(block);
+                }
+            }
+        }
+
+        log::info!(
+            "Found {} dependents of removed blocks in {:?}",
+            dependents.len(),
+            s.elapsed()
+        );
+
+        Ok(dependents)
+    }
+
+    // For each block in the set, check the ThenChange target(s) and ensure that they have also changed.
+    // This will happen _recursively_ for all ThenChange targets. If a violation is detected, it will
+    // be returned.
+    fn validate_changed_files_and_blocks<'a, 'b>(
+        &'a self,
+        files_changed: HashSet<&Path>,
+        blocks_changed: Vec<&'a OnChangeBlock>,
+        targetable_blocks_changed: HashSet<(&Path, &'a str)>,
+    ) -> Vec<OnChangeViolation<'a>>
+    where
+        'a: 'b,
+    {
+        let mut violations = Vec::new();
+        // Changed group members on the new side, keyed by location since they can be unnamed.
+        let group_members_changed: HashSet<(&Path, u32)> = self
+            .files
+            .values()
+            .flat_map(|f| f.blocks.iter())
+            .filter(|b| b.group().is_some())
+            .map(|b| (b.file(), b.start_line()))
+            .collect();
+        let mut groups_checked = HashSet::new();
+
+        for &block in &blocks_changed {
+            // Each unchanged member of a group is only reported once, due to the first changed
+            // member.
+            if let Some(group) = block.group().filter(|g| groups_checked.insert(*g)) {
+                let members = self.group_members.get(group).into_iter().flatten();
+                for member in members {
+                    if group_members_changed.contains(&(member.file(), member.start_line())) {
+                        continue;
+                    }
+                    violations.push(OnChangeViolation {
+                        target_block_name: member.name_raw(),
+                        target_block: Some(member),
+                        target_group: Some(group),
+                        ..OnChangeViolation::new(
+                            &self.root_path,
+                            OnChangeViolationKind::TargetNotChanged,
+                            block,
+                            member.file(),
+                        )
+                    });
+                }
+            }
+
+            // Targets that did not cause any violations, for blocks with a quantifier.
+            let start = violations.len();
+            let mut satisfied = 0;
+
+            let blocks_to_check = block.get_then_change_targets_as_keys();
+            for (then_change_file, then_change_block_name) in blocks_to_check {
+                let before = violations.len();
+                if let Some(then_change_block_name) = then_change_block_name {
+                    if !targetable_blocks_changed
+                        .contains(&(then_change_file, then_change_block_name))
+                    {
+                        // Target files are only missing from the map if they were deleted or
+                        // renamed, in which case the target block's location is unknown.
+                        let target_blocks = self.target_blocks.get(then_change_file);
+                        let target_block = target_blocks.and_then(|blocks| {
+                            blocks
+                                .iter()
+                                .find(|b| b.is_targetable() && b.name() == then_change_block_name)
+                        });
+                        let kind = if target_blocks.is_some() && target_block.is_none() {
+                            OnChangeViolationKind::TargetBlockMissing
+                        } else {
+                            OnChangeViolationKind::TargetNotChanged
+                        };
+                        violations.push(OnChangeViolation {
+                            target_block_name: Some(then_change_block_name),
+                            target_block,
+                            ..OnChangeViolation::new(&self.root_path, kind, block, then_change_file)
+                        });
+                    }
+                } else if !files_changed.contains(then_change_file) {
+                    violations.push(OnChangeViolation::new(
+                        &self.root_path,
+                        OnChangeViolationKind::TargetNotChanged,
+                        block,
+                        then_change_file,
+                    ));
+                }
+                if violations.len() == before {
+                    satisfied += 1;
+                }
+            }
+
+            for (then_change_file, pattern) in block.block_pattern_targets() {
+                let target_blocks = match self.target_blocks.get(then_change_file) {
+                    Some(blocks) => blocks,
+                    // The target file could not be read (or was removed), so the matching
+                    // blocks are unknown.
+                    None => {
+                        if files_changed.contains(then_change_file) {
+                            satisfied += 1;
+                        } else {
+                            violations.push(OnChangeViolation {
+                                target_block_name: Some(pattern.pattern()),
+                                ..OnChangeViolation::new(
+                                    &self.root_path,
+                                    OnChangeViolationKind::TargetNotChanged,
+                                    block,
+                                    then_change_file,
+                                )
+                            });
+                        }
+                        continue;
+                    }
+                };
+                let before = violations.len();
+                let mut matched = target_blocks
+                    .iter()
+                    .filter(|b| b.name_raw().is_some_and(|name| pattern.is_match(name)))
+                    .peekable();
+                if matched.peek().is_none() {
+                    violations.push(OnChangeViolation {
+                        target_block_name: Some(pattern.pattern()),
+                        ..OnChangeViolation::new(
+                            &self.root_path,
+                            OnChangeViolationKind::TargetBlockMissing,
+                            block,
+                            then_change_file,
+                        )
+                    });
+                }
+                for target_block in matched {
+                    if targetable_blocks_changed.contains(&(then_change_file, target_block.name()))
+                    {
+                        continue;
+                    }
+                    violations.push(OnChangeViolation {
+                        target_block_name: Some(target_block.name()),
+                        target_block: Some(target_block),
+                        ..OnChangeViolation::new(
+                            &self.root_path,
+                            OnChangeViolationKind::TargetNotChanged,
+                            block,
+                            then_change_file,
+                        )
+                    });
+                }
+                if violations.len() == before {
+                    satisfied += 1;
+                }
+            }
+
+            for glob in block.glob_targets() {
+                let unchanged: Vec<&Path> = match self.glob_mode {
+                    GlobMode::Any if files_changed.iter().any(|f| glob.is_match(f)) => Vec::new(),
+                    // The glob itself is reported, since any matching file would do.
+                    GlobMode::Any => vec![Path::new(glob.pattern())],
+                    GlobMode::All => self
+                        .glob_target_files
+                        .get(glob.pattern())
+                        .into_iter()
+                        .flatten()
+                        .map(|f| f.as_path())
+                        .filter(|f| !files_changed.contains(f))
+                        .collect(),
+                };
+                for &target_file in &unchanged {
+                    violations.push(OnChangeViolation {
+                        target_glob: Some(glob.pattern()),
+                        ..OnChangeViolation::new(
+                            &self.root_path,
+                            OnChangeViolationKind::TargetNotChanged,
+                            block,
+                            target_file,
+                        )
+                    });
+                }
+                if unchanged.is_empty() {
+                    satisfied += 1;
+                }
+            }
+
+            if let Quantifier::AtLeast(n) = block.quantifier() {
+                if satisfied >= n as usize {
+                    violations.truncate(start);
+                } else {
+                    for v in &mut violations[start..] {
+                        v.at_least = Some(n);
+                    }
+                }
+            }
+        }
+
+        // Blocks reachable from transitive blocks must change too. Each one is only reported
+        // once, and not at all if it is already reported as a direct target.
+        let mut reported: HashSet<(&Path, &str)> = violations
+            .iter()
+            .filter_map(|v| Some((v.target_file, v.target_block_name?)))
+            .collect();
+        for &block in blocks_changed.iter().filter(|b| self.is_transitive(b)) {
+            for (via, target) in self.transitive_targets(block).0 {
+                let key = (target.file(), target.name());
+                if targetable_blocks_changed.contains(&key) || !reported.insert(key) {
+                    continue;
+                }
+                violations.push(OnChangeViolation {
+                    target_block_name: Some(target.name()),
+                    target_block: Some(target),
+                    via,
+                    ..OnChangeViolation::new(
+                        &self.root_path,
+                        OnChangeViolationKind::TargetNotChanged,
+                        block,
+                        target.file(),
+                    )
+                });
+            }
+        }
+
+        violations
+    }
+
+    /// Validates the changes this parser was built from (staged changes by default) and
+    /// returns any violations found.
+    ///
+    /// This also works for parsers built from a patch, in which case Git is not used.
+    pub fn validate_git_repo(&self) -> Result<Vec<OnChangeViolation<'_>>> {
+        let path = self.root_path.as_path();
+
+        if self.files.is_empty() && self.removed.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let queried_files;
+        let staged_files: &[PathBuf] = match &self.changed_files {
+            Some(files) => files,
+            None => {
+                queried_files = crate::git::open(path)?.get_changed_files(&Changes::Staged)?;
+                &queried_files
+            }
+        };
+
+        let s = std::time::Instant::now();
+
+        // Deleted files count as changed, while a pure rename (i.e., a renamed file that was
+        // not parsed because it has no hunks) does not change the content of the file.
+        let files_changed: HashSet<&Path> = HashSet::from_iter(
+            staged_files
+                .iter()
+                .chain(self.removed.files.iter())
+                .map(|p| p.as_path())
+                .filter(|p| {
+                    self.files.contains_key(*p)
+                        || !self.removed.renamed.iter().any(|(_, new)| new == p)
+                }),
+        );
+        let mut blocks_changed: Vec<&OnChangeBlock> = Vec::new();
+        let mut targetable_blocks_changed: HashSet<(&Path, &str)> = HashSet::new();
+
+        for path in staged_files {
+            // Note that _all_ blocks in the file have changed, because we already filtered
+            // blocks out during file parsing using staged hunks.
+            let changed_blocks: Vec<&OnChangeBlock> =
+                if let Some(blocks) = self.on_change_blocks_in_file(path) {
+                    blocks.collect()
+                } else {
+                    continue;
+                };
+            for block in changed_blocks {
+                blocks_changed.push(block);
+                if block.is_targetable() {
+                    targetable_blocks_changed.insert((path, block.name()));
+                }
+            }
+        }
+
+        // Removed blocks count as changed, so their ThenChange targets must change too.
+        for block in &self.removed.blocks {
+            blocks_changed.push(block);
+            if block.is_targetable() {
+                targetable_blocks_changed.insert((block.file(), block.name()));
+            }
+        }
+
+        log::info!("Found changed blocks in {:?}", s.elapsed());
 
         let s = std::time::Instant::now();
 
-        let violations = self.validate_changed_files_and_blocks(
+        let mut violations = self.validate_changed_files_and_blocks(
             files_changed,
             blocks_changed,
             targetable_blocks_changed,
         );
 
+        // Blocks that still target a removed block, or a deleted or renamed file are always
+        // a violation.
+        for block in &self.removed.dependents {
+            for (target_file, target_block_name) in block.get_then_change_targets_as_keys() {
+                if !self.removed.contains_target(target_file, target_block_name) {
+                    continue;
+                }
+                let renamed_to = self.removed.renamed_to(target_file);
+                let kind = if renamed_to.is_some() {
+                    OnChangeViolationKind::TargetRenamed
+                } else {
+                    OnChangeViolationKind::TargetRemoved
+                };
+                // A block target in a deleted file is reported as a file target.
+                let target_block_name = target_block_name
+                    .filter(|_| !self.removed.files.iter().any(|f| f == target_file));
+                violations.push(OnChangeViolation {
+                    target_block_name,
+                    renamed_to,
+                    ..OnChangeViolation::new(&self.root_path, kind, block, target_file)
+                });
+            }
+        }
+
+        // Blocks that are allowed to diverge waive the violations on either side. Expired
+        // suppressions do not, and are reported by expired_suppressions.
+        let today = Local::now().date_naive();
+        for v in &mut violations {
+            let suppression = [Some(v.block), v.target_block]
+                .into_iter()
+                .flatten()
+                .filter_map(|b| b.suppression())
+                .find(|s| !s.is_expired(today));
+            if let Some(suppression) = suppression {
+                v.waive(&suppression.reason());
+            }
+        }
+
         log::info!("Validated changed files and blocks in {:?}", s.elapsed());
 
         Ok(violations)
@@ -485,6 +1807,11 @@ mod test {
         assert_eq!(p.validate_git_repo().unwrap().len(), num_violations);
     }
 
+    fn parse_and_validate_range(path: &Path, range: &str, num_violations: usize) {
+        let p = Parser::from_git_range(path, range).unwrap();
+        assert_eq!(p.validate_git_repo().unwrap().len(), num_violations);
+    }
+
     #[test]
     fn test_from_directory() {
         let files = &[
@@ -613,10 +1940,14 @@ mod test {
         let d = TestDir::from_files(files);
         let file_names = files.iter().map(|f| f.0);
         let res = Parser::from_files(file_names, d.path());
-        assert!(res.is_err());
-        let err = res.unwrap_err().to_string();
+        let err = res.unwrap_err().into_iter().next().unwrap();
+        assert!(matches!(
+            &err,
+            Error::MissingTargetFile { path, line: 6, block, target_file }
+                if path == Path::new("f1.txt") && block == "default" && target_file == Path::new("f3.txt")
+        ));
         assert_eq!(
-            err,
+            err.to_string(),
             r#"ThenChange target file "f3.txt" at f1.txt:6 does not exist"#
         );
     }
@@ -639,43 +1970,255 @@ mod test {
         let d = TestDir::from_files(files);
         let file_names = files.iter().map(|f| f.0);
         let res = Parser::from_files(file_names, d.path());
-        assert!(res.is_err());
-        let err = res.unwrap_err().to_string();
+        let err = res.unwrap_err().into_iter().next().unwrap();
+        assert!(matches!(
+            &err,
+            Error::MissingTargetBlock { line: 6, target_file, target_block, .. }
+                if target_file == Path::new("f2.txt") && target_block == "invalid"
+        ));
         assert_eq!(
-            err,
+            err.to_string(),
             r#"block "default" at "f1.txt:6" has non-existent ThenChange target "f2.txt:invalid""#,
         );
     }
 
     #[test]
-    fn test_from_files_duplicate_block_in_file() {
-        let files = &[(
-            "f1.txt",
-            "LINT.OnChange(default)\n
-             abdbbda\nadadd\n
-             LINT.ThenChange(:other)
-             LINT.OnChange(default)\n
-             abdbbda\n
-             LINT.ThenChange(:other)
-             LINT.OnChange(other)\n
-             abdbbda\nadadd\n
-             LINT.ThenChange(:default)",
-        )];
+    fn test_from_files_multi_line_then_change() {
+        let files = &[
+            (
+                "a.rs",
+                indoc! {"
+                    // LINT.OnChange(a)
+                    fn a() {}
+                    // LINT.ThenChange(
+                    //   b.py:b,
+                    //   /abs/c.txt,
+                    //   //d/d.sql
+                    // )
+                "},
+            ),
+            (
+                "b.py",
+                indoc! {"
+                    # LINT.OnChange(b)
+                    # LINT.ThenChange(
+                    #   a.rs:a,
+                    # )
+                "},
+            ),
+            (
+                "d/d.sql",
+                indoc! {"
+                    /* LINT.OnChange(d)
+                     * LINT.ThenChange(
+                     * )
+                     */
+                "},
+            ),
+        ];
         let d = TestDir::from_files(files);
-        let file_names = files.iter().map(|f| f.0);
-        let res = Parser::from_files(file_names, d.path());
-        assert!(res.is_err());
-        let err = res.unwrap_err().to_string();
+        let diagnostics = Parser::from_directory(d.path(), true).unwrap_err();
+        let errors: Vec<&Error> = diagnostics.iter().collect();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0],
+            Error::InvalidTargetPath { path, line: 5, target }
+                if path == Path::new("a.rs") && target == "/abs/c.txt"
+        ));
+
+        d.write_file(
+            "a.rs",
+            indoc! {"
+                // LINT.OnChange(a)
+                fn a() {}
+                // LINT.ThenChange(
+                //   b.py:b,
+                //   //d/d.sql
+                // )
+            "},
+        );
+        let p = Parser::from_directory(d.path(), true).unwrap();
+        let block = p.get_block_in_file("a.rs", "a").unwrap();
+        assert_eq!((block.start_line(), block.end_line()), (1, 6));
+        let targets: Vec<_> = block.get_then_change_targets_as_keys().collect();
         assert_eq!(
-            err,
-            r#"duplicate block name "default" found on f1.txt:1 and f1.txt:7"#,
+            targets,
+            vec![(Path::new("b.py"), Some("b")), (Path::new("d/d.sql"), None)]
         );
-    }
+        let block = p.get_block_in_file("b.py", "b").unwrap();
+        assert_eq!(block.end_line(), 4);
+        let block = p.get_block_in_file("d/d.sql", "d").unwrap();
+        assert!(matches!(block.then_change(), ThenChange::NoTarget));
 
-    #[test]
-    fn test_from_files_nested_on_change() {
-        let files = &[(
-            "f1.txt",
+        // Missing targets are reported on their own line.
+        d.write_file(
+            "a.rs",
+            indoc! {"
+                // LINT.OnChange(a)
+                fn a() {}
+                // LINT.ThenChange(
+                //   b.py:b,
+                //   e.txt,
+                //   b.py:x
+                // )
+            "},
+        );
+        let diagnostics = Parser::from_directory(d.path(), true).unwrap_err();
+        let errors: Vec<(&str, Option<u32>)> =
+            diagnostics.iter().map(|e| (e.kind(), e.line())).collect();
+        assert_eq!(
+            errors,
+            vec![
+                ("missing_target_file", Some(5)),
+                ("missing_target_block", Some(6))
+            ]
+        );
+
+        // A list without a closing ')' must not swallow the markers that follow it.
+        d.write_file(
+            "a.rs",
+            indoc! {"
+                // LINT.OnChange(a)
+                fn a() {}
+                // LINT.ThenChange(
+                //   b.py:b,
+                // LINT.OnChange(c)
+                // LINT.ThenChange()
+            "},
+        );
+        let diagnostics = Parser::from_directory(d.path(), true).unwrap_err();
+        assert!(diagnostics.iter().any(|e| matches!(
+            e,
+            Error::InvalidTargetSyntax { line: 5, message, .. } if message.starts_with("unexpected '('")
+        )));
+    }
+
+    #[test]
+    fn test_from_files_quoted_targets() {
+        let files = &[
+            (
+                "f1.txt",
+                indoc! {r#"
+                    LINT.OnChange(a)
+                    LINT.ThenChange("a b, c.txt":"x:y", "p(1).txt", "q\"\\.txt")
+                "#},
+            ),
+            ("a b, c.txt", "LINT.OnChange(x:y)\nLINT.ThenChange()\n"),
+            ("p(1).txt", "abc\n"),
+            ("q\"\\.txt", "abc\n"),
+        ];
+        let d = TestDir::from_files(files);
+        let p = Parser::from_directory(d.path(), true).unwrap();
+        let block = p.get_block_in_file("f1.txt", "a").unwrap();
+        let targets: Vec<_> = block.get_then_change_targets_as_keys().collect();
+        assert_eq!(
+            targets,
+            vec![
+                (Path::new("a b, c.txt"), Some("x:y")),
+                (Path::new("p(1).txt"), None),
+                (Path::new("q\"\\.txt"), None),
+            ]
+        );
+
+        for (then_change, column, message) in [
+            ("  LINT.ThenChange(a.txt:b:c)", 26, "unexpected ':'"),
+            (r#"LINT.ThenChange("a.txt)"#, 17, "unterminated quote"),
+            (r#"LINT.ThenChange(a.txt, "b\n.txt")"#, 26, "invalid escape"),
+            (r#"LINT.ThenChange("a.txt"b)"#, 24, "unexpected 'b'"),
+            (r#"LINT.ThenChange(a.txt, "")"#, 24, "empty target"),
+            ("LINT.ThenChange(a.txt, b(1).txt)", 25, "unexpected '('"),
+        ] {
+            d.write_file("f2.txt", &format!("LINT.OnChange(b)\n{}\n", then_change));
+            let diagnostics = Parser::from_files(["f2.txt"].iter(), d.path()).unwrap_err();
+            let err = diagnostics.into_iter().next().unwrap();
+            assert!(
+                matches!(
+                    &err,
+                    Error::InvalidTargetSyntax { line: 2, column: c, message: m, .. }
+                        if *c == column && m.starts_with(message)
+                ),
+                "{}: {}",
+                then_change,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_files_block_attributes() {
+        let d = TestDir::from_files(&[(
+            "f.txt",
+            indoc! {r#"
+                LINT.OnChange(a, severity=warn, owner=@team-api, reason="wire compat (v2), \"see\" docs")
+                LINT.ThenChange()
+                LINT.OnChange(b)
+                LINT.ThenChange()
+            "#},
+        )]);
+        let p = Parser::from_files(["f.txt"].iter(), d.path()).unwrap();
+        let block = p.get_block_in_file("f.txt", "a").unwrap();
+        assert_eq!(block.severity(), Severity::Warn);
+        assert_eq!(block.owner(), Some("@team-api"));
+        assert_eq!(block.reason(), Some(r#"wire compat (v2), "see" docs"#));
+        let block = p.get_block_in_file("f.txt", "b").unwrap();
+        assert_eq!(block.severity(), Severity::Error);
+        assert_eq!((block.owner(), block.reason()), (None, None));
+
+        d.write_file(
+            "f.txt",
+            indoc! {r#"
+                LINT.OnChange(a, severity=fatal)
+                LINT.ThenChange()
+                LINT.OnChange(b, owner=x, owner=y)
+                LINT.ThenChange()
+                LINT.OnChange(c, reason="abc)
+                LINT.ThenChange()
+            "#},
+        );
+        let diagnostics = Parser::from_files(["f.txt"].iter(), d.path()).unwrap_err();
+        let errors: Vec<String> = diagnostics.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                r#"invalid OnChange at f.txt:1: expected error or warn, found "fatal""#,
+                r#"invalid OnChange at f.txt:3: duplicate attribute "owner""#,
+                "invalid OnChange at f.txt:5: unterminated quote",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_files_duplicate_block_in_file() {
+        let files = &[(
+            "f1.txt",
+            "LINT.OnChange(default)\n
+             abdbbda\nadadd\n
+             LINT.ThenChange(:other)
+             LINT.OnChange(default)\n
+             abdbbda\n
+             LINT.ThenChange(:other)
+             LINT.OnChange(other)\n
+             abdbbda\nadadd\n
+             LINT.ThenChange(:default)",
+        )];
+        let d = TestDir::from_files(files);
+        let file_names = files.iter().map(|f| f.0);
+        let res = Parser::from_files(file_names, d.path());
+        let err = res.unwrap_err().into_iter().next().unwrap();
+        assert!(matches!(
+            &err,
+            Error::DuplicateBlock { line: 7, first_line: 1, block, .. } if block == "default"
+        ));
+        assert_eq!(
+            err.to_string(),
+            r#"duplicate block name "default" found on f1.txt:1 and f1.txt:7"#,
+        );
+    }
+
+    #[test]
+    fn test_from_files_nested_on_change() {
+        let files = &[(
+            "f1.txt",
             "LINT.OnChange(default)\n
              abdbbda\nadadd\n
              LINT.OnChange(other)\n
@@ -699,10 +2242,10 @@ mod test {
         let d = TestDir::from_files(files);
         let file_names = files.iter().map(|f| f.0);
         let res = Parser::from_files(file_names, d.path());
-        assert!(res.is_err());
-        let err = res.unwrap_err().to_string();
-        assert!(err.contains(
-            "while looking for ThenChange for block \"default\" which started on line 1"
+        let err = res.unwrap_err().into_iter().next().unwrap();
+        assert!(matches!(
+            &err,
+            Error::UnclosedBlock { line: 1, block, .. } if block == "default"
         ));
     }
 
@@ -712,9 +2255,241 @@ mod test {
         let d = TestDir::from_files(files);
         let file_names = files.iter().map(|f| f.0);
         let res = Parser::from_files(file_names, d.path());
-        assert!(res.is_err());
-        let err = res.unwrap_err().to_string();
-        assert!(err.contains("reached end of file"));
+        let err = res.unwrap_err().into_iter().next().unwrap();
+        assert!(err.to_string().contains("reached end of file"));
+        assert!(matches!(err, Error::UnclosedBlock { line: 1, .. }));
+    }
+
+    #[test]
+    fn test_from_directory_reports_all_errors() {
+        let files = &[
+            (
+                "f1.txt",
+                indoc! {"
+                    LINT.OnChange(a)
+                    LINT.ThenChange()
+                    LINT.OnChange(a)
+                    LINT.ThenChange()
+                    LINT.OnChange(b)
+                "},
+            ),
+            (
+                "f2.txt",
+                indoc! {"
+                    abc
+                    LINT.ThenChange()
+                "},
+            ),
+            (
+                "f3.txt",
+                indoc! {"
+                    LINT.OnChange(c)
+                    LINT.ThenChange(f4.txt:missing, missing.txt)
+                "},
+            ),
+            (
+                "f4.txt",
+                indoc! {"
+                    LINT.OnChange(d)
+                    LINT.ThenChange(f3.txt:c)
+                "},
+            ),
+        ];
+        let d = TestDir::from_files(files);
+        let diagnostics = Parser::from_directory(d.path(), true).unwrap_err();
+        assert_eq!(diagnostics.len(), 5);
+        assert!(diagnostics.to_string().starts_with("found 5 errors:"));
+        for e in diagnostics.iter() {
+            let location = (e.path().unwrap().to_str().unwrap(), e.line().unwrap());
+            match e {
+                Error::DuplicateBlock {
+                    block, first_line, ..
+                } => {
+                    assert_eq!(location, ("f1.txt", 3));
+                    assert_eq!((block.as_str(), *first_line), ("a", 1));
+                }
+                Error::UnclosedBlock { block, .. } => {
+                    assert_eq!(location, ("f1.txt", 5));
+                    assert_eq!(block, "b");
+                }
+                Error::OrphanThenChange { .. } => assert_eq!(location, ("f2.txt", 2)),
+                Error::MissingTargetFile { target_file, .. } => {
+                    assert_eq!(location, ("f3.txt", 2));
+                    assert_eq!(target_file, Path::new("missing.txt"));
+                }
+                Error::MissingTargetBlock {
+                    target_file,
+                    target_block,
+                    ..
+                } => {
+                    assert_eq!(location, ("f3.txt", 2));
+                    assert_eq!(target_file, Path::new("f4.txt"));
+                    assert_eq!(target_block, "missing");
+                }
+                _ => panic!("unexpected error: {}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_directory_with_config() {
+        let files = &[
+            (
+                ".onchg.toml",
+                indoc! {r#"
+                    exclude = ["vendor/**"]
+                "#},
+            ),
+            (
+                "f1.txt",
+                indoc! {"
+                    LINT.OnChange(a)
+                    LINT.ThenChange(sub/f2.txt:b)
+                "},
+            ),
+            (
+                "sub/.onchg.toml",
+                indoc! {r#"
+                    marker = 'CHECK\.OnChange\((?<on_change>.*?)\)|CHECK\.ThenChange\((?<then_change>.*?)\)'
+                "#},
+            ),
+            (
+                "sub/f2.txt",
+                indoc! {"
+                    CHECK.OnChange(b)
+                    CHECK.ThenChange(../f1.txt:a)
+                "},
+            ),
+            // Excluded, so the unclosed block is not an error.
+            ("vendor/f3.txt", "LINT.OnChange(c)\n"),
+        ];
+        let d = TestDir::from_files(files);
+        let p = Parser::from_directory(d.path(), true).unwrap();
+        assert_eq!(p.num_blocks(), 2);
+        assert!(p.get_block_in_file("sub/f2.txt", "b").is_some());
+
+        // Flags take precedence over the config file.
+        let overrides = Settings {
+            exclude: vec!["sub/**".to_string()],
+            ..Default::default()
+        };
+        let config = Config::load(d.path(), &overrides).unwrap();
+        let diagnostics = Parser::from_directory_with_config(d.path(), &config).unwrap_err();
+        assert!(matches!(
+            diagnostics.iter().next().unwrap(),
+            Error::MissingTargetBlock { .. }
+        ));
+    }
+
+    #[test]
+    fn test_from_directory_with_presets() {
+        let files = &[
+            (
+                ".onchg.toml",
+                indoc! {r#"
+                    presets = ["onchg", "lint-ifchange", "ifttt"]
+
+                    [[markers]]
+                    on_change = "@sync-start"
+                    then_change = "@sync-end"
+                "#},
+            ),
+            (
+                "a.py",
+                indoc! {"
+                    # LINT.IfChange
+                    x = 1
+                    # LINT.ThenChange(b.sh:b)
+                "},
+            ),
+            (
+                "b.sh",
+                indoc! {"
+                    # IF_CHANGE(b)
+                    X=1
+                    # THEN_CHANGE(c.txt:c)
+                "},
+            ),
+            (
+                "c.txt",
+                indoc! {"
+                    LINT.OnChange(c)
+                    @sync-start(d)
+                    @sync-end(a.py)
+                    LINT.ThenChange()
+                "},
+            ),
+        ];
+        let d = TestDir::from_files(files);
+        let p = Parser::from_directory(d.path(), true).unwrap();
+        assert_eq!(p.num_blocks(), 4);
+        let block = p.on_change_blocks_in_file("a.py").unwrap().next().unwrap();
+        assert!(!block.is_targetable());
+        assert_eq!((block.start_line(), block.end_line()), (1, 3));
+        assert!(p.get_block_in_file("b.sh", "b").is_some());
+        assert!(p.get_block_in_file("c.txt", "d").is_some());
+
+        // With only the default preset, the LINT.IfChange is not recognized.
+        let overrides = Settings {
+            presets: Some(vec!["onchg".to_string()]),
+            ..Default::default()
+        };
+        let config = Config::load(d.path(), &overrides).unwrap();
+        let diagnostics = Parser::from_directory_with_config(d.path(), &config).unwrap_err();
+        assert!(matches!(
+            diagnostics.iter().next().unwrap(),
+            Error::OrphanThenChange { line: 3, .. }
+        ));
+    }
+
+    #[test]
+    fn test_from_directory_comments_only() {
+        let files = &[
+            (
+                "a.rs",
+                indoc! {r#"
+                    // LINT.OnChange(a)
+                    const PAT: &str = "LINT.OnChange(x)";
+                    const TEXT: &str = "
+                    // LINT.OnChange(y) \"
+                    ";
+                    const RAW: &str = r"LINT.ThenChange(\)";
+                    /* LINT.ThenChange(b.py:b) */
+                    /// LINT.OnChange(d)
+                    /// LINT.ThenChange(
+                    ///     b.py:b,
+                    ///     c.txt
+                    /// )
+                "#},
+            ),
+            (
+                "b.py",
+                indoc! {r#"
+                    # LINT.OnChange(b)
+                    s = """
+                    LINT.ThenChange()
+                    """
+                    # LINT.ThenChange()
+                "#},
+            ),
+            // Unknown languages are matched as a whole.
+            ("c.txt", "LINT.OnChange(c)\nLINT.ThenChange()\n"),
+        ];
+        let d = TestDir::from_files(files);
+        assert!(Parser::from_directory(d.path(), true).is_err());
+
+        let overrides = Settings {
+            comments_only: Some(true),
+            ..Default::default()
+        };
+        let config = Config::load(d.path(), &overrides).unwrap();
+        let p = Parser::from_directory_with_config(d.path(), &config).unwrap();
+        assert_eq!(p.num_blocks(), 4);
+        let block = p.get_block_in_file("b.py", "b").unwrap();
+        assert_eq!((block.start_line(), block.end_line()), (1, 5));
+        let block = p.get_block_in_file("a.rs", "d").unwrap();
+        assert_eq!((block.start_line(), block.end_line()), (8, 12));
+        assert_eq!(block.get_then_change_targets_as_keys().count(), 2);
     }
 
     #[test]
@@ -1314,4 +3089,1112 @@ mod test {
         )]);
         parse_and_validate(d.path(), 0);
     }
+
+    #[test]
+    fn test_from_git_range() {
+        let files = &[
+            (
+                "f1.txt",
+                "LINT.OnChange(default)\n
+                 abdbbda\nadadd\n
+                 LINT.ThenChange(f2.txt:default)\n",
+            ),
+            (
+                "f2.txt",
+                "LINT.OnChange(default)\n
+                 LINT.ThenChange(f1.txt:default)\n",
+            ),
+        ];
+        let d = GitRepo::from_files(files);
+
+        // Commit a change to f1.txt only.
+        let f1 = "LINT.OnChange(default)\nadadd\nLINT.ThenChange(f2.txt:default)\n";
+        d.write_and_add_files(&[("f1.txt", f1)]);
+        d.commit(None);
+        parse_and_validate_range(d.path(), "HEAD~1..HEAD", 1);
+        parse_and_validate_range(d.path(), "HEAD~1", 1);
+
+        // Nothing is staged, so repo mode has nothing to check.
+        parse_and_validate(d.path(), 0);
+
+        // Unstaged changes in the working tree must not affect the range.
+        d.write_file(
+            "f1.txt",
+            "\n\n\nLINT.OnChange(default)\nLINT.ThenChange()\n",
+        );
+        parse_and_validate_range(d.path(), "HEAD~1..HEAD", 1);
+        // Neither must a target that only differs in the working tree.
+        d.write_file("f2.txt", "LINT.OnChange(other)\nLINT.ThenChange()\n");
+        parse_and_validate_range(d.path(), "HEAD~1..HEAD", 1);
+
+        // Commit a change to f2.txt and ensure the full range passes.
+        d.write_and_add_files(&[
+            ("f1.txt", f1),
+            (
+                "f2.txt",
+                "LINT.OnChange(default)\nadadd\nLINT.ThenChange(f1.txt:default)\n",
+            ),
+        ]);
+        d.commit(None);
+        parse_and_validate_range(d.path(), "HEAD~2..HEAD", 0);
+        parse_and_validate_range(d.path(), "HEAD~1..HEAD", 1);
+        parse_and_validate_range(d.path(), "HEAD..HEAD", 0);
+    }
+
+    #[test]
+    fn test_from_git_config_from_revision() {
+        let d = GitRepo::from_files(&[
+            ("f1.txt", "LINT.OnChange(a)\nabc\nLINT.ThenChange(f2.txt)\n"),
+            ("f2.txt", "abc\n"),
+        ]);
+
+        // The staged config excludes the changed file, but the one on disk does not.
+        d.write_and_add_files(&[
+            ("f1.txt", "LINT.OnChange(a)\nxyz\nLINT.ThenChange(f2.txt)\n"),
+            (".onchg.toml", "exclude = [\"f1.txt\"]\n"),
+        ]);
+        d.write_file(".onchg.toml", "");
+        parse_and_validate(d.path(), 0);
+        let config = Config::load_from_git(d.path(), None, &Settings::default()).unwrap();
+        assert!(!config.is_included("f1.txt").unwrap());
+
+        // A range is checked with the config files of its head revision.
+        d.commit(None);
+        d.write_file(".onchg.toml", "include = 1\n");
+        parse_and_validate_range(d.path(), "HEAD~1..HEAD", 0);
+        d.write_and_add_files(&[
+            ("f1.txt", "LINT.OnChange(a)\nabc\nLINT.ThenChange(f2.txt)\n"),
+            (".onchg.toml", ""),
+        ]);
+        d.commit(None);
+        parse_and_validate_range(d.path(), "HEAD~1..HEAD", 1);
+        parse_and_validate_range(d.path(), "HEAD~2..HEAD~1", 0);
+    }
+
+    #[test]
+    fn test_from_git_repo_non_ascii_path() {
+        let d = GitRepo::from_files(&[
+            (
+                "é.txt",
+                "LINT.OnChange(a)\nabc\nLINT.ThenChange(ü/f2.txt)\n",
+            ),
+            (
+                "ü/f2.txt",
+                "LINT.OnChange()\nabc\nLINT.ThenChange(//é.txt)\n",
+            ),
+        ]);
+
+        // Git quotes non-ASCII paths by default, which must not hide the change.
+        d.write_and_add_files(&[(
+            "é.txt",
+            "LINT.OnChange(a)\nxyz\nLINT.ThenChange(ü/f2.txt)\n",
+        )]);
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        assert_eq!(p.paths().collect::<Vec<_>>(), vec![Path::new("é.txt")]);
+        assert_eq!(p.validate_git_repo().unwrap().len(), 1);
+        d.commit(None);
+        parse_and_validate_range(d.path(), "HEAD~1..HEAD", 1);
+
+        d.write_and_add_files(&[(
+            "ü/f2.txt",
+            "LINT.OnChange()\nxyz\nLINT.ThenChange(//é.txt)\n",
+        )]);
+        parse_and_validate(d.path(), 1);
+    }
+
+    #[test]
+    fn test_from_git_range_deleted_target() {
+        let d = GitRepo::from_files(&[
+            ("f1.txt", "LINT.OnChange(a)\nabc\nLINT.ThenChange(f2.txt)\n"),
+            ("f2.txt", "abc\n"),
+        ]);
+        d.write_and_add_files(&[
+            ("f1.txt", "LINT.OnChange(a)\nxyz\nLINT.ThenChange(f2.txt)\n"),
+            ("f2.txt", "xyz\n"),
+        ]);
+        d.commit(None);
+
+        // The target is deleted after the range, which must not affect it.
+        d.write_and_add_files(&[("f1.txt", "LINT.OnChange(a)\nxyz\nLINT.ThenChange()\n")]);
+        d.remove_file("f2.txt");
+        d.commit(None);
+        parse_and_validate_range(d.path(), "HEAD~2..HEAD~1", 0);
+        let p = Parser::from_git_commit(d.path(), "HEAD~1").unwrap();
+        assert_eq!(p.validate_git_repo().unwrap().len(), 0);
+
+        // The target is missing in the range, even though it exists on disk.
+        d.write_and_add_files(&[("f1.txt", "LINT.OnChange(a)\nabc\nLINT.ThenChange(f2.txt)\n")]);
+        d.commit(None);
+        d.write_file("f2.txt", "xyz\n");
+        let err = Parser::from_git_range(d.path(), "HEAD~1..HEAD").unwrap_err();
+        assert_eq!(err.iter().next().unwrap().kind(), "missing_target_file");
+    }
+
+    #[test]
+    fn test_from_git_repo_partially_staged() {
+        let files = &[
+            (
+                "f1.txt",
+                indoc! {"
+                    LINT.OnChange(a)
+                    x
+                    LINT.ThenChange(f2.txt:a)
+                    filler
+                    LINT.OnChange(b)
+                    y
+                    LINT.ThenChange()
+                "},
+            ),
+            (
+                "f2.txt",
+                indoc! {"
+                    LINT.OnChange(a)
+                    LINT.ThenChange(f1.txt:a)
+                "},
+            ),
+        ];
+        let d = GitRepo::from_files(files);
+
+        // Stage a change to block "a".
+        d.write_and_add_files(&[(
+            "f1.txt",
+            indoc! {"
+                LINT.OnChange(a)
+                xx
+                LINT.ThenChange(f2.txt:a)
+                filler
+                LINT.OnChange(b)
+                y
+                LINT.ThenChange()
+            "},
+        )]);
+        parse_and_validate(d.path(), 1);
+
+        // Remove block "a" in the working tree without staging it. The staged hunk
+        // still describes the index version, so block "a" must still be flagged.
+        d.write_file(
+            "f1.txt",
+            indoc! {"
+                filler
+                LINT.OnChange(b)
+                y
+                LINT.ThenChange()
+            "},
+        );
+        parse_and_validate(d.path(), 1);
+    }
+
+    #[test]
+    fn test_from_git_repo_removed_block() {
+        let files = &[
+            (
+                "f1.txt",
+                indoc! {"
+                    LINT.OnChange(a)
+                    x
+                    LINT.ThenChange(f2.txt:a)
+                    LINT.OnChange(b)
+                    y
+                    LINT.ThenChange()
+                "},
+            ),
+            (
+                "f2.txt",
+                indoc! {"
+                    LINT.OnChange(a)
+                    LINT.ThenChange(f1.txt:a)
+                "},
+            ),
+            (
+                "f3.txt",
+                indoc! {"
+                    LINT.OnChange()
+                    LINT.ThenChange(//f1.txt:b)
+                "},
+            ),
+        ];
+        let d = GitRepo::from_files(files);
+
+        // Remove block "a" from f1.txt. This should be flagged twice: f2.txt:a is a
+        // dangling dependent and the ThenChange target (f2.txt:a) of the removed block
+        // has not changed.
+        d.write_and_add_files(&[(
+            "f1.txt",
+            indoc! {"
+                LINT.OnChange(b)
+                y
+                LINT.ThenChange()
+            "},
+        )]);
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        assert_eq!(p.removed_blocks().count(), 1);
+        let violations = p.validate_git_repo().unwrap();
+        assert_eq!(violations.len(), 2);
+        assert_eq!(
+            violations
+                .iter()
+                .filter(|v| v.kind() == OnChangeViolationKind::TargetRemoved)
+                .count(),
+            1
+        );
+
+        // Remove the block in f2.txt as well.
+        d.write_and_add_files(&[("f2.txt", "")]);
+        parse_and_validate(d.path(), 0);
+
+        // Rename block "b" in f1.txt, which leaves f3.txt dangling.
+        d.write_and_add_files(&[(
+            "f1.txt",
+            indoc! {"
+                LINT.OnChange(c)
+                y
+                LINT.ThenChange()
+            "},
+        )]);
+        parse_and_validate(d.path(), 1);
+    }
+
+    #[test]
+    fn test_from_git_repo_deleted_file() {
+        let files = &[
+            (
+                "f1.txt",
+                indoc! {"
+                    LINT.OnChange(a)
+                    x
+                    LINT.ThenChange(f2.txt:a)
+                "},
+            ),
+            (
+                "f2.txt",
+                indoc! {"
+                    LINT.OnChange(a)
+                    LINT.ThenChange(f1.txt:a)
+                "},
+            ),
+            (
+                "abc/f3.txt",
+                indoc! {"
+                    LINT.OnChange()
+                    LINT.ThenChange(../f1.txt)
+                "},
+            ),
+        ];
+        let d = GitRepo::from_files(files);
+
+        // Deleting f1.txt leaves f2.txt:a and abc/f3.txt dangling, and f2.txt:a (the
+        // target of the removed block) has not changed.
+        d.remove_file("f1.txt");
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        assert_eq!(
+            p.deleted_paths().collect::<Vec<_>>(),
+            vec![Path::new("f1.txt")]
+        );
+        assert_eq!(p.validate_git_repo().unwrap().len(), 3);
+
+        // Same thing for a committed range.
+        d.commit(None);
+        parse_and_validate_range(d.path(), "HEAD~1..HEAD", 3);
+    }
+
+    #[test]
+    fn test_from_git_repo_renamed_file() {
+        let files = &[
+            (
+                "f1.txt",
+                indoc! {"
+                    LINT.OnChange(a)
+                    x
+                    LINT.ThenChange()
+                "},
+            ),
+            (
+                "f2.txt",
+                indoc! {"
+                    LINT.OnChange(a)
+                    LINT.ThenChange(f1.txt:a)
+                "},
+            ),
+            (
+                "f3.txt",
+                indoc! {"
+                    LINT.OnChange()
+                    LINT.ThenChange(//f1.txt)
+                "},
+            ),
+        ];
+        let d = GitRepo::from_files(files);
+
+        // Rename f1.txt. Both blocks in f2.txt and f3.txt still refer to the old path.
+        d.remove_file("f1.txt");
+        d.write_and_add_files(&[("g1.txt", files[0].1)]);
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        assert_eq!(
+            p.renamed_paths().collect::<Vec<_>>(),
+            vec![(Path::new("f1.txt"), Path::new("g1.txt"))]
+        );
+        // A pure rename does not change any blocks.
+        assert_eq!(p.num_blocks(), 0);
+        assert_eq!(p.removed_blocks().count(), 0);
+        let violations = p.validate_git_repo().unwrap();
+        assert_eq!(violations.len(), 2);
+        assert!(violations
+            .iter()
+            .all(|v| v.kind() == OnChangeViolationKind::TargetRenamed));
+        assert!(violations[0].to_string().contains(r#"renamed to "g1.txt""#));
+
+        // Point f3.txt at the new path. Since the rename did not change the content of
+        // g1.txt, this is only OK once g1.txt is changed too.
+        d.write_and_add_files(&[(
+            "f3.txt",
+            indoc! {"
+                LINT.OnChange()
+                LINT.ThenChange(//g1.txt)
+            "},
+        )]);
+        parse_and_validate(d.path(), 2);
+        d.write_and_add_files(&[(
+            "g1.txt",
+            indoc! {"
+                LINT.OnChange(a)
+                xyz
+                LINT.ThenChange()
+            "},
+        )]);
+        parse_and_validate(d.path(), 1);
+    }
+
+    #[test]
+    fn test_from_git_repo_target_location() {
+        let files = &[
+            (
+                "f1.txt",
+                indoc! {"
+                    LINT.OnChange(a)
+                    x
+                    LINT.ThenChange(f2.txt:b)
+                    LINT.OnChange(c)
+                    x
+                    LINT.ThenChange(f2.txt:missing)
+                "},
+            ),
+            (
+                "f2.txt",
+                indoc! {"
+                    abc
+                    LINT.OnChange(b)
+                    x
+                    LINT.ThenChange()
+                "},
+            ),
+        ];
+        let d = GitRepo::from_files(files);
+        d.write_and_add_files(&[(
+            "f1.txt",
+            indoc! {"
+                LINT.OnChange(a)
+                y
+                LINT.ThenChange(f2.txt:b)
+                LINT.OnChange(c)
+                y
+                LINT.ThenChange(f2.txt:missing)
+            "},
+        )]);
+
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        let violations = p.validate_git_repo().unwrap();
+        assert_eq!(violations.len(), 2);
+
+        let v = &violations[0];
+        assert_eq!(v.kind(), OnChangeViolationKind::TargetNotChanged);
+        let target = v.target_block().unwrap();
+        assert_eq!((target.start_line(), target.end_line()), (2, 4));
+        assert!(v.to_string().contains("f2.txt:2"));
+
+        let v = &violations[1];
+        assert_eq!(v.kind(), OnChangeViolationKind::TargetBlockMissing);
+        assert!(v.target_block().is_none());
+        assert!(v
+            .to_string()
+            .contains(r#"non-existent ThenChange target "f2.txt:missing""#));
+    }
+
+    #[test]
+    fn test_from_git_repo_glob_targets() {
+        let files = &[
+            (
+                "api.rs",
+                indoc! {"
+                    // LINT.OnChange(enum)
+                    enum Api {}
+                    // LINT.ThenChange(//locales/*/messages.po, //clients/)
+                "},
+            ),
+            ("locales/en/messages.po", "a\n"),
+            ("locales/fr/messages.po", "a\n"),
+            ("clients/go/api.go", "a\n"),
+            ("clients/py/api.py", "a\n"),
+        ];
+        let d = GitRepo::from_files(files);
+        assert!(Parser::from_directory(d.path(), true).is_ok());
+
+        d.write_and_add_files(&[
+            (
+                "api.rs",
+                indoc! {"
+                    // LINT.OnChange(enum)
+                    enum Api { A }
+                    // LINT.ThenChange(//locales/*/messages.po, //clients/)
+                "},
+            ),
+            ("locales/en/messages.po", "b\n"),
+            ("clients/go/api.go", "b\n"),
+        ]);
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        let violations = p.validate_git_repo().unwrap();
+        let mut unchanged: Vec<_> = violations
+            .iter()
+            .map(|v| (v.target_file(), v.target_glob().unwrap()))
+            .collect();
+        unchanged.sort();
+        assert_eq!(
+            unchanged,
+            vec![
+                (Path::new("clients/py/api.py"), "clients/**"),
+                (Path::new("locales/fr/messages.po"), "locales/*/messages.po"),
+            ]
+        );
+
+        // At least one matching file changed for each glob.
+        let overrides = Settings {
+            glob_targets: Some(GlobMode::Any),
+            ..Default::default()
+        };
+        let config = Config::load(d.path(), &overrides).unwrap();
+        let p = Parser::from_git_repo_with_config(d.path(), &config).unwrap();
+        assert!(p.validate_git_repo().unwrap().is_empty());
+
+        d.write_and_add_files(&[
+            ("locales/en/messages.po", "a\n"),
+            ("clients/go/api.go", "a\n"),
+        ]);
+        let p = Parser::from_git_repo_with_config(d.path(), &config).unwrap();
+        let violations = p.validate_git_repo().unwrap();
+        assert_eq!(violations.len(), 2);
+        assert_eq!(
+            violations[0].target_file(),
+            Path::new("locales/*/messages.po")
+        );
+        assert!(violations[0]
+            .to_string()
+            .starts_with(r#"any file matching "locales/*/messages.po""#));
+    }
+
+    #[test]
+    fn test_from_git_repo_block_pattern_targets() {
+        let files = &[
+            (
+                "api.rs",
+                indoc! {"
+                    // LINT.OnChange(api)
+                    fn a() {}
+                    // LINT.ThenChange(api.md:api-v1-*)
+                "},
+            ),
+            (
+                "api.md",
+                indoc! {"
+                    <!-- LINT.OnChange(api-v1-get) -->
+                    a
+                    <!-- LINT.ThenChange() -->
+                    <!-- LINT.OnChange(api-v1-put) -->
+                    a
+                    <!-- LINT.ThenChange() -->
+                    <!-- LINT.OnChange(api-v2-get) -->
+                    a
+                    <!-- LINT.ThenChange() -->
+                "},
+            ),
+        ];
+        let d = GitRepo::from_files(files);
+        let p = Parser::from_directory(d.path(), true).unwrap();
+        let block = p.get_block_in_file("api.rs", "api").unwrap();
+        assert_eq!(block.get_then_change_targets_as_keys().count(), 0);
+        let (file, pattern) = block.block_pattern_targets().next().unwrap();
+        assert_eq!((file, pattern.pattern()), (Path::new("api.md"), "api-v1-*"));
+
+        // Every matching block must change.
+        d.write_and_add_files(&[
+            ("api.rs", &files[0].1.replace("a()", "b()")),
+            ("api.md", &files[1].1.replacen("a\n", "b\n", 1)),
+        ]);
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        let violations = p.validate_git_repo().unwrap();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].target_block_name(), Some("api-v1-put"));
+        assert_eq!(violations[0].target_block().unwrap().start_line(), 4);
+
+        d.write_and_add_files(&[("api.md", &files[1].1.replacen("a\n", "b\n", 2))]);
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        assert!(p.validate_git_repo().unwrap().is_empty());
+
+        // A pattern that matches no blocks is reported as a missing target.
+        d.write_and_add_files(&[("api.rs", &files[0].1.replace("api-v1-*", "api-v3-*"))]);
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        let violations = p.validate_git_repo().unwrap();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(
+            violations[0].kind(),
+            OnChangeViolationKind::TargetBlockMissing
+        );
+        let diagnostics = Parser::from_directory(d.path(), true).unwrap_err();
+        assert!(matches!(
+            diagnostics.iter().next().unwrap(),
+            Error::MissingTargetBlock { target_block, .. } if target_block == "api-v3-*"
+        ));
+
+        // A quoted block name is never a pattern.
+        d.write_file("api.rs", &files[0].1.replace("api-v1-*", r#""api-v1-*""#));
+        let diagnostics = Parser::from_directory(d.path(), true).unwrap_err();
+        assert!(matches!(
+            diagnostics.iter().next().unwrap(),
+            Error::MissingTargetBlock { target_block, .. } if target_block == "api-v1-*"
+        ));
+    }
+
+    #[test]
+    fn test_from_git_repo_sync_group() {
+        let files = &[
+            (
+                "a.proto",
+                indoc! {"
+                    // LINT.OnChange(group=wire-format)
+                    message A {}
+                    // LINT.ThenChange()
+                "},
+            ),
+            (
+                "b.rs",
+                indoc! {"
+                    // LINT.OnChange(b, group=wire-format)
+                    struct A;
+                    // LINT.ThenChange()
+                "},
+            ),
+            (
+                "c.py",
+                indoc! {"
+                    x = 1
+                    # LINT.OnChange(group = wire-format)
+                    class A: pass
+                    # LINT.ThenChange()
+                "},
+            ),
+        ];
+        let d = GitRepo::from_files(files);
+        let p = Parser::from_directory(d.path(), true).unwrap();
+        let block = p.get_block_in_file("b.rs", "b").unwrap();
+        assert_eq!(block.group(), Some("wire-format"));
+
+        // All other members must change.
+        d.write_and_add_files(&[("a.proto", &files[0].1.replace("A {}", "A { int32 a = 1; }"))]);
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        let violations = p.validate_git_repo().unwrap();
+        let mut targets: Vec<_> = violations
+            .iter()
+            .map(|v| {
+                assert_eq!(v.target_group(), Some("wire-format"));
+                assert_eq!(v.block().file(), Path::new("a.proto"));
+                (
+                    v.target_file(),
+                    v.target_block_name(),
+                    v.target_block().unwrap().start_line(),
+                )
+            })
+            .collect();
+        targets.sort();
+        assert_eq!(
+            targets,
+            vec![
+                (Path::new("b.rs"), Some("b"), 1),
+                (Path::new("c.py"), None, 2)
+            ]
+        );
+        assert!(violations
+            .iter()
+            .any(|v| v.to_string().contains(r#"in group "wire-format""#)));
+
+        // An unchanged member is only reported once.
+        d.write_and_add_files(&[("b.rs", &files[1].1.replace("A;", "B;"))]);
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        let violations = p.validate_git_repo().unwrap();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].target_file(), Path::new("c.py"));
+
+        d.write_and_add_files(&[("c.py", &files[2].1.replace("pass", "x = 1"))]);
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        assert!(p.validate_git_repo().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_from_directory_sync_group_errors() {
+        let files = &[
+            ("a.txt", "LINT.OnChange(group=lonely)\nLINT.ThenChange()\n"),
+            ("b.txt", "LINT.OnChange(b, color=red)\nLINT.ThenChange()\n"),
+            ("c.txt", "LINT.OnChange(group=)\nLINT.ThenChange()\n"),
+        ];
+        let d = TestDir::from_files(files);
+        let diagnostics = Parser::from_directory(d.path(), true).unwrap_err();
+        let mut errors: Vec<String> = diagnostics.iter().map(|e| e.to_string()).collect();
+        errors.sort();
+        assert_eq!(
+            errors,
+            vec![
+                r#"block "<unnamed>" at a.txt:1 is the only member of group "lonely""#,
+                r#"invalid OnChange at b.txt:1: unknown attribute "color""#,
+                r#"invalid OnChange at c.txt:1: attribute "group" has no value"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_git_repo_any_of_targets() {
+        let files = &[
+            (
+                "a.rs",
+                indoc! {"
+                    // LINT.OnChange(x)
+                    fn x() {}
+                    // LINT.ThenChange(any: b.go:x, c.py:x)
+                "},
+            ),
+            (
+                "b.go",
+                indoc! {"
+                    // LINT.OnChange(x)
+                    func x() {}
+                    // LINT.ThenChange()
+                "},
+            ),
+            (
+                "c.py",
+                indoc! {"
+                    # LINT.OnChange(x)
+                    def x(): pass
+                    # LINT.ThenChange()
+                "},
+            ),
+        ];
+        let d = GitRepo::from_files(files);
+        let p = Parser::from_directory(d.path(), true).unwrap();
+        let block = p.get_block_in_file("a.rs", "x").unwrap();
+        assert_eq!(block.quantifier(), Quantifier::AtLeast(1));
+
+        d.write_and_add_files(&[("a.rs", &files[0].1.replace("{}", "{ }"))]);
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        let violations = p.validate_git_repo().unwrap();
+        assert_eq!(violations.len(), 2);
+        for v in &violations {
+            assert_eq!(v.at_least(), Some(1));
+            assert!(v
+                .to_string()
+                .ends_with("(at least 1 of 2 must change: b.go:x, c.py:x)"));
+        }
+
+        // Changing either target is enough.
+        d.write_and_add_files(&[("c.py", &files[2].1.replace("pass", "return"))]);
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        assert!(p.validate_git_repo().unwrap().is_empty());
+
+        let at_least_two = files[0].1.replace("any:", "at-least=2:");
+        d.write_and_add_files(&[("a.rs", &at_least_two)]);
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        let violations = p.validate_git_repo().unwrap();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].target_file(), Path::new("b.go"));
+        assert_eq!(violations[0].at_least(), Some(2));
+    }
+
+    #[test]
+    fn test_from_files_quantifier_errors() {
+        let d = TestDir::from_files(&[(
+            "f.txt",
+            indoc! {"
+                LINT.OnChange(a)
+                LINT.ThenChange(at-least=3: :b, :c)
+                LINT.OnChange(b)
+                LINT.ThenChange(at-least=x: :a)
+                LINT.OnChange(c)
+                LINT.ThenChange()
+            "},
+        )]);
+        let diagnostics = Parser::from_files(["f.txt"].iter(), d.path()).unwrap_err();
+        let errors: Vec<String> = diagnostics.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "invalid ThenChange target at f.txt:2:17: expected at least 3 targets, found 2",
+                r#"invalid ThenChange target at f.txt:4:26: expected a positive number, found "x""#,
+            ]
+        );
+
+        // Without whitespace after the colon, "any" is a file.
+        d.write_file("f.txt", "LINT.OnChange(a)\nLINT.ThenChange(any:a)\n");
+        let diagnostics = Parser::from_files(["f.txt"].iter(), d.path()).unwrap_err();
+        assert_eq!(
+            diagnostics.iter().next().unwrap().to_string(),
+            r#"ThenChange target file "any" at f.txt:2 does not exist"#
+        );
+    }
+
+    #[test]
+    fn test_from_git_repo_transitive() {
+        let files = &[
+            (
+                "schema.proto",
+                indoc! {"
+                    // LINT.OnChange(schema, transitive)
+                    message A {}
+                    // LINT.ThenChange(codegen.rs:gen)
+                "},
+            ),
+            (
+                "codegen.rs",
+                indoc! {"
+                    // LINT.OnChange(gen)
+                    fn gen() {}
+                    // LINT.ThenChange(docs.md:client)
+                "},
+            ),
+            (
+                "docs.md",
+                indoc! {"
+                    <!-- LINT.OnChange(client) -->
+                    The client.
+                    <!-- LINT.ThenChange(schema.proto:schema) -->
+                "},
+            ),
+        ];
+        let d = GitRepo::from_files(files);
+        let p = Parser::from_directory(d.path(), true).unwrap();
+        assert!(p
+            .get_block_in_file("schema.proto", "schema")
+            .unwrap()
+            .is_transitive());
+        assert!(!p
+            .get_block_in_file("codegen.rs", "gen")
+            .unwrap()
+            .is_transitive());
+
+        d.write_and_add_files(&[("schema.proto", &files[0].1.replace("{}", "{ }"))]);
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        let violations = p.validate_git_repo().unwrap();
+        assert_eq!(violations.len(), 2);
+        assert_eq!(violations[0].target_block_name(), Some("gen"));
+        assert!(violations[0].via().is_empty());
+        assert_eq!(violations[1].target_block_name(), Some("client"));
+        assert_eq!(
+            violations[1].target_block().unwrap().file(),
+            Path::new("docs.md")
+        );
+        let via: Vec<&str> = violations[1].via().iter().map(|b| b.name()).collect();
+        assert_eq!(via, vec!["gen"]);
+        assert!(violations[1].to_string().ends_with("(via codegen.rs:gen)"));
+
+        // A block that changed is checked directly, so it is not reported twice.
+        d.write_and_add_files(&[("codegen.rs", &files[1].1.replace("{}", "{ }"))]);
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        let violations = p.validate_git_repo().unwrap();
+        assert_eq!(violations.len(), 1);
+        assert!(violations[0].via().is_empty());
+
+        // Without the attribute, only direct targets are checked unless enabled globally.
+        d.write_and_add_files(&[
+            ("schema.proto", &files[0].1.replace(", transitive", "")),
+            ("codegen.rs", files[1].1),
+        ]);
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        assert_eq!(p.validate_git_repo().unwrap().len(), 1);
+        let overrides = Settings {
+            transitive: Some(true),
+            ..Default::default()
+        };
+        let config = Config::load(d.path(), &overrides).unwrap();
+        let p = Parser::from_git_repo_with_config(d.path(), &config).unwrap();
+        assert_eq!(p.validate_git_repo().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_from_git_repo_transitive_file_targets() {
+        let d = GitRepo::from_files(&[
+            (
+                "a.txt",
+                "LINT.OnChange(a, transitive)\nabc\nLINT.ThenChange(b.txt, docs/*.md)\n",
+            ),
+            ("b.txt", "LINT.OnChange(b)\nabc\nLINT.ThenChange(c.txt:c)\n"),
+            (
+                "docs/x.md",
+                "LINT.OnChange()\nabc\nLINT.ThenChange(//c.txt:d)\n",
+            ),
+            (
+                "c.txt",
+                "LINT.OnChange(c)\nLINT.ThenChange()\nLINT.OnChange(d)\nLINT.ThenChange()\n",
+            ),
+        ]);
+
+        // The target files change outside of their blocks, which satisfies the direct
+        // targets, but the blocks in them still lead to c.txt.
+        d.write_and_add_files(&[
+            (
+                "a.txt",
+                "LINT.OnChange(a, transitive)\nxyz\nLINT.ThenChange(b.txt, docs/*.md)\n",
+            ),
+            (
+                "b.txt",
+                "LINT.OnChange(b)\nabc\nLINT.ThenChange(c.txt:c)\nxyz\n",
+            ),
+            (
+                "docs/x.md",
+                "LINT.OnChange()\nabc\nLINT.ThenChange(//c.txt:d)\nxyz\n",
+            ),
+        ]);
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        let violations = p.validate_git_repo().unwrap();
+        let targets: Vec<(Option<&str>, Vec<&Path>)> = violations
+            .iter()
+            .map(|v| {
+                (
+                    v.target_block_name(),
+                    v.via().iter().map(|b| b.file()).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            targets,
+            vec![
+                (Some("c"), vec![Path::new("b.txt")]),
+                (Some("d"), vec![Path::new("docs/x.md")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_git_repo_suppressions() {
+        let d = GitRepo::from_files(&[
+            (
+                "f1.txt",
+                indoc! {"
+                    LINT.OnChange(a) onchg:allow-unpaired until=2999-12-31
+                    abc
+                    LINT.ThenChange(f2.txt:b)
+                "},
+            ),
+            (
+                "f2.txt",
+                indoc! {"
+                    LINT.OnChange(b)
+                    abc
+                    LINT.ThenChange(f1.txt:a)
+                "},
+            ),
+        ]);
+
+        // The suppression waives violations of the block, and of blocks that target it.
+        let f1 = "LINT.OnChange(a) onchg:allow-unpaired until=2999-12-31\nabc\nLINT.ThenChange(f2.txt:b)\n";
+        let f2 = "LINT.OnChange(b)\nabc\nLINT.ThenChange(f1.txt:a)\n";
+        for (f1, f2) in [
+            (f1.replace("abc", "xyz"), f2.to_string()),
+            (f1.to_string(), f2.replace("abc", "xyz")),
+        ] {
+            d.write_and_add_files(&[("f1.txt", f1.as_str()), ("f2.txt", f2.as_str())]);
+            let p = Parser::from_git_repo(d.path()).unwrap();
+            let violations = p.validate_git_repo().unwrap();
+            assert_eq!(violations.len(), 1);
+            assert_eq!(
+                violations[0].waiver(),
+                Some("allowed unpaired until 2999-12-31")
+            );
+        }
+
+        d.write_and_add_files(&[
+            (
+                "f1.txt",
+                "LINT.OnChange(a) onchg:allow-unpaired until=2000-01-01\nxyz\nLINT.ThenChange(f2.txt:b)\n",
+            ),
+            ("f2.txt", f2),
+        ]);
+        // An expired suppression no longer waives violations, and is reported along with them.
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        let violations = p.validate_git_repo().unwrap();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].waiver(), None);
+        let errors: Vec<String> = p
+            .expired_suppressions()
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                r#"block "a" at f1.txt:1 has an onchg:allow-unpaired suppression that expired on 2000-01-01"#
+            ]
+        );
+
+        d.write_and_add_files(&[(
+            "f1.txt",
+            "LINT.OnChange(a) onchg:allow-unpaired until=2000-13-01\nabc\nLINT.ThenChange(f2.txt:b)\n",
+        )]);
+        let diagnostics = Parser::from_git_repo(d.path()).unwrap_err();
+        assert_eq!(
+            diagnostics.iter().next().unwrap().to_string(),
+            r#"invalid OnChange at f1.txt:1: expected a date (YYYY-MM-DD) for until, found "2000-13-01""#
+        );
+
+        for (line, message) in [
+            (
+                "LINT.OnChange(a) onchg:allow-unpaired",
+                "expected until=YYYY-MM-DD after onchg:allow-unpaired, as suppressions must expire",
+            ),
+            (
+                "LINT.OnChange(a) onchg:allow-unpaired until=2999-12-31 forever",
+                r#"unexpected "forever" after onchg:allow-unpaired"#,
+            ),
+        ] {
+            d.write_and_add_files(&[(
+                "f1.txt",
+                format!("{}\nabc\nLINT.ThenChange(f2.txt:b)\n", line).as_str(),
+            )]);
+            let diagnostics = Parser::from_git_repo(d.path()).unwrap_err();
+            assert_eq!(
+                diagnostics.iter().next().unwrap().to_string(),
+                format!("invalid OnChange at f1.txt:1: {}", message)
+            );
+        }
+
+        // A suppression can be followed by the end of a block comment.
+        d.write_and_add_files(&[(
+            "f1.txt",
+            "<!-- LINT.OnChange(a) onchg:allow-unpaired until=2999-12-31 -->\nabc\nLINT.ThenChange(f2.txt:b)\n",
+        )]);
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        assert_eq!(
+            p.get_block_in_file("f1.txt", "a")
+                .unwrap()
+                .suppression()
+                .map(|s| s.until().to_string()),
+            Some("2999-12-31".to_string())
+        );
+    }
+
+    #[test]
+    fn test_from_directory_glob_targets() {
+        let files = &[
+            (
+                "a/f1.txt",
+                "LINT.OnChange(a)\nLINT.ThenChange(*.md, //missing/, \"*.txt\")\n",
+            ),
+            ("a/README.md", "abc\n"),
+        ];
+        let d = TestDir::from_files(files);
+        let diagnostics = Parser::from_directory(d.path(), true).unwrap_err();
+        let mut errors: Vec<String> = diagnostics.iter().map(|e| e.kind().to_string()).collect();
+        errors.sort();
+        assert_eq!(errors, vec!["missing_target_file", "unmatched_target_glob"]);
+        assert!(diagnostics.iter().any(|e| matches!(
+            e,
+            Error::UnmatchedTargetGlob { pattern, .. } if pattern == "missing/**"
+        )));
+
+        // A glob cannot target a block.
+        d.write_file("a/f1.txt", "LINT.OnChange(a)\nLINT.ThenChange(*.md:b)\n");
+        let diagnostics = Parser::from_directory(d.path(), true).unwrap_err();
+        assert!(matches!(
+            diagnostics.iter().next().unwrap(),
+            Error::InvalidTargetPath { target, .. } if target == "*.md"
+        ));
+    }
+
+    #[test]
+    fn test_from_patch() {
+        let files = &[
+            (
+                "f1.txt",
+                indoc! {"
+                    LINT.OnChange(a)
+                    abc
+                    LINT.ThenChange(f2.txt:b)
+                "},
+            ),
+            (
+                "f2.txt",
+                indoc! {"
+                    LINT.OnChange(b)
+                    abc
+                    LINT.ThenChange(f1.txt:a)
+                "},
+            ),
+            (
+                "f3.txt",
+                indoc! {"
+                    LINT.OnChange(c)
+                    LINT.ThenChange(f1.txt:a)
+                "},
+            ),
+            (
+                "f4.txt",
+                indoc! {"
+                    LINT.OnChange()
+                    LINT.ThenChange(//f3.txt)
+                "},
+            ),
+        ];
+        let d = GitRepo::from_files(files);
+        d.write_and_add_files(&[(
+            "f1.txt",
+            indoc! {"
+                LINT.OnChange(a)
+                xyz
+                LINT.ThenChange(f2.txt:b)
+            "},
+        )]);
+        d.remove_file("f3.txt");
+
+        // The patch should be handled exactly like the staged changes it was generated from.
+        parse_and_validate(d.path(), 2);
+        let p = Parser::from_patch(d.path(), &d.diff()).unwrap();
+        assert_eq!(p.num_blocks(), 1);
+        assert_eq!(
+            p.deleted_paths().collect::<Vec<_>>(),
+            vec![Path::new("f3.txt")]
+        );
+        assert_eq!(p.removed_blocks().count(), 1);
+        let violations = p.validate_git_repo().unwrap();
+        assert_eq!(violations.len(), 2);
+        assert_eq!(
+            violations[0].kind(),
+            OnChangeViolationKind::TargetNotChanged
+        );
+        assert_eq!(violations[1].kind(), OnChangeViolationKind::TargetRemoved);
+
+        // The patch is applied to the files on disk, regardless of the Git state.
+        let d = TestDir::from_files(&files[..2]);
+        let patch = indoc! {"
+            --- f1.txt
+            +++ f1.txt
+            @@ -1,3 +1,3 @@
+             LINT.OnChange(a)
+            -abc
+            +xyz
+             LINT.ThenChange(f2.txt:b)
+        "};
+        let p = Parser::from_patch(d.path(), patch).unwrap();
+        assert_eq!(p.validate_git_repo().unwrap().len(), 1);
+
+        // A changed file that is missing on disk is reported as an I/O error for that file.
+        let patch = patch.replace("f1.txt", "missing.txt");
+        let diagnostics = Parser::from_patch(d.path(), &patch).unwrap_err();
+        let e = diagnostics.iter().next().unwrap();
+        assert_eq!(e.kind(), "io");
+        assert_eq!(e.path(), Some(Path::new("missing.txt")));
+    }
 }
@@ -0,0 +1,141 @@
+use std::path::Path;
+
+use crate::git::{Commit, Repo};
+use crate::{Error, Result};
+
+/// Returns true if the object ID is all zeroes, which Git uses for a ref that does not exist.
+fn is_zero_id(id: &str) -> bool {
+    id.chars().all(|c| c == '0')
+}
+
+/// A single ref update that is about to be pushed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PushUpdate {
+    pub local_ref: String,
+    pub local_id: String,
+    pub remote_ref: String,
+    pub remote_id: String,
+}
+
+impl PushUpdate {
+    /// Parses the input passed to a pre-push hook on stdin. Each line has the form:
+    ///
+    /// `<local ref> <local object ID> <remote ref> <remote object ID>`
+    pub fn parse(input: &str) -> Result<Vec<Self>> {
+        let mut updates = Vec::new();
+        for line in input.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if let [local_ref, local_id, remote_ref, remote_id] = parts.as_slice() {
+                updates.push(Self {
+                    local_ref: local_ref.to_string(),
+                    local_id: local_id.to_string(),
+                    remote_ref: remote_ref.to_string(),
+                    remote_id: remote_id.to_string(),
+                });
+            } else {
+                return Err(Error::InvalidPushUpdate {
+                    line: line.to_string(),
+                });
+            }
+        }
+        Ok(updates)
+    }
+
+    /// Builds an update that pushes the commits in `from..to`.
+    pub fn from_range(from: &str, to: &str) -> Self {
+        Self {
+            local_ref: to.to_string(),
+            local_id: to.to_string(),
+            remote_ref: from.to_string(),
+            remote_id: from.to_string(),
+        }
+    }
+
+    /// Returns true if this update deletes the remote ref.
+    pub fn is_delete(&self) -> bool {
+        is_zero_id(&self.local_id)
+    }
+
+    /// Returns true if this update creates a new ref on the remote.
+    pub fn is_new(&self) -> bool {
+        is_zero_id(&self.remote_id)
+    }
+
+    /// Returns the remote object ID that the outgoing commits are based on, or None if the
+    /// remote ref is new, or its object has not been fetched (e.g., someone else pushed to
+    /// it first).
+    fn remote_base(&self, repo: &impl Repo) -> Result<Option<&str>> {
+        if self.is_new() || !repo.has_commit(&self.remote_id)? {
+            return Ok(None);
+        }
+        Ok(Some(self.remote_id.as_str()))
+    }
+
+    /// Returns the commits that this update sends to the remote, oldest first.
+    ///
+    /// For a new ref, or a remote ref whose object is not known locally, this is every commit
+    /// that is not on any remote-tracking branch (as in Git's sample pre-push hook).
+    pub fn outgoing_commits<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Commit>> {
+        if self.is_delete() {
+            return Ok(Vec::new());
+        }
+        let repo = crate::git::open(path.as_ref())?;
+        let base = self.remote_base(&repo)?;
+        Ok(repo.get_commits(&self.local_id, base)?)
+    }
+
+    /// Returns the revision range that covers all of the given outgoing commits, or None if
+    /// there is nothing to diff against.
+    ///
+    /// A root commit has no parent, so its own changes are never part of the range.
+    pub fn outgoing_range<P: AsRef<Path>>(
+        &self,
+        path: P,
+        commits: &[Commit],
+    ) -> Result<Option<String>> {
+        let repo = crate::git::open(path.as_ref())?;
+        if let Some(base) = self.remote_base(&repo)? {
+            return Ok(Some(format!("{}..{}", base, self.local_id)));
+        }
+        let Some(first) = commits.first() else {
+            return Ok(None);
+        };
+        Ok(if !first.root {
+            Some(format!("{}^..{}", first.id, self.local_id))
+        } else if commits.len() > 1 {
+            Some(format!("{}..{}", first.id, self.local_id))
+        } else {
+            None
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_push_updates() {
+        let zero = "0".repeat(40);
+        let (a, b) = ("a".repeat(40), "b".repeat(40));
+        let input = format!(
+            "refs/heads/main {a} refs/heads/main {b}\n\
+             refs/heads/new {a} refs/heads/new {zero}\n\
+             \n\
+             (delete) {zero} refs/heads/old {b}\n",
+        );
+        let updates = PushUpdate::parse(&input).unwrap();
+        assert_eq!(updates.len(), 3);
+        assert_eq!(updates[0].local_ref, "refs/heads/main");
+        assert_eq!(updates[0].remote_id, b);
+        assert!(!updates[0].is_new() && !updates[0].is_delete());
+        assert!(updates[1].is_new());
+        assert!(updates[2].is_delete());
+
+        assert!(PushUpdate::parse("refs/heads/main abc").is_err());
+    }
+}
@@ -0,0 +1,332 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::{Diagnostics, Error, OnChangeBlock, OnChangeViolation, Parser, Quantifier, Severity};
+
+/// Version of the report schema. This is bumped on any breaking change to the schema.
+pub const REPORT_VERSION: u32 = 1;
+
+/// A machine-readable summary of a run, i.e., the parsed files and blocks, along with any
+/// violations or errors found.
+///
+/// All paths are relative to the root path, and all lines are 1-indexed.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub version: u32,
+    pub root_path: Option<PathBuf>,
+    /// Commit that was validated (pre-push mode only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<CommitEntry>,
+    pub files: Vec<FileEntry>,
+    pub violations: Vec<ViolationEntry>,
+    pub errors: Vec<ErrorEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommitEntry {
+    pub id: String,
+    pub summary: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileEntry {
+    pub path: PathBuf,
+    pub blocks: Vec<BlockEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BlockEntry {
+    /// None for an unnamed block.
+    pub name: Option<String>,
+    pub file: PathBuf,
+    pub start_line: u32,
+    pub end_line: u32,
+    pub targets: Vec<TargetEntry>,
+    /// Sync group of the block, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Number of targets that must change, if the block has a quantifier (e.g., `any:`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub at_least: Option<u32>,
+    #[serde(skip_serializing_if = "is_default_severity")]
+    pub severity: Severity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+fn is_default_severity(severity: &Severity) -> bool {
+    *severity == Severity::default()
+}
+
+#[derive(Debug, Serialize)]
+pub struct TargetEntry {
+    /// The file, or the pattern of a glob target.
+    pub file: PathBuf,
+    /// None if the target is a file.
+    pub block: Option<String>,
+    /// Set if the target is a glob (or directory), or a block pattern.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub glob: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ViolationEntry {
+    /// One of the [crate::OnChangeViolationKind] identifiers (e.g., "target_not_changed").
+    pub kind: &'static str,
+    /// Violations with a "warn" severity do not fail the run.
+    pub severity: Severity,
+    pub message: String,
+    /// The block whose target was violated.
+    pub block: BlockEntry,
+    pub target: TargetEntry,
+    /// Start line of the target block, if known.
+    pub target_line: Option<u32>,
+    /// End line of the target block, if known.
+    pub target_end_line: Option<u32>,
+    /// New path of the target file if it was renamed.
+    pub renamed_to: Option<PathBuf>,
+    /// Pattern of the glob target that matched the target file, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_glob: Option<String>,
+    /// Sync group shared with the target block, if the target is a group member.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_group: Option<String>,
+    /// Blocks on the path from the block to a transitive target.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub via: Vec<TargetEntry>,
+    /// Reason the violation was waived, if it was. Waived violations do not fail the run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub waived: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorEntry {
+    /// One of the [Error] kind identifiers (e.g., "duplicate_block").
+    pub kind: &'static str,
+    pub message: String,
+    pub path: Option<PathBuf>,
+    pub line: Option<u32>,
+    /// Only set for errors that point at a specific character (e.g., "invalid_target_syntax").
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<u32>,
+    pub block: Option<String>,
+}
+
+impl From<&OnChangeBlock> for BlockEntry {
+    fn from(block: &OnChangeBlock) -> Self {
+        let targets = block
+            .get_then_change_targets_as_keys()
+            .map(|(file, block)| TargetEntry {
+                file: file.to_owned(),
+                block: block.map(|b| b.to_string()),
+                glob: false,
+            })
+            .chain(
+                block
+                    .block_pattern_targets()
+                    .map(|(file, pattern)| TargetEntry {
+                        file: file.to_owned(),
+                        block: Some(pattern.pattern().to_string()),
+                        glob: true,
+                    }),
+            )
+            .chain(block.glob_targets().map(|glob| TargetEntry {
+                file: PathBuf::from(glob.pattern()),
+                block: None,
+                glob: true,
+            }))
+            .collect();
+        Self {
+            name: block.name_raw().map(|n| n.to_string()),
+            file: block.file().to_owned(),
+            start_line: block.start_line(),
+            end_line: block.end_line(),
+            targets,
+            group: block.group().map(|g| g.to_string()),
+            at_least: match block.quantifier() {
+                Quantifier::All => None,
+                Quantifier::AtLeast(n) => Some(n),
+            },
+            severity: block.severity(),
+            owner: block.owner().map(|o| o.to_string()),
+            reason: block.reason().map(|r| r.to_string()),
+        }
+    }
+}
+
+impl From<&OnChangeViolation<'_>> for ViolationEntry {
+    fn from(v: &OnChangeViolation<'_>) -> Self {
+        Self {
+            kind: v.kind().as_str(),
+            severity: v.severity(),
+            message: v.to_string(),
+            block: v.block().into(),
+            target: TargetEntry {
+                file: v.target_file().to_owned(),
+                block: v.target_block_name().map(|b| b.to_string()),
+                // In any mode, the target of a glob violation is the glob itself.
+                glob: v.target_glob().map(Path::new) == Some(v.target_file()),
+            },
+            target_line: v.target_block().map(|b| b.start_line()),
+            target_end_line: v.target_block().map(|b| b.end_line()),
+            renamed_to: v.renamed_to().map(|p| p.to_owned()),
+            target_glob: v.target_glob().map(|g| g.to_string()),
+            target_group: v.target_group().map(|g| g.to_string()),
+            via: v
+                .via()
+                .iter()
+                .map(|b| TargetEntry {
+                    file: b.file().to_owned(),
+                    block: b.name_raw().map(|n| n.to_string()),
+                    glob: false,
+                })
+                .collect(),
+            waived: v.waiver().map(|r| r.to_string()),
+        }
+    }
+}
+
+impl From<&Error> for ErrorEntry {
+    fn from(e: &Error) -> Self {
+        Self {
+            kind: e.kind(),
+            message: e.to_string(),
+            path: e.path().map(|p| p.to_owned()),
+            line: e.line(),
+            column: e.column(),
+            block: e.block().map(|b| b.to_string()),
+        }
+    }
+}
+
+impl Report {
+    /// Builds a report from a parser and the violations found when validating it. Expired
+    /// suppressions of the parsed blocks are included as errors.
+    pub fn new(parser: &Parser, violations: &[OnChangeViolation]) -> Self {
+        let mut paths: Vec<_> = parser.paths().collect();
+        paths.sort();
+        let files = paths
+            .into_iter()
+            .map(|path| FileEntry {
+                path: path.to_owned(),
+                blocks: parser
+                    .on_change_blocks_in_file(path)
+                    .into_iter()
+                    .flatten()
+                    .map(BlockEntry::from)
+                    .collect(),
+            })
+            .collect();
+        Self {
+            version: REPORT_VERSION,
+            root_path: Some(parser.root_path().to_owned()),
+            commit: None,
+            files,
+            violations: violations.iter().map(ViolationEntry::from).collect(),
+            errors: parser
+                .expired_suppressions()
+                .iter()
+                .map(ErrorEntry::from)
+                .collect(),
+        }
+    }
+
+    /// Builds a report for a run that failed with the given errors.
+    pub fn from_diagnostics(diagnostics: &Diagnostics) -> Self {
+        Self::from_errors(diagnostics.iter())
+    }
+
+    /// Builds a report for a run that failed with the given errors.
+    pub fn from_errors<'a>(errors: impl Iterator<Item = &'a Error>) -> Self {
+        Self {
+            version: REPORT_VERSION,
+            root_path: None,
+            commit: None,
+            files: Vec::new(),
+            violations: Vec::new(),
+            errors: errors.map(ErrorEntry::from).collect(),
+        }
+    }
+
+    /// Returns true if the report contains no errors, and no violations other than warnings
+    /// and waived violations.
+    pub fn is_ok(&self) -> bool {
+        self.violations
+            .iter()
+            .all(|v| v.severity == Severity::Warn || v.waived.is_some())
+            && self.errors.is_empty()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("report should serialize to JSON")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_helpers::*;
+
+    #[test]
+    fn test_report_json() {
+        let files = &[
+            (
+                "f1.txt",
+                "LINT.OnChange(a)\nabc\nLINT.ThenChange(f2.txt:b)\n",
+            ),
+            ("f2.txt", "LINT.OnChange(b)\nabc\nLINT.ThenChange()\n"),
+        ];
+        let d = GitRepo::from_files(files);
+        d.write_and_add_files(&[(
+            "f1.txt",
+            "LINT.OnChange(a)\nxyz\nLINT.ThenChange(f2.txt:b)\n",
+        )]);
+
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        let violations = p.validate_git_repo().unwrap();
+        let report = Report::new(&p, &violations);
+        assert!(!report.is_ok());
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["version"], REPORT_VERSION);
+        assert_eq!(json["files"][0]["path"], "f1.txt");
+        assert_eq!(
+            json["files"][0]["blocks"][0],
+            serde_json::json!({
+                "name": "a",
+                "file": "f1.txt",
+                "start_line": 1,
+                "end_line": 3,
+                "targets": [{"file": "f2.txt", "block": "b"}],
+            })
+        );
+        let violation = &json["violations"][0];
+        assert_eq!(violation["kind"], "target_not_changed");
+        assert_eq!(violation["block"]["name"], "a");
+        assert_eq!(
+            violation["target"],
+            serde_json::json!({"file": "f2.txt", "block": "b"})
+        );
+        assert_eq!(violation["target_line"], 1);
+        assert_eq!(violation["target_end_line"], 3);
+        assert!(json.get("commit").is_none());
+
+        let d = TestDir::from_files(&[("f1.txt", "LINT.OnChange(a)\n")]);
+        let diagnostics = Parser::from_directory(d.path(), true).unwrap_err();
+        let json: serde_json::Value =
+            serde_json::from_str(&Report::from_diagnostics(&diagnostics).to_json()).unwrap();
+        assert_eq!(
+            json["errors"][0],
+            serde_json::json!({
+                "kind": "unclosed_block",
+                "message": diagnostics.iter().next().unwrap().to_string(),
+                "path": "f1.txt",
+                "line": 1,
+                "block": "a",
+            })
+        );
+    }
+}
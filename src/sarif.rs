@@ -0,0 +1,265 @@
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::{ErrorEntry, Report, Severity, ViolationEntry};
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const SARIF_VERSION: &str = "2.1.0";
+
+/// All rules that can be reported, as (ID, description) tuples. The IDs are the violation
+/// and error kinds used in [Report]s.
+const RULES: &[(&str, &str)] = &[
+    (
+        "target_not_changed",
+        "A block changed, but its ThenChange target did not.",
+    ),
+    (
+        "target_removed",
+        "A block targets a block or file that was removed.",
+    ),
+    (
+        "target_renamed",
+        "A block targets a file using its path from before it was renamed.",
+    ),
+    (
+        "target_block_missing",
+        "A block changed, but its ThenChange target block does not exist.",
+    ),
+    (
+        "duplicate_block",
+        "Two blocks in the same file have the same name.",
+    ),
+    (
+        "missing_target_file",
+        "A ThenChange targets a file that does not exist.",
+    ),
+    (
+        "missing_target_block",
+        "A ThenChange targets a block that does not exist in the target file.",
+    ),
+    ("unclosed_block", "An OnChange has no matching ThenChange."),
+    (
+        "orphan_then_change",
+        "A ThenChange has no matching OnChange.",
+    ),
+    (
+        "invalid_target_path",
+        "A ThenChange target cannot be parsed or uses an unsupported path.",
+    ),
+    (
+        "unmatched_target_glob",
+        "A glob or directory ThenChange target does not match any files.",
+    ),
+    (
+        "invalid_target_syntax",
+        "A ThenChange target is malformed, e.g., it has an unterminated quote.",
+    ),
+    (
+        "invalid_on_change",
+        "An OnChange has an unknown or malformed attribute.",
+    ),
+    (
+        "single_member_group",
+        "A block is the only member of its sync group.",
+    ),
+    (
+        "expired_suppression",
+        "The onchg:allow-unpaired suppression of a block has expired.",
+    ),
+    (
+        "invalid_utf8",
+        "An OnChange or ThenChange contains invalid UTF-8.",
+    ),
+    ("invalid_root_path", "The root path is not a directory."),
+    ("invalid_patch", "A unified diff could not be parsed."),
+    (
+        "invalid_push_update",
+        "A line passed to the pre-push hook could not be parsed.",
+    ),
+    (
+        "invalid_skip_trailer",
+        "An Onchg-Skip commit message trailer does not name a block or file and a reason.",
+    ),
+    (
+        "invalid_config",
+        "A config file could not be parsed, or contains an invalid glob or marker.",
+    ),
+    ("io", "Failed to read a file."),
+    ("git", "A Git command failed."),
+];
+
+/// Converts a relative path into a URI reference relative to the source root.
+fn uri(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+fn location(path: &Path, start_line: Option<u32>, end_line: Option<u32>) -> Value {
+    let mut physical = json!({
+        "artifactLocation": {
+            "uri": uri(path),
+            "uriBaseId": "%SRCROOT%",
+        },
+    });
+    if let Some(start_line) = start_line {
+        let mut region = json!({ "startLine": start_line });
+        if let Some(end_line) = end_line {
+            region["endLine"] = json!(end_line);
+        }
+        physical["region"] = region;
+    }
+    json!({ "physicalLocation": physical })
+}
+
+fn violation_result(v: &ViolationEntry, report: &Report) -> Value {
+    let block = &v.block;
+    let mut target = location(&v.target.file, v.target_line, v.target_end_line);
+    let target_name = match &v.target.block {
+        Some(name) => format!("{}:{}", v.target.file.display(), name),
+        None => v.target.file.display().to_string(),
+    };
+    target["id"] = json!(0);
+    target["message"] = json!({ "text": format!("ThenChange target \"{}\"", target_name) });
+
+    let mut result = json!({
+        "ruleId": v.kind,
+        "level": match v.severity {
+            Severity::Error => "error",
+            Severity::Warn => "warning",
+        },
+        "message": { "text": v.message },
+        "locations": [location(&block.file, Some(block.start_line), Some(block.end_line))],
+        "relatedLocations": [target],
+    });
+    if let Some(reason) = &v.waived {
+        result["suppressions"] = json!([{ "kind": "external", "justification": reason }]);
+    }
+    if let Some(commit) = &report.commit {
+        result["properties"] = json!({ "commit": commit.id });
+    }
+    result
+}
+
+fn error_result(e: &ErrorEntry) -> Value {
+    let mut result = json!({
+        "ruleId": e.kind,
+        "level": "error",
+        "message": { "text": e.message },
+    });
+    if let Some(path) = &e.path {
+        let mut location = location(path, e.line, None);
+        if let Some(column) = e.column {
+            location["physicalLocation"]["region"]["startColumn"] = json!(column);
+        }
+        result["locations"] = json!([location]);
+    }
+    result
+}
+
+/// Builds a SARIF 2.1.0 log with a single run containing the violations and errors of all of
+/// the given reports.
+pub fn sarif_log(reports: &[Report]) -> Value {
+    let rules: Vec<Value> = RULES
+        .iter()
+        .map(|(id, description)| {
+            json!({
+                "id": id,
+                "shortDescription": { "text": description },
+            })
+        })
+        .collect();
+    let mut results = Vec::new();
+    for report in reports {
+        results.extend(
+            report
+                .violations
+                .iter()
+                .map(|v| violation_result(v, report)),
+        );
+        results.extend(report.errors.iter().map(error_result));
+    }
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": SARIF_VERSION,
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                    "rules": rules,
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_helpers::*;
+    use crate::Parser;
+
+    #[test]
+    fn test_sarif_log() {
+        let d = GitRepo::from_files(&[
+            (
+                "f1.txt",
+                "LINT.OnChange(a)\nabc\nLINT.ThenChange(f2.txt:b)\n",
+            ),
+            ("f2.txt", "LINT.OnChange(b)\nabc\nLINT.ThenChange()\n"),
+        ]);
+        d.write_and_add_files(&[(
+            "f1.txt",
+            "LINT.OnChange(a)\nxyz\nLINT.ThenChange(f2.txt:b)\n",
+        )]);
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        let violations = p.validate_git_repo().unwrap();
+
+        let d = TestDir::from_files(&[("f3.txt", "LINT.ThenChange()\n")]);
+        let diagnostics = Parser::from_directory(d.path(), true).unwrap_err();
+
+        let reports = [
+            Report::new(&p, &violations),
+            Report::from_diagnostics(&diagnostics),
+        ];
+        let log = sarif_log(&reports);
+        assert_eq!(log["version"], "2.1.0");
+
+        let run = &log["runs"][0];
+        let rule_ids: Vec<&str> = run["tool"]["driver"]["rules"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["id"].as_str().unwrap())
+            .collect();
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        for r in results {
+            assert!(rule_ids.contains(&r["ruleId"].as_str().unwrap()));
+        }
+
+        assert_eq!(results[0]["ruleId"], "target_not_changed");
+        assert_eq!(
+            results[0]["locations"][0]["physicalLocation"],
+            json!({
+                "artifactLocation": { "uri": "f1.txt", "uriBaseId": "%SRCROOT%" },
+                "region": { "startLine": 1, "endLine": 3 },
+            })
+        );
+        assert_eq!(
+            results[0]["relatedLocations"][0]["physicalLocation"],
+            json!({
+                "artifactLocation": { "uri": "f2.txt", "uriBaseId": "%SRCROOT%" },
+                "region": { "startLine": 1, "endLine": 3 },
+            })
+        );
+
+        assert_eq!(results[1]["ruleId"], "orphan_then_change");
+        assert_eq!(
+            results[1]["locations"][0]["physicalLocation"]["region"],
+            json!({ "startLine": 1 })
+        );
+    }
+}
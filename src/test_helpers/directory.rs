@@ -8,6 +8,12 @@ pub struct TestDir {
     d: TempDir,
 }
 
+impl Default for TestDir {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TestDir {
     pub fn new() -> Self {
         let d = tempfile::tempdir().unwrap();
@@ -44,11 +50,17 @@ impl TestDir {
 #[derive(Debug)]
 pub struct GitRepo(TestDir);
 
+impl Default for GitRepo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl GitRepo {
     pub fn new() -> Self {
         let t = TestDir::new();
         std::process::Command::new("git")
-            .current_dir(&t.path())
+            .current_dir(t.path())
             .arg("init")
             .output()
             .unwrap();
@@ -69,7 +81,7 @@ impl GitRepo {
     }
 
     pub fn path(&self) -> &Path {
-        &self.0.path()
+        self.0.path()
     }
 
     pub fn write_file<P: AsRef<Path>>(&self, path: P, content: &str) {
@@ -104,6 +116,17 @@ impl GitRepo {
         assert!(output.status.success());
     }
 
+    /// Removes the file from the working tree and stages the deletion.
+    pub fn remove_file<P: AsRef<Path>>(&self, path: P) {
+        let output = std::process::Command::new("git")
+            .current_dir(self.path())
+            .arg("rm")
+            .arg(path.as_ref())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+    }
+
     pub fn add_all_files(&self) {
         self.add_files::<&str>(None);
     }
@@ -119,11 +142,21 @@ impl GitRepo {
         assert!(output.status.success());
     }
 
-    #[allow(unused)]
+    /// Returns the full object ID of the given revision.
+    pub fn rev_parse(&self, rev: &str) -> String {
+        let output = std::process::Command::new("git")
+            .current_dir(self.path())
+            .args(["rev-parse", rev])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
     pub fn diff(&self) -> String {
         let output = std::process::Command::new("git")
             .current_dir(self.path())
-            .args(&["diff", "--cached"])
+            .args(["diff", "--cached"])
             .output()
             .unwrap();
         assert!(output.status.success());
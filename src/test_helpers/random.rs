@@ -99,7 +99,7 @@ impl RandomOnChangeTree {
 
         // If we have existing directories, we should randomly try to choose one as a parent.
         let mut parent: Option<PathBuf> = None;
-        if self.directories.len() > 0 && self.rand_bool() {
+        if !self.directories.is_empty() && self.rand_bool() {
             // This attempt will fail if the parent's depth is equal to the max depth.
             // In this case, we simply fallback to the normal flow.
             let n = self.rand_le(self.directories.len());
@@ -112,10 +112,9 @@ impl RandomOnChangeTree {
         }
 
         let parts = (0..depth)
-            .into_iter()
             .map(|_| self.rand_string(None))
             .collect::<Vec<String>>();
-        let p = PathBuf::from_iter(parts.into_iter());
+        let p = PathBuf::from_iter(parts);
         let p = if let Some(parent) = parent {
             parent.join(p)
         } else {
@@ -157,7 +156,7 @@ impl RandomOnChangeTree {
 
         let then_change_target = match block.then_change() {
             ThenChange::Targets(targets) => targets
-                .into_iter()
+                .iter()
                 .map(|t| {
                     let target_file = t
                         .file()
@@ -168,7 +167,7 @@ impl RandomOnChangeTree {
                     if let Some(target_block) = target_block {
                         format!("{}:{}", target_file, target_block)
                     } else {
-                        format!("{}", target_file)
+                        target_file.to_string()
                     }
                 })
                 .collect::<Vec<String>>()
@@ -200,12 +199,12 @@ impl RandomOnChangeTree {
             let mut then_change_block: Option<String> = None;
 
             let chosen = self.rand_bool();
-            if chosen && self.blocks.len() > 0 {
+            if chosen && !self.blocks.is_empty() {
                 // Target an existing file + block.
                 let (p, b) = {
                     let target_blocks = self.targetable_blocks();
                     let r = self.rand_le(target_blocks.len());
-                    let b = self.targetable_blocks()[r].clone();
+                    let b = self.targetable_blocks()[r];
                     (b.0.to_owned(), b.1.to_owned())
                 };
                 then_change_file = Some(p);
@@ -230,9 +229,14 @@ impl RandomOnChangeTree {
                 (then_change_file, Some(then_change_block)) => ThenChangeTarget::Block {
                     block: then_change_block,
                     file: then_change_file,
+                    line: end_line,
+                }
+                .into(),
+                (Some(then_change_file), None) => ThenChangeTarget::File {
+                    file: then_change_file,
+                    line: end_line,
                 }
                 .into(),
-                (Some(then_change_file), None) => ThenChangeTarget::File(then_change_file).into(),
                 (None, None) => ThenChange::NoTarget,
             };
             let block =
@@ -254,7 +258,7 @@ impl RandomOnChangeTree {
             line_num += num_lines + 1;
         }
 
-        f.write(content.as_bytes()).unwrap();
+        f.write_all(content.as_bytes()).unwrap();
 
         blocks
     }
@@ -0,0 +1,275 @@
+use std::path::Path;
+
+use crate::git::{Changes, Repo};
+use crate::{Error, OnChangeViolation, Result};
+
+/// Key of the commit message trailer that waives violations.
+pub const SKIP_TRAILER: &str = "Onchg-Skip";
+
+/// Line that marks the end of the message in a commit opened with `git commit --verbose`.
+const SCISSORS_LINE: &str = "# ------------------------ >8 ------------------------";
+
+/// Returns the key and value of a trailer line, e.g., `Signed-off-by: A <a@example.com>`.
+fn split_trailer(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once(':')?;
+    let key = key.trim_end();
+    let valid = !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    valid.then_some((key, value.trim()))
+}
+
+/// Returns the trailers of a commit message as (key, value) pairs, following the rules of
+/// `git interpret-trailers`: the trailers are the last paragraph of the message (which cannot
+/// be the title), and every line in it must be a trailer or the continuation of one.
+///
+/// As with Git, a paragraph where only some lines are trailers is still accepted if at least
+/// a quarter of them are, and one of them is a `Signed-off-by` trailer.
+fn trailers(message: &str) -> Vec<(&str, String)> {
+    let lines: Vec<&str> = message
+        .lines()
+        .take_while(|l| *l != SCISSORS_LINE)
+        .filter(|l| !l.starts_with('#'))
+        .collect();
+    let Some(end) = lines.iter().rposition(|l| !l.trim().is_empty()) else {
+        return Vec::new();
+    };
+    let start = lines[..end]
+        .iter()
+        .rposition(|l| l.trim().is_empty())
+        .map_or(0, |i| i + 1);
+    // The first paragraph is the title.
+    if lines[..start].iter().all(|l| l.trim().is_empty()) {
+        return Vec::new();
+    }
+
+    let mut trailers: Vec<(&str, String)> = Vec::new();
+    let (mut num_trailers, mut num_other, mut signed_off) = (0, 0, false);
+    for line in &lines[start..=end] {
+        if let Some((key, value)) = split_trailer(line) {
+            num_trailers += 1;
+            signed_off |= key.eq_ignore_ascii_case("Signed-off-by");
+            trailers.push((key, value.to_string()));
+        } else if line.starts_with(char::is_whitespace) && !trailers.is_empty() {
+            let (_, value) = trailers.last_mut().unwrap();
+            value.push(' ');
+            value.push_str(line.trim());
+        } else {
+            num_other += 1;
+        }
+    }
+    if num_other == 0 || (signed_off && num_trailers * 3 >= num_other) {
+        trailers
+    } else {
+        Vec::new()
+    }
+}
+
+/// A waiver read from an `Onchg-Skip: <block or file> <reason>` commit message trailer.
+///
+/// The target is either a file (e.g., `docs/api.md`) or a block (e.g., `docs/api.md:usage`),
+/// relative to the root path. A waiver matches a violation if it names the block that changed,
+/// the target that did not, or the file of either.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Waiver {
+    pub target: String,
+    pub reason: String,
+}
+
+impl Waiver {
+    /// Parses all `Onchg-Skip` trailers in the given commit message. The key is matched
+    /// case-insensitively, as Git does for trailers. Lines in the body of the message that
+    /// look like a trailer are ignored.
+    pub fn parse(message: &str) -> Result<Vec<Self>> {
+        let mut waivers = Vec::new();
+        for (key, value) in trailers(message) {
+            if !key.eq_ignore_ascii_case(SKIP_TRAILER) {
+                continue;
+            }
+            match value.split_once(char::is_whitespace) {
+                Some((target, reason)) => waivers.push(Self {
+                    target: target.trim_start_matches("./").to_string(),
+                    reason: reason.trim().to_string(),
+                }),
+                None => {
+                    return Err(Error::InvalidSkipTrailer {
+                        line: format!("{}: {}", key, value),
+                    })
+                }
+            }
+        }
+        Ok(waivers)
+    }
+
+    /// Parses the waivers in the messages of all commits in the given revision range (see
+    /// [crate::Parser::from_git_range]). Since the range is validated as a whole, a waiver in
+    /// any of its commits applies to all of it.
+    pub fn from_range<P: AsRef<Path>>(path: P, range: &str) -> Result<Vec<Self>> {
+        let repo = crate::git::open(path.as_ref())?;
+        let Changes::Range { base, head, .. } = Changes::from_range(range)? else {
+            unreachable!("a revision range is always a range of commits");
+        };
+        let mut waivers = Vec::new();
+        for commit in repo.get_commits(&head, Some(&base))? {
+            waivers.extend(Self::parse(&commit.message)?);
+        }
+        Ok(waivers)
+    }
+
+    /// Returns true if this waiver names a block, rather than a whole file.
+    fn is_block(&self) -> bool {
+        self.target.contains(':')
+    }
+
+    fn matches_block(&self, file: &Path, block: Option<&str>) -> bool {
+        if Path::new(&self.target) == file {
+            return true;
+        }
+        match (self.target.rsplit_once(':'), block) {
+            (Some((target_file, target_block)), Some(block)) => {
+                Path::new(target_file) == file && target_block == block
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true if this waiver names the block or target of the given violation.
+    pub fn matches(&self, violation: &OnChangeViolation) -> bool {
+        let block = violation.block();
+        self.matches_block(block.file(), block.name_raw())
+            || self.matches_block(violation.target_file(), violation.target_block_name())
+    }
+}
+
+/// Waives every violation matched by one of the given waivers, with the reason of the most
+/// specific one (i.e., a block before a file). Returns the waivers that did not match any
+/// violation, which usually means the trailer has a typo.
+pub fn apply_waivers<'w>(
+    violations: &mut [OnChangeViolation],
+    waivers: &'w [Waiver],
+) -> Vec<&'w Waiver> {
+    let mut used = vec![false; waivers.len()];
+    for v in violations.iter_mut() {
+        let mut reason: Option<&Waiver> = None;
+        for (i, w) in waivers.iter().enumerate() {
+            if !w.matches(v) {
+                continue;
+            }
+            used[i] = true;
+            if reason.is_none_or(|r| w.is_block() && !r.is_block()) {
+                reason = Some(w);
+            }
+        }
+        if let Some(w) = reason {
+            v.waive(&w.reason);
+        }
+    }
+    waivers
+        .iter()
+        .zip(used)
+        .filter_map(|(w, used)| (!used).then_some(w))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_helpers::*;
+    use crate::Parser;
+
+    #[test]
+    fn test_parse_waivers() {
+        let message = "Fix typo in docs\n\
+                       \n\
+                       Onchg-Skip: ./docs/api.md typo fix only\n\
+                       onchg-skip: src/api.rs:handler  not a behavior change \n\
+                       # Onchg-Skip: ignored.txt comment\n\
+                       Signed-off-by: A <a@example.com>\n";
+        assert_eq!(
+            Waiver::parse(message).unwrap(),
+            vec![
+                Waiver {
+                    target: "docs/api.md".to_string(),
+                    reason: "typo fix only".to_string(),
+                },
+                Waiver {
+                    target: "src/api.rs:handler".to_string(),
+                    reason: "not a behavior change".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            Waiver::parse("Fix\n\nOnchg-Skip: docs/api.md\n")
+                .unwrap_err()
+                .kind(),
+            "invalid_skip_trailer"
+        );
+
+        // Only the trailers in the last paragraph count, and a trailer can span lines.
+        let message = "Fix typo\n\n\
+                       Onchg-Skip: docs/api.md is mentioned in the body\n\
+                       \n\
+                       Reviewed-by: A <a@example.com>\n\
+                       Onchg-Skip: src/api.rs:handler typo\n  fix only\n\
+                       \n\
+                       # Onchg-Skip: ignored.txt comment\n";
+        assert_eq!(
+            Waiver::parse(message).unwrap(),
+            vec![Waiver {
+                target: "src/api.rs:handler".to_string(),
+                reason: "typo fix only".to_string(),
+            }]
+        );
+        // A paragraph that is not all trailers is part of the body.
+        assert!(Waiver::parse("Fix\n\nOnchg-Skip: a.txt b\nmore text\n")
+            .unwrap()
+            .is_empty());
+        // The title is never a trailer.
+        assert!(Waiver::parse("Onchg-Skip: a.txt b\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_apply_waivers() {
+        let d = GitRepo::from_files(&[
+            (
+                "f1.txt",
+                "LINT.OnChange(a)\nabc\nLINT.ThenChange(f2.txt:b)\n",
+            ),
+            ("f2.txt", "LINT.OnChange(b)\nabc\nLINT.ThenChange()\n"),
+            ("f3.txt", "LINT.OnChange(c)\nabc\nLINT.ThenChange(f2.txt)\n"),
+        ]);
+        d.write_and_add_files(&[
+            (
+                "f1.txt",
+                "LINT.OnChange(a)\nxyz\nLINT.ThenChange(f2.txt:b)\n",
+            ),
+            ("f3.txt", "LINT.OnChange(c)\nxyz\nLINT.ThenChange(f2.txt)\n"),
+        ]);
+        let p = Parser::from_git_repo(d.path()).unwrap();
+        let mut violations = p.validate_git_repo().unwrap();
+        assert_eq!(violations.len(), 2);
+
+        let waivers = Waiver::parse(
+            "Fix\n\nOnchg-Skip: f1.txt:a typo fix\nOnchg-Skip: f4.txt unrelated\nOnchg-Skip: f2.txt:c x\n",
+        )
+        .unwrap();
+        let unused = apply_waivers(&mut violations, &waivers);
+        assert_eq!(unused, vec![&waivers[1], &waivers[2]]);
+        let waived: Vec<_> = violations
+            .iter()
+            .map(|v| (v.block().name(), v.waiver()))
+            .collect();
+        assert_eq!(waived, vec![("a", Some("typo fix")), ("c", None)]);
+
+        // Overlapping waivers are all used, and the block waiver takes precedence.
+        let waivers = Waiver::parse(
+            "Fix\n\nOnchg-Skip: f1.txt whole file\nOnchg-Skip: f1.txt:a block only\nOnchg-Skip: f1.txt:a again\n",
+        )
+        .unwrap();
+        assert!(apply_waivers(&mut violations, &waivers).is_empty());
+        assert_eq!(violations[0].waiver(), Some("block only"));
+
+        // A target file waives all violations that target it.
+        let waivers = Waiver::parse("Fix\n\nOnchg-Skip: f2.txt generated\n").unwrap();
+        assert!(apply_waivers(&mut violations, &waivers).is_empty());
+        assert!(violations.iter().all(|v| v.waiver() == Some("generated")));
+    }
+}
@@ -26,7 +26,7 @@ fn test_directory() {
 
     Command::cargo_bin(env!("CARGO_PKG_NAME"))
         .unwrap()
-        .args(&["directory", "."])
+        .args(["directory", "."])
         .current_dir(d.path())
         .assert()
         .success();
@@ -63,7 +63,7 @@ fn test_git_repo() {
 
     Command::cargo_bin(env!("CARGO_PKG_NAME"))
         .unwrap()
-        .args(&["repo", "."])
+        .args(["repo", "."])
         .current_dir(d.path())
         .assert()
         .failure()
@@ -71,3 +71,466 @@ fn test_git_repo() {
 
     eprintln!("Parsed & validated staged files in {:?}", s.elapsed())
 }
+
+#[test]
+fn test_pre_push() {
+    let d = GitRepo::from_files(&[
+        (
+            "f1.txt",
+            "LINT.OnChange(a)\nabc\nLINT.ThenChange(f2.txt:b)\n",
+        ),
+        (
+            "f2.txt",
+            "LINT.OnChange(b)\nabc\nLINT.ThenChange(f1.txt:a)\n",
+        ),
+    ]);
+    let base = d.rev_parse("HEAD");
+
+    // Each commit only changes one side of the pair.
+    d.write_and_add_files(&[(
+        "f1.txt",
+        "LINT.OnChange(a)\nxyz\nLINT.ThenChange(f2.txt:b)\n",
+    )]);
+    d.commit(Some("change f1"));
+    d.write_and_add_files(&[(
+        "f2.txt",
+        "LINT.OnChange(b)\nxyz\nLINT.ThenChange(f1.txt:a)\n",
+    )]);
+    d.commit(Some("change f2"));
+    let head = d.rev_parse("HEAD");
+
+    let stdin = format!("refs/heads/main {} refs/heads/main {}\n", head, base);
+
+    assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["pre-push", "origin", "https://example.com/repo.git"])
+        .current_dir(d.path())
+        .write_stdin(stdin.clone())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("\"change f1\""))
+        .stderr(predicate::str::contains("\"change f2\""));
+
+    assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["pre-push", "--squash"])
+        .current_dir(d.path())
+        .write_stdin(stdin)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Checked 2 outgoing commits."));
+
+    // A new ref without any remote-tracking branches includes the initial commit, which is
+    // skipped (with a warning) since it has no parent.
+    let stdin = format!(
+        "refs/heads/main {} refs/heads/main {}\n",
+        head,
+        "0".repeat(40)
+    );
+    assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["pre-push", "--squash"])
+        .current_dir(d.path())
+        .write_stdin(stdin)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("skipping root commit"));
+
+    // A remote object that was never fetched (e.g., after someone else pushed) is treated
+    // like a new ref, rather than failing the push.
+    let stdin = format!(
+        "refs/heads/main {} refs/heads/main {}\n",
+        head,
+        "1".repeat(40)
+    );
+    assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["pre-push"])
+        .current_dir(d.path())
+        .write_stdin(stdin.clone())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("\"change f1\""))
+        .stderr(predicate::str::contains("bad object").not());
+    assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["pre-push", "--squash"])
+        .current_dir(d.path())
+        .write_stdin(stdin)
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_pre_push_renamed_target() {
+    let d = GitRepo::from_files(&[
+        ("f1.txt", "LINT.OnChange(a)\nabc\nLINT.ThenChange(f2.txt)\n"),
+        ("f2.txt", "abc\n"),
+    ]);
+    let base = d.rev_parse("HEAD");
+
+    d.write_and_add_files(&[
+        ("f1.txt", "LINT.OnChange(a)\nxyz\nLINT.ThenChange(f2.txt)\n"),
+        ("f2.txt", "xyz\n"),
+    ]);
+    d.commit(Some("change f1 and f2"));
+    // Renaming the target in a later commit must not break the check of the first one.
+    d.remove_file("f2.txt");
+    d.write_and_add_files(&[
+        ("f1.txt", "LINT.OnChange(a)\nxyz\nLINT.ThenChange(f3.txt)\n"),
+        ("f3.txt", "xyz\nxyz\n"),
+    ]);
+    d.commit(Some("rename f2 to f3"));
+    let head = d.rev_parse("HEAD");
+
+    assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["pre-push", "origin", "https://example.com/repo.git"])
+        .current_dir(d.path())
+        .write_stdin(format!(
+            "refs/heads/main {} refs/heads/main {}\n",
+            head, base
+        ))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Checked 2 outgoing commits."));
+}
+
+#[test]
+fn test_json_format() {
+    let d = GitRepo::from_files(&[
+        (
+            "f1.txt",
+            "LINT.OnChange(a)\nabc\nLINT.ThenChange(f2.txt:b)\n",
+        ),
+        ("f2.txt", "LINT.OnChange(b)\nabc\nLINT.ThenChange()\n"),
+    ]);
+    d.write_and_add_files(&[(
+        "f1.txt",
+        "LINT.OnChange(a)\nxyz\nLINT.ThenChange(f2.txt:b)\n",
+    )]);
+
+    let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["--format", "json", "repo", "."])
+        .current_dir(d.path())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["version"], 1);
+    assert_eq!(report["violations"][0]["kind"], "target_not_changed");
+    assert_eq!(report["violations"][0]["block"]["file"], "f1.txt");
+    assert_eq!(report["violations"][0]["target"]["file"], "f2.txt");
+    assert_eq!(report["violations"][0]["target_line"], 1);
+
+    // Parse errors are reported in the same format.
+    d.write_file("f3.txt", "LINT.OnChange(c)\n");
+    let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["directory", ".", "--format", "json"])
+        .current_dir(d.path())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["errors"][0]["kind"], "unclosed_block");
+    assert_eq!(report["errors"][0]["path"], "f3.txt");
+}
+
+#[test]
+fn test_sarif_format() {
+    let d = TestDir::from_files(&[
+        ("f1.txt", "LINT.OnChange(a)\nabc\nLINT.ThenChange()\n"),
+        ("f2.txt", "abc\nLINT.ThenChange()\n"),
+    ]);
+
+    let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["--format", "sarif", "directory", "."])
+        .current_dir(d.path())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let log: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(log["version"], "2.1.0");
+    let result = &log["runs"][0]["results"][0];
+    assert_eq!(result["ruleId"], "orphan_then_change");
+    assert_eq!(
+        result["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+        "f2.txt"
+    );
+    assert_eq!(
+        result["locations"][0]["physicalLocation"]["region"]["startLine"],
+        2
+    );
+}
+
+#[test]
+fn test_config() {
+    let d = TestDir::from_files(&[
+        (".onchg.toml", "exclude = [\"bad/**\"]\n"),
+        ("f1.txt", "LINT.OnChange(a)\nLINT.ThenChange()\n"),
+        ("bad/f2.txt", "LINT.OnChange(b)\n"),
+    ]);
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["directory", "."])
+        .current_dir(d.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Parsed 1 files"));
+
+    // Flags take precedence over the config file.
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["directory", ".", "--max-files-to-display", "0"])
+        .current_dir(d.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 files omitted"));
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["directory", ".", "--exclude", "f1.txt"])
+        .current_dir(d.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Parsed").not());
+
+    d.write_file(".onchg.toml", "include = 1\n");
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["directory", "."])
+        .current_dir(d.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid config file"));
+}
+
+#[test]
+fn test_block_severity() {
+    let d = GitRepo::from_files(&[
+        (
+            "f1.txt",
+            "LINT.OnChange(a, severity=warn, owner=@team-api)\nabc\nLINT.ThenChange(f2.txt)\n",
+        ),
+        (
+            "f2.txt",
+            "LINT.OnChange(b, reason=\"wire compat\")\nabc\nLINT.ThenChange(f1.txt)\n",
+        ),
+    ]);
+    d.write_and_add_files(&[(
+        "f1.txt",
+        "LINT.OnChange(a, severity=warn, owner=@team-api)\nxyz\nLINT.ThenChange(f2.txt)\n",
+    )]);
+
+    // Warnings are reported, but do not fail the run.
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["repo", "."])
+        .current_dir(d.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("* warning: file"))
+        .stderr(predicate::str::contains("[owner: @team-api]"));
+    let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["--format", "sarif", "repo", "."])
+        .current_dir(d.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let log: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(log["runs"][0]["results"][0]["level"], "warning");
+
+    d.commit(None);
+    d.write_and_add_files(&[(
+        "f2.txt",
+        "LINT.OnChange(b, reason=\"wire compat\")\nxyz\nLINT.ThenChange(f1.txt)\n",
+    )]);
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["repo", "."])
+        .current_dir(d.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("[reason: wire compat]"));
+}
+
+#[test]
+fn test_commit_msg() {
+    let d = GitRepo::from_files(&[
+        (
+            "f1.txt",
+            "LINT.OnChange(a)\nabc\nLINT.ThenChange(f2.txt:b)\n",
+        ),
+        ("f2.txt", "LINT.OnChange(b)\nabc\nLINT.ThenChange()\n"),
+    ]);
+    d.write_and_add_files(&[(
+        "f1.txt",
+        "LINT.OnChange(a)\nxyz\nLINT.ThenChange(f2.txt:b)\n",
+    )]);
+
+    let msg = d.path().join(".git").join("COMMIT_EDITMSG");
+    std::fs::write(&msg, "Fix typo\n").unwrap();
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["commit-msg", ".git/COMMIT_EDITMSG"])
+        .current_dir(d.path())
+        .assert()
+        .failure();
+
+    // Waived violations are still listed.
+    std::fs::write(&msg, "Fix typo\n\nOnchg-Skip: f1.txt:a typo fix\n").unwrap();
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["commit-msg", ".git/COMMIT_EDITMSG"])
+        .current_dir(d.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("* waived (typo fix): block"));
+    let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["--format", "json", "commit-msg", ".git/COMMIT_EDITMSG"])
+        .current_dir(d.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["violations"][0]["waived"], "typo fix");
+
+    // Only the named violations are waived.
+    std::fs::write(&msg, "Fix typo\n\nOnchg-Skip: f3.txt typo fix\n").unwrap();
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["commit-msg", ".git/COMMIT_EDITMSG"])
+        .current_dir(d.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            r#"Onchg-Skip trailer for "f3.txt" does not match any violations"#,
+        ));
+
+    std::fs::write(&msg, "Fix typo\n\nOnchg-Skip: f1.txt\n").unwrap();
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["commit-msg", ".git/COMMIT_EDITMSG"])
+        .current_dir(d.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid trailer"));
+}
+
+#[test]
+fn test_waivers_in_history() {
+    let d = GitRepo::from_files(&[
+        (
+            "f1.txt",
+            "LINT.OnChange(a)\nabc\nLINT.ThenChange(f2.txt:b)\n",
+        ),
+        ("f2.txt", "LINT.OnChange(b)\nabc\nLINT.ThenChange()\n"),
+    ]);
+    let base = d.rev_parse("HEAD");
+    d.write_and_add_files(&[(
+        "f1.txt",
+        "LINT.OnChange(a)\nxyz\nLINT.ThenChange(f2.txt:b)\n",
+    )]);
+    d.commit(Some("Fix typo\n\nOnchg-Skip: f1.txt:a typo fix"));
+    let head = d.rev_parse("HEAD");
+
+    // A commit that was waived at commit time must not be rejected later.
+    assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["pre-push", "origin", "https://example.com/repo.git"])
+        .current_dir(d.path())
+        .write_stdin(format!(
+            "refs/heads/main {} refs/heads/main {}\n",
+            head, base
+        ))
+        .assert()
+        .success();
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["diff", &format!("{}..{}", base, head)])
+        .current_dir(d.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("waived (typo fix)"));
+
+    // A trailer-like line in the body is not a waiver.
+    d.write_and_add_files(&[(
+        "f1.txt",
+        "LINT.OnChange(a)\nxyz!\nLINT.ThenChange(f2.txt:b)\n",
+    )]);
+    d.commit(Some(
+        "Fix typo\n\nOnchg-Skip: f1.txt:a typo fix\nbut not really",
+    ));
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["diff", "HEAD~1..HEAD"])
+        .current_dir(d.path())
+        .assert()
+        .failure();
+
+    // Only an invalid trailer is reported as a commit message error.
+    d.write_and_add_files(&[(
+        "f1.txt",
+        "LINT.OnChange(a)\nxyz!!\nLINT.ThenChange(f2.txt:b)\n",
+    )]);
+    d.commit(Some("Fix typo\n\nOnchg-Skip: f1.txt:a"));
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["diff", "HEAD~1..HEAD"])
+        .current_dir(d.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed to parse commit message"));
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["--format", "json", "diff", "HEAD~1..HEAD"])
+        .current_dir(d.path())
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("\"invalid_skip_trailer\""));
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["--format", "json", "diff", "missing..HEAD"])
+        .current_dir(d.path())
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("\"kind\": \"git\""))
+        .stderr(predicate::str::contains("commit message").not());
+}
+
+#[test]
+fn test_expired_suppression() {
+    let d = GitRepo::from_files(&[
+        (
+            "f1.txt",
+            "LINT.OnChange(a) onchg:allow-unpaired until=2000-01-01\nabc\nLINT.ThenChange(f2.txt)\n",
+        ),
+        ("f2.txt", "abc\n"),
+    ]);
+    d.write_and_add_files(&[(
+        "f1.txt",
+        "LINT.OnChange(a) onchg:allow-unpaired until=2000-01-01\nxyz\nLINT.ThenChange(f2.txt)\n",
+    )]);
+
+    // The expired suppression is listed with the violations, using the same paths.
+    let f1 = d.path().canonicalize().unwrap().join("f1.txt");
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["repo", "."])
+        .current_dir(d.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(format!(
+            "(due to block \"a\" at {}:1)",
+            f1.display()
+        )))
+        .stderr(predicate::str::contains(format!(
+            "block \"a\" at {}:1 has an onchg:allow-unpaired suppression that expired on 2000-01-01",
+            f1.display()
+        )));
+}